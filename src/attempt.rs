@@ -0,0 +1,270 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FusedFuture};
+
+use crate::lock::{AcquireOptions, Lock, LockError, LockManager};
+use crate::resource::LockResource;
+
+/// An acquisition driven step by step by the caller, created with `LockManager::begin_acquire`.
+///
+/// Each `step` makes one attempt at reaching a quorum and never sleeps, so schedulers with
+/// their own backoff can decide when, and whether, to try again. The retry count and
+/// delay of the manager are not applied.
+#[derive(Debug)]
+pub struct AcquireAttempt<'a> {
+    lock_manager: &'a LockManager,
+    resource: Vec<u8>,
+    ttl: Duration,
+    attempts: u32,
+}
+
+/// The result of `AcquireAttempt::step`.
+#[derive(Debug)]
+pub enum AcquireStep<'a> {
+    /// The lock has been acquired.
+    Acquired(Lock<'a>),
+    /// No quorum was reached. `attempt` can be stepped again,
+    /// `delay` is the random delay the manager would wait before doing so.
+    Retry {
+        attempt: AcquireAttempt<'a>,
+        delay: Duration,
+    },
+}
+
+impl<'a> AcquireAttempt<'a> {
+    /// The resource to be locked.
+    pub fn resource(&self) -> &[u8] {
+        &self.resource
+    }
+
+    /// Number of attempts made so far.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Make a single attempt at acquiring the lock, like `LockManager::try_lock`, so fencing
+    /// and shadow mode apply as they do to `lock`.
+    ///
+    /// Errors are final, e.g. `LockError::TtlExceeded` if the attempt took longer than the TTL,
+    /// `LockError::MaintenanceMode` if the manager is in maintenance mode,
+    /// or `LockError::QuotaExceeded` if a quota set with `LockManager::set_lock_quota` is used up.
+    pub async fn step(mut self) -> Result<AcquireStep<'a>, LockError> {
+        self.attempts += 1;
+        let lock_manager = self.lock_manager;
+        let options = AcquireOptions::new().retry(1, Duration::ZERO);
+
+        match lock_manager
+            .lock_with_options(&self.resource[..], self.ttl, &options)
+            .await
+        {
+            Ok(lock) => Ok(AcquireStep::Acquired(lock)),
+            Err(LockError::Unavailable | LockError::Contended { .. }) => {
+                let delay = LockManager::retry_jitter(lock_manager.retry_delay())?;
+                Ok(AcquireStep::Retry {
+                    attempt: self,
+                    delay,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A `lock` call as a named future, created with `LockManager::acquire_future`.
+///
+/// Being `Unpin` and fused, it can be polled by hand with `poll_acquire`, e.g. from a custom
+/// `Future` or event loop, or raced by reference in a `select!` loop, where the other arms
+/// completing leaves the acquisition where it was instead of starting over. Polling it again
+/// only checks the pending round or retry delay, no new attempt is made until they complete.
+///
+/// Dropping it cancels the acquisition, releasing the instances it may have locked in
+/// the background, like dropping the future returned by `lock`.
+///
+/// ```rust,no_run
+/// # async fn run(rl: &rslock::LockManager, mut jobs: tokio::sync::mpsc::Receiver<()>) {
+/// let mut acquire = rl.acquire_future(b"leader", std::time::Duration::from_secs(10));
+/// loop {
+///     tokio::select! {
+///         lock = &mut acquire => {
+///             // Lead with `lock`
+///             break;
+///         }
+///         Some(job) = jobs.recv() => {
+///             // Handle `job` while waiting for the lock
+///         }
+///     }
+/// }
+/// # }
+/// ```
+pub struct Acquire<'a> {
+    future: Option<BoxFuture<'a, Result<Lock<'a>, LockError>>>,
+}
+
+impl<'a> Acquire<'a> {
+    /// Poll the acquisition, returning its outcome once it has completed.
+    ///
+    /// Panics if polled again after it has completed, see `FusedFuture::is_terminated`.
+    pub fn poll_acquire(&mut self, cx: &mut Context<'_>) -> Poll<Result<Lock<'a>, LockError>> {
+        let future = self
+            .future
+            .as_mut()
+            .expect("Acquire polled after completion");
+        let result = ready!(future.as_mut().poll(cx));
+        self.future = None;
+        Poll::Ready(result)
+    }
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Result<Lock<'a>, LockError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().poll_acquire(cx)
+    }
+}
+
+impl FusedFuture for Acquire<'_> {
+    fn is_terminated(&self) -> bool {
+        self.future.is_none()
+    }
+}
+
+impl fmt::Debug for Acquire<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acquire")
+            .field("terminated", &self.is_terminated())
+            .finish()
+    }
+}
+
+impl LockManager {
+    /// Acquire the lock for the given resource like `lock`, as an `Acquire` future
+    /// that can be polled by hand or raced in `select!` loops.
+    pub fn acquire_future<R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Acquire<'_> {
+        let resource = resource.key().into_owned();
+        Acquire {
+            future: Some(Box::pin(async move { self.lock(&resource, ttl).await })),
+        }
+    }
+
+    /// Prepare acquiring the lock for the given resource, to be driven with `AcquireAttempt::step`.
+    ///
+    /// May return `LockError::TtlTooLarge` if `ttl` is too large,
+    /// or `LockError::TtlOutOfRange` if it is outside of the configured bounds.
+    pub fn begin_acquire<R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<AcquireAttempt<'_>, LockError> {
+        self.ttl_millis(ttl)?;
+        Ok(AcquireAttempt {
+            lock_manager: self,
+            resource: resource.key().into_owned(),
+            ttl,
+            attempts: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_acquire_attempt_steps() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let held = rl.lock(&key, Duration::from_millis(10_000)).await?;
+
+        let attempt = match rl
+            .begin_acquire(&key, Duration::from_millis(10_000))?
+            .step()
+            .await?
+        {
+            AcquireStep::Retry { attempt, .. } => attempt,
+            AcquireStep::Acquired(lock) => panic!("Expected a retry, got {:?}", lock),
+        };
+        assert_eq!(attempt.attempts(), 1);
+
+        rl.unlock(&held).await;
+        match attempt.step().await? {
+            AcquireStep::Acquired(lock) => assert_eq!(lock.resource, key),
+            AcquireStep::Retry { .. } => panic!("Expected the lock to be acquired"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_acquire_attempt_fencing() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_fencing(true);
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let first = rl.lock(&key, ttl).await?;
+        rl.unlock(&first).await;
+        match rl.begin_acquire(&key, ttl)?.step().await? {
+            AcquireStep::Acquired(lock) => {
+                assert!(lock.fencing_token.unwrap() > first.fencing_token.unwrap());
+            }
+            AcquireStep::Retry { .. } => panic!("Expected the lock to be acquired"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_acquire_future_select() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_retry(100, Duration::from_millis(20));
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+        let held = rl.lock(&key, ttl).await?;
+
+        // Other arms completing leave the acquisition pending
+        let mut acquire = rl.acquire_future(&key, ttl);
+        let mut ticks = 0;
+        let lock = loop {
+            tokio::select! {
+                lock = &mut acquire => break lock?,
+                _ = tokio::time::sleep(Duration::from_millis(10)) => {
+                    ticks += 1;
+                    if ticks == 10 {
+                        rl.unlock(&held).await;
+                    }
+                }
+            }
+        };
+        assert!(ticks >= 10);
+        assert!(acquire.is_terminated());
+        assert_eq!(lock.resource, key);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_acquire_future_is_send_and_unpin() {
+        fn assert_send_unpin<T: Send + Unpin>(_: &T) {}
+
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        assert_send_unpin(&rl.acquire_future(b"resource", Duration::from_secs(1)));
+    }
+}
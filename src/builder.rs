@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use redis::{ConnectionInfo, IntoConnectionInfo};
+
+use crate::endpoint;
+use crate::lock::{check_ttl_bounds, ConnectStrategy, LockError, LockManager};
+
+/// Configures a `LockManager` in one chain, created with `LockManager::builder`.
+///
+/// Settings that are not given keep the defaults of `LockManager::new`.
+/// Each setting has the meaning of the corresponding `LockManager` setter.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rslock::LockManager;
+/// let rl = LockManager::builder()
+///     .endpoints("redis://10.0.0.1,10.0.0.2,10.0.0.3")
+///     .retry(5, Duration::from_millis(100))
+///     .response_timeout(Duration::from_millis(50))
+///     .build()?;
+/// # Ok::<(), rslock::LockError>(())
+/// ```
+#[derive(Debug)]
+pub struct LockManagerBuilder {
+    endpoints: Result<Vec<ConnectionInfo>, LockError>,
+    quorum: Option<u32>,
+    quorum_margin: Option<u32>,
+    retry: Option<(u32, Duration)>,
+    retry_warmup: Option<(Duration, Duration)>,
+    clock_drift: Option<(f32, Duration)>,
+    ttl_bounds: Option<(Duration, Duration)>,
+    response_timeout: Option<Duration>,
+    client_name: Option<String>,
+    connect_strategy: Option<ConnectStrategy>,
+}
+
+impl LockManager {
+    /// Start configuring a lock manager, see `LockManagerBuilder`.
+    pub fn builder() -> LockManagerBuilder {
+        LockManagerBuilder {
+            endpoints: Err(LockError::NoServers),
+            quorum: None,
+            quorum_margin: None,
+            retry: None,
+            retry_warmup: None,
+            clock_drift: None,
+            ttl_bounds: None,
+            response_timeout: None,
+            client_name: None,
+            connect_strategy: None,
+        }
+    }
+}
+
+impl LockManagerBuilder {
+    /// The instances, given as connection URIs, see `LockManager::new`.
+    pub fn uris<T: IntoConnectionInfo>(mut self, uris: Vec<T>) -> LockManagerBuilder {
+        self.endpoints = uris
+            .into_iter()
+            .enumerate()
+            .map(|(index, uri)| {
+                uri.into_connection_info()
+                    .map_err(|e| LockError::InvalidEndpoint {
+                        index,
+                        reason: e.to_string(),
+                    })
+            })
+            .collect();
+        self
+    }
+
+    /// The instances, given as a comma-separated list, see `LockManager::from_endpoints`.
+    pub fn endpoints(mut self, spec: &str) -> LockManagerBuilder {
+        self.endpoints = endpoint::parse_endpoints(spec);
+        self
+    }
+
+    /// Number of instances that must agree for a lock to be acquired, instead of a majority
+    /// of the instances. Must still be a majority: `build` rejects smaller quorums, which
+    /// would allow two clients to hold the same lock.
+    pub fn quorum(mut self, quorum: u32) -> LockManagerBuilder {
+        self.quorum = Some(quorum);
+        self
+    }
+
+    /// See `LockManager::set_quorum_margin`.
+    pub fn quorum_margin(mut self, margin: u32) -> LockManagerBuilder {
+        self.quorum_margin = Some(margin);
+        self
+    }
+
+    /// See `LockManager::set_retry`.
+    pub fn retry(mut self, count: u32, delay: Duration) -> LockManagerBuilder {
+        self.retry = Some((count, delay));
+        self
+    }
+
+    /// See `LockManager::set_retry_warmup`.
+    pub fn retry_warmup(mut self, window: Duration, delay: Duration) -> LockManagerBuilder {
+        self.retry_warmup = Some((window, delay));
+        self
+    }
+
+    /// See `LockManager::set_clock_drift`.
+    pub fn clock_drift(mut self, factor: f32, padding: Duration) -> LockManagerBuilder {
+        self.clock_drift = Some((factor, padding));
+        self
+    }
+
+    /// See `LockManager::set_ttl_bounds`.
+    pub fn ttl_bounds(mut self, min: Duration, max: Duration) -> LockManagerBuilder {
+        self.ttl_bounds = Some((min, max));
+        self
+    }
+
+    /// The time each instance is given to answer, see `LockManager::set_response_timeout`.
+    pub fn response_timeout(mut self, timeout: Duration) -> LockManagerBuilder {
+        self.response_timeout = Some(timeout);
+        self
+    }
+
+    /// See `LockManager::set_client_name`.
+    pub fn client_name(mut self, name: &str) -> LockManagerBuilder {
+        self.client_name = Some(name.to_string());
+        self
+    }
+
+    /// See `LockManager::set_connect_strategy`. With `ConnectStrategy::Eager`, await
+    /// `LockManager::warm_up` on the built manager before the first lock call.
+    pub fn connect_strategy(mut self, strategy: ConnectStrategy) -> LockManagerBuilder {
+        self.connect_strategy = Some(strategy);
+        self
+    }
+
+    /// Create the lock manager.
+    ///
+    /// Fails like `LockManager::try_new` if no instance or an invalid one was given,
+    /// and with `LockError::InvalidSetting` if the quorum is not a majority of the instances
+    /// or more than their number, or if the minimum TTL is greater than the maximum.
+    pub fn build(self) -> Result<LockManager, LockError> {
+        let mut lock_manager = LockManager::try_new(self.endpoints?)?;
+
+        if let Some(quorum) = self.quorum {
+            let instances = lock_manager.servers.len() as u32;
+            if quorum <= instances / 2 || quorum > instances {
+                return Err(LockError::InvalidSetting {
+                    setting: "quorum".to_string(),
+                    reason: format!(
+                        "{} is not between a majority of the {} instances and all of them",
+                        quorum, instances
+                    ),
+                });
+            }
+            lock_manager.set_quorum(quorum);
+        }
+        if let Some(margin) = self.quorum_margin {
+            lock_manager.set_quorum_margin(margin);
+        }
+        if let Some((count, delay)) = self.retry {
+            lock_manager.set_retry(count, delay);
+        }
+        if let Some((window, delay)) = self.retry_warmup {
+            lock_manager.set_retry_warmup(window, delay);
+        }
+        if let Some((factor, padding)) = self.clock_drift {
+            lock_manager.set_clock_drift(factor, padding);
+        }
+        if let Some((min, max)) = self.ttl_bounds {
+            check_ttl_bounds(min, max).map_err(|reason| LockError::InvalidSetting {
+                setting: "ttl_bounds".to_string(),
+                reason,
+            })?;
+            lock_manager.set_ttl_bounds(min, max);
+        }
+        if let Some(timeout) = self.response_timeout {
+            lock_manager.set_response_timeout(timeout);
+        }
+        if let Some(name) = &self.client_name {
+            lock_manager.set_client_name(name);
+        }
+        if let Some(strategy) = self.connect_strategy {
+            lock_manager.set_connect_strategy(strategy);
+        }
+        Ok(lock_manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let rl = LockManager::builder()
+            .endpoints("127.0.0.1:1,127.0.0.1:2,127.0.0.1:3")
+            .quorum(3)
+            .retry(5, Duration::from_millis(50))
+            .build()
+            .unwrap();
+        assert_eq!(rl.servers.len(), 3);
+        assert_eq!(rl.quorum(), 3);
+        assert_eq!(rl.retry_delay(), Duration::from_millis(50));
+
+        let rl = LockManager::builder()
+            .uris(vec!["redis://127.0.0.1:1/"])
+            .build()
+            .unwrap();
+        assert_eq!(rl.quorum(), 1);
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        assert!(matches!(
+            LockManager::builder().build(),
+            Err(LockError::NoServers)
+        ));
+        assert!(matches!(
+            LockManager::builder().uris(vec!["nope://"]).build(),
+            Err(LockError::InvalidEndpoint { index: 0, .. })
+        ));
+        for quorum in [1, 4] {
+            assert!(matches!(
+                LockManager::builder()
+                    .endpoints("127.0.0.1:1,127.0.0.1:2,127.0.0.1:3")
+                    .quorum(quorum)
+                    .build(),
+                Err(LockError::InvalidSetting { setting, .. }) if setting == "quorum"
+            ));
+        }
+        assert!(matches!(
+            LockManager::builder()
+                .uris(vec!["redis://127.0.0.1:1/"])
+                .ttl_bounds(Duration::from_secs(2), Duration::from_secs(1))
+                .build(),
+            Err(LockError::InvalidSetting { setting, .. }) if setting == "ttl_bounds"
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use futures::Future;
+
+use crate::lock::{AcquireOptions, LockError, LockManager};
+use crate::resource::LockResource;
+use crate::transport::{get_instance, locked_set_instance};
+
+impl LockManager {
+    /// Read `cache_key` from the instances, or compute and store it under the lock for
+    /// `resource` if it is missing, so that only one client recomputes an expired entry.
+    ///
+    /// The value is read from a quorum of the instances. On a miss, the lock is acquired,
+    /// waiting up to `ttl` for another client computing the value, and the key is read again.
+    /// If it is still missing, `compute` runs and its output is stored for `ttl`, on the
+    /// instances that still hold the lock, and returned. `ttl` is also the lock's TTL,
+    /// so `compute` should complete within it.
+    ///
+    /// Fails like `lock` if the lock cannot be acquired in time and the value did not appear
+    /// in the meantime.
+    pub async fn get_or_compute<R, F, Fut>(
+        &self,
+        resource: &R,
+        cache_key: &[u8],
+        ttl: Duration,
+        compute: F,
+    ) -> Result<Vec<u8>, LockError>
+    where
+        R: LockResource + ?Sized,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Vec<u8>>,
+    {
+        if let Some(value) = self.cached(cache_key).await {
+            return Ok(value);
+        }
+
+        let options = AcquireOptions::new().timeout(ttl);
+        let lock = match self
+            .acquire_no_guard_with_options(resource, ttl, &options)
+            .await
+        {
+            Ok(lock) => lock,
+            Err(e) => return self.cached(cache_key).await.ok_or(e),
+        };
+
+        // Another client may have computed the value while we waited for the lock
+        let value = match self.cached(cache_key).await {
+            Some(value) => value,
+            None => {
+                let value = compute().await;
+                let ttl = ttl.as_millis() as usize;
+                self.on_all(|instance| {
+                    locked_set_instance(instance, &lock, cache_key, &value, Some(ttl))
+                })
+                .await;
+                value
+            }
+        };
+        self.unlock(&lock).await;
+        Ok(value)
+    }
+
+    // The value stored at `key` on a quorum of the instances
+    async fn cached(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let values: Vec<Vec<u8>> = self
+            .on_all(|instance| get_instance(instance, key))
+            .await
+            .into_iter()
+            .filter_map(|result| result.ok().flatten())
+            .collect();
+        let count = |value: &Vec<u8>| values.iter().filter(|v| *v == value).count();
+        values
+            .iter()
+            .find(|value| count(value) >= self.quorum() as usize)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::Result;
+    use futures::future::join_all;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+    use crate::rt;
+
+    #[tokio::test]
+    async fn test_get_or_compute() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let resource = rl.get_unique_lock_id()?;
+        let cache_key = rl.get_unique_lock_id()?;
+        let computed = AtomicUsize::new(0);
+
+        let compute = || async {
+            computed.fetch_add(1, Ordering::SeqCst);
+            rt::sleep(Duration::from_millis(200)).await;
+            b"value".to_vec()
+        };
+        let values = join_all((0..5).map(|_| {
+            rl.get_or_compute(&resource, &cache_key, Duration::from_millis(5000), compute)
+        }))
+        .await;
+
+        for value in values {
+            assert_eq!(value?, b"value");
+        }
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use crate::lock::{LockError, LockGuard, LockManager};
+use crate::state::Child;
+use crate::transport;
+
+impl<'a> LockGuard<'a> {
+    /// Lock the resource named by this guard's resource followed by `suffix`, as a child
+    /// of this lock, e.g. `b":row-7"` under a lock on `b"table"`.
+    ///
+    /// The child is released along with this lock, by `LockManager::unlock`, `unlock_all`,
+    /// a `ReleaseHandle` or dropping the guard, so fine-grained sub-locks taken under
+    /// a coarse lock need no cleanup of their own. Children of children are released too.
+    /// A child can still be released before its parent.
+    ///
+    /// Fails like `LockManager::lock`.
+    pub async fn lock_child(
+        &self,
+        suffix: &[u8],
+        ttl: Duration,
+    ) -> Result<LockGuard<'a>, LockError> {
+        let lock_manager = self.lock.lock_manager;
+        let resource = [&self.lock.resource[..], suffix].concat();
+        let lock = lock_manager.lock(&resource, ttl).await?;
+        lock_manager.tracker().add_child(
+            &self.lock.val,
+            Child {
+                resource,
+                val: lock.val.clone(),
+            },
+        );
+        Ok(LockGuard { lock })
+    }
+}
+
+impl LockManager {
+    // Releases the children of the lock with value `val`, deepest first
+    pub(crate) async fn release_children(&self, val: &[u8]) {
+        let descendants = self.tracker().take_descendants(val);
+        for child in descendants.iter().rev() {
+            self.on_all(|instance| {
+                transport::unlock_instance(instance, &child.resource, &child.val)
+            })
+            .await;
+            self.tracker().released(&child.val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_lock_child() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let rl2 = LockManager::new(addresses.clone());
+        let ttl = Duration::from_millis(10_000);
+        let key = rl.get_unique_lock_id()?;
+
+        let parent = LockGuard {
+            lock: rl.lock(&key, ttl).await?,
+        };
+        let child = parent.lock_child(b":a", ttl).await?;
+        let grandchild = child.lock_child(b":b", ttl).await?;
+        assert_eq!(grandchild.lock.resource, [&key[..], b":a:b"].concat());
+
+        // Released on its own, the child is not released again with its parent
+        let other = parent.lock_child(b":c", ttl).await?;
+        rl.unlock(&other.lock).await;
+        let taken = rl2.lock(&other.lock.resource, ttl).await?;
+
+        rl.unlock(&parent.lock).await;
+        rl2.lock(&child.lock.resource, ttl).await?;
+        rl2.lock(&grandchild.lock.resource, ttl).await?;
+        assert_eq!(rl2.verify(&taken).await, 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::core;
+use crate::lock::{LockError, LockManager};
+use crate::transport::{expire_persistent_instance, scan_instance};
+
+/// An inconsistency between the instances found by `LockManager::inspect_namespace`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Inconsistency {
+    /// The key exists on fewer than a quorum of instances, e.g. left behind by an acquisition
+    /// that failed and could not be undone. It expires by itself, unless it has no expiry.
+    Minority { key: Vec<u8>, instances: Vec<usize> },
+    /// The instances holding the key hold different locks.
+    Split { key: Vec<u8>, values: usize },
+    /// The remaining TTLs of the key differ by more than the tolerance, hinting at
+    /// clock problems or instances that missed extensions.
+    DivergentTtl {
+        key: Vec<u8>,
+        min: Duration,
+        max: Duration,
+    },
+    /// The key has no expiry on these instances, so it is never released by expiration.
+    NoExpiry { key: Vec<u8>, instances: Vec<usize> },
+}
+
+/// The result of `LockManager::inspect_namespace`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CleanupReport {
+    /// Number of distinct keys found.
+    pub scanned: usize,
+    pub inconsistencies: Vec<Inconsistency>,
+    /// Instances that could not be scanned. Their keys are missing from the report,
+    /// which may show keys as `Minority` that are not.
+    pub unreachable: Vec<usize>,
+}
+
+impl CleanupReport {
+    pub fn is_healthy(&self) -> bool {
+        self.inconsistencies.is_empty() && self.unreachable.is_empty()
+    }
+}
+
+// The state of a key on one instance
+struct Entry {
+    instance: usize,
+    val: Vec<u8>,
+    pttl: Option<Duration>,
+}
+
+impl LockManager {
+    /// Scan the keys starting with `prefix` on every instance and report inconsistencies.
+    ///
+    /// Meant for maintenance of long-lived deployments, where crashed clients and instance
+    /// outages can leave lock keys behind. Only string keys are considered. Requires
+    /// Redis 6 or later, and `SCAN` walks the whole keyspace of each instance, so run it
+    /// off-peak on large databases.
+    ///
+    /// Locks that are being acquired or released while scanning may be reported as well.
+    pub async fn inspect_namespace(&self, prefix: &[u8], ttl_tolerance: Duration) -> CleanupReport {
+        let scans = self
+            .on_all(|instance| scan_instance(instance, prefix))
+            .await;
+
+        let mut report = CleanupReport::default();
+        let mut keys: BTreeMap<Vec<u8>, Vec<Entry>> = BTreeMap::new();
+        for (instance, scan) in scans.into_iter().enumerate() {
+            let Ok(found) = scan else {
+                report.unreachable.push(instance);
+                continue;
+            };
+            for (key, val, pttl) in found {
+                let entries = keys.entry(key).or_default();
+                // SCAN may return a key more than once
+                if entries.last().is_none_or(|e| e.instance != instance) {
+                    entries.push(Entry {
+                        instance,
+                        val,
+                        pttl,
+                    });
+                }
+            }
+        }
+        report.scanned = keys.len();
+
+        for (key, entries) in keys {
+            let persistent: Vec<usize> = entries
+                .iter()
+                .filter(|e| e.pttl.is_none())
+                .map(|e| e.instance)
+                .collect();
+            if !persistent.is_empty() {
+                report.inconsistencies.push(Inconsistency::NoExpiry {
+                    key: key.clone(),
+                    instances: persistent,
+                });
+            }
+
+            if (entries.len() as u32) < self.quorum() {
+                report.inconsistencies.push(Inconsistency::Minority {
+                    key: key.clone(),
+                    instances: entries.iter().map(|e| e.instance).collect(),
+                });
+            }
+
+            let mut tokens: Vec<&[u8]> =
+                entries.iter().map(|e| core::value_token(&e.val)).collect();
+            tokens.sort();
+            tokens.dedup();
+            if tokens.len() > 1 {
+                report.inconsistencies.push(Inconsistency::Split {
+                    key: key.clone(),
+                    values: tokens.len(),
+                });
+            }
+
+            let pttls = entries.iter().filter_map(|e| e.pttl);
+            if let (Some(min), Some(max)) = (pttls.clone().min(), pttls.max()) {
+                if max - min > ttl_tolerance {
+                    report
+                        .inconsistencies
+                        .push(Inconsistency::DivergentTtl { key, min, max });
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Give the keys reported as `Inconsistency::NoExpiry` an expiry of `ttl`,
+    /// so that they are eventually released. Returns the number of keys updated.
+    ///
+    /// Keys that got an expiry since the report are left alone. Other inconsistencies
+    /// are not repaired: removing a key held by a minority could break a lock that is
+    /// being acquired at the same moment, and such keys expire by themselves.
+    pub async fn repair_namespace(
+        &self,
+        report: &CleanupReport,
+        ttl: Duration,
+    ) -> Result<usize, LockError> {
+        let ttl = self.ttl_millis(ttl)?;
+        let mut repaired = 0;
+        for inconsistency in &report.inconsistencies {
+            let Inconsistency::NoExpiry { key, instances } = inconsistency else {
+                continue;
+            };
+            let results = self
+                .on_all(|instance| expire_persistent_instance(instance, key, ttl))
+                .await;
+            repaired += instances
+                .iter()
+                .filter(|i| matches!(results.get(**i), Some(Ok(true))))
+                .count();
+        }
+        Ok(repaired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_inspect_and_repair_namespace() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let prefix = b"cleanup:";
+        let ttl = Duration::from_millis(10_000);
+        rl.lock(b"cleanup:healthy", ttl).await?;
+
+        let mut con = rl.servers[0].get_connection()?;
+        redis::cmd("SET")
+            .arg("cleanup:fragment")
+            .arg("x")
+            .arg("PX")
+            .arg(10_000)
+            .execute(&mut con);
+        redis::cmd("SET")
+            .arg("cleanup:stuck")
+            .arg("x")
+            .execute(&mut con);
+
+        let report = rl.inspect_namespace(prefix, Duration::from_secs(1)).await;
+        assert_eq!(report.scanned, 3);
+        assert_eq!(
+            report.inconsistencies,
+            [
+                Inconsistency::Minority {
+                    key: b"cleanup:fragment".to_vec(),
+                    instances: vec![0]
+                },
+                Inconsistency::NoExpiry {
+                    key: b"cleanup:stuck".to_vec(),
+                    instances: vec![0]
+                },
+                Inconsistency::Minority {
+                    key: b"cleanup:stuck".to_vec(),
+                    instances: vec![0]
+                },
+            ]
+        );
+
+        assert_eq!(rl.repair_namespace(&report, ttl).await?, 1);
+        let report = rl.inspect_namespace(prefix, Duration::from_secs(1)).await;
+        assert_eq!(report.inconsistencies.len(), 2);
+
+        Ok(())
+    }
+}
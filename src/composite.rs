@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use futures::future::join_all;
+
+use crate::core::UnlockOutcome;
+use crate::lock::{Lock, LockError, LockGuard};
+
+/// Several lock guards held together, created with `join_guards`.
+///
+/// `CompositeGuard::release` releases all locks concurrently. Dropping the composite guard
+/// drops the inner guards one after another, with the caveats of dropping a `LockGuard`.
+#[derive(Debug)]
+pub struct CompositeGuard<'a> {
+    guards: Vec<LockGuard<'a>>,
+}
+
+/// Combine guards that must be held simultaneously into a single guard.
+///
+/// The guards may belong to different lock managers.
+pub fn join_guards(guards: Vec<LockGuard<'_>>) -> CompositeGuard<'_> {
+    CompositeGuard { guards }
+}
+
+impl<'a> CompositeGuard<'a> {
+    /// The held locks, in the order the guards were given.
+    pub fn locks(&self) -> impl Iterator<Item = &Lock<'a>> {
+        self.guards.iter().map(|guard| &guard.lock)
+    }
+
+    /// Extend all locks to `ttl`, with one round per lock manager, see `LockManager::extend_all`.
+    ///
+    /// The guards of the locks extended are updated. Returns the positions of the locks that
+    /// could not be extended, with the reasons, so only the work they protect needs to stop.
+    /// Fails as a whole only if `ttl` is not accepted by a manager.
+    pub async fn extend_all(
+        &mut self,
+        ttl: Duration,
+    ) -> Result<Vec<(usize, LockError)>, LockError> {
+        // Positions of the guards by lock manager, in order of first appearance
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (i, guard) in self.guards.iter().enumerate() {
+            let manager = guard.lock.lock_manager;
+            match groups
+                .iter_mut()
+                .find(|group| std::ptr::eq(self.guards[group[0]].lock.lock_manager, manager))
+            {
+                Some(group) => group.push(i),
+                None => groups.push(vec![i]),
+            }
+        }
+
+        let mut failed = Vec::new();
+        for group in groups {
+            let manager = self.guards[group[0]].lock.lock_manager;
+            let locks: Vec<Lock<'a>> = group.iter().map(|&i| self.guards[i].lock.clone()).collect();
+            let outcomes = manager.extend_all(&locks, ttl).await?;
+            for (i, outcome) in group.into_iter().zip(outcomes) {
+                match outcome {
+                    Ok(extended) => self.guards[i].lock = extended,
+                    Err(e) => failed.push((i, e)),
+                }
+            }
+        }
+        failed.sort_by_key(|(i, _)| *i);
+        Ok(failed)
+    }
+
+    /// Release all locks concurrently.
+    ///
+    /// Returns the number of locks released by this call, see `UnlockOutcome::Released`.
+    /// Locks that expired or were lost in the meantime are not counted.
+    /// Like `LockManager::unlock`, releasing is best effort.
+    pub async fn release(self) -> usize {
+        let locks: Vec<Lock<'a>> = self.guards.into_iter().map(LockGuard::into_lock).collect();
+        let released = join_all(locks.iter().map(|lock| lock.lock_manager.unlock(lock))).await;
+
+        released
+            .into_iter()
+            .filter(|&outcome| outcome == UnlockOutcome::Released)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+    use crate::lock::LockManager;
+
+    #[tokio::test]
+    async fn test_join_guards_release() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let mut guards = Vec::new();
+        for _ in 0..3 {
+            let key = rl.get_unique_lock_id()?;
+            let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+            guards.push(LockGuard { lock });
+        }
+        let composite = join_guards(guards);
+        let locks: Vec<_> = composite.locks().cloned().collect();
+
+        assert_eq!(composite.release().await, 3);
+        for lock in &locks {
+            assert_eq!(rl.verify(lock).await, 0);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_join_guards_extend_all() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let ttl = Duration::from_millis(10_000);
+        let mut guards = Vec::new();
+        for _ in 0..3 {
+            let key = rl.get_unique_lock_id()?;
+            let lock = rl.lock(&key, ttl).await?;
+            guards.push(LockGuard { lock });
+        }
+        let mut composite = join_guards(guards);
+        let lost = composite.locks().nth(1).unwrap().resource.clone();
+
+        // Take the second lock away on two of the instances
+        for server in &rl.servers[..2] {
+            let mut con = server.get_connection()?;
+            redis::cmd("SET").arg(&lost).arg("other").execute(&mut con);
+        }
+
+        let failed = composite.extend_all(Duration::from_millis(20_000)).await?;
+        assert!(matches!(failed.as_slice(), [(1, LockError::LockLost)]));
+        let locks: Vec<_> = composite.locks().cloned().collect();
+        assert!(locks[0].validity_time > 10_000);
+        assert!(locks[2].validity_time > 10_000);
+        assert_eq!(rl.verify(&locks[0]).await, 3);
+
+        Ok(())
+    }
+}
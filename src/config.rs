@@ -0,0 +1,158 @@
+//! Configuration from strings, e.g. read from configuration files or the environment.
+
+use std::time::Duration;
+
+use crate::lock::{check_ttl_bounds, LockError, LockManager};
+
+// Settings accepted by `LockManager::configure`
+const SETTINGS: &[&str] = &[
+    "retry_count",
+    "retry_delay",
+    "min_ttl",
+    "max_ttl",
+    "max_clock_discrepancy",
+    "round_budget",
+    "response_timeout",
+    "restart_quarantine",
+    "renewal_interval",
+    "quorum_margin",
+];
+
+/// Parse a human-readable duration like `500ms`, `2s` or `1m 30s`.
+///
+/// Fails with `LockError::InvalidSetting` naming the value and what is wrong with it.
+pub fn parse_duration(value: &str) -> Result<Duration, LockError> {
+    humantime::parse_duration(value.trim()).map_err(|e| LockError::InvalidSetting {
+        setting: format!("`{}`", value),
+        reason: e.to_string(),
+    })
+}
+
+impl LockManager {
+    /// Change a setting given as strings.
+    ///
+    /// Durations are human-readable, like `500ms`, `2s` or `1m`, see `parse_duration`.
+    /// Accepted settings are `retry_count`, `retry_delay`, `min_ttl`, `max_ttl`,
+    /// `max_clock_discrepancy`, `round_budget`, `response_timeout` and `restart_quarantine`,
+    /// with the meaning of the corresponding setters.
+    ///
+    /// Fails with `LockError::InvalidSetting` for unknown settings and invalid values.
+    pub fn configure(&mut self, setting: &str, value: &str) -> Result<(), LockError> {
+        let invalid = |reason: String| LockError::InvalidSetting {
+            setting: setting.to_string(),
+            reason,
+        };
+        let count = || {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| invalid(format!("`{}`: {}", value, e)))
+        };
+        let duration = || {
+            humantime::parse_duration(value.trim())
+                .map_err(|e| invalid(format!("`{}`: {}", value, e)))
+        };
+
+        match setting {
+            "retry_count" => self.set_retry(count()?, self.retry_delay()),
+            "retry_delay" => self.set_retry(self.retry_count(), duration()?),
+            "min_ttl" | "max_ttl" => {
+                let (mut min, mut max) = self.ttl_bounds();
+                match setting {
+                    "min_ttl" => min = duration()?,
+                    _ => max = duration()?,
+                }
+                check_ttl_bounds(min, max).map_err(invalid)?;
+                self.set_ttl_bounds(min, max)
+            }
+            "max_clock_discrepancy" => self.set_max_clock_discrepancy(duration()?),
+            "round_budget" => self.set_round_budget(duration()?),
+            "response_timeout" => self.set_response_timeout(duration()?),
+            "restart_quarantine" => self.set_restart_quarantine(duration()?),
+            "renewal_interval" => self.set_renewal_interval(duration()?),
+            "quorum_margin" => self.set_quorum_margin(count()?),
+            _ => {
+                return Err(invalid(format!(
+                    "unknown setting, expected one of {}",
+                    SETTINGS.join(", ")
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a lock manager from environment variables starting with `prefix`, e.g. `RSLOCK_`.
+    ///
+    /// `<prefix>ENDPOINTS` lists the instances, see `from_endpoints`. The settings accepted by
+    /// `configure` are read from `<prefix>` followed by their upper-case name, e.g.
+    /// `RSLOCK_RETRY_DELAY=500ms`. Settings without a variable keep their defaults.
+    pub fn from_env(prefix: &str) -> Result<LockManager, LockError> {
+        let var = |name: &str| std::env::var(format!("{}{}", prefix, name.to_uppercase()));
+        let endpoints = var("endpoints").map_err(|e| LockError::InvalidSetting {
+            setting: format!("{}ENDPOINTS", prefix),
+            reason: e.to_string(),
+        })?;
+
+        let mut lock_manager = LockManager::from_endpoints(&endpoints)?;
+        for setting in SETTINGS {
+            if let Ok(value) = var(setting) {
+                lock_manager.configure(setting, &value)?;
+            }
+        }
+        Ok(lock_manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration(" 1m 30s ").unwrap(), Duration::from_secs(90));
+        match parse_duration("5 parsecs") {
+            Err(LockError::InvalidSetting { setting, reason }) => {
+                assert_eq!(setting, "`5 parsecs`");
+                assert!(reason.contains("unknown time unit"), "{}", reason);
+            }
+            r => panic!("Expected LockError::InvalidSetting, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_configure() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.configure("retry_count", "5").unwrap();
+        rl.configure("retry_delay", "50ms").unwrap();
+        rl.configure("max_ttl", "1m").unwrap();
+        assert_eq!(rl.retry_count(), 5);
+        assert_eq!(rl.retry_delay(), Duration::from_millis(50));
+        assert_eq!(rl.ttl_bounds(), (Duration::ZERO, Duration::from_secs(60)));
+
+        assert!(matches!(
+            rl.configure("retry_delay", "soon"),
+            Err(LockError::InvalidSetting { setting, .. }) if setting == "retry_delay"
+        ));
+        assert!(rl.configure("ttl", "1s").is_err());
+        assert!(matches!(
+            rl.configure("min_ttl", "2m"),
+            Err(LockError::InvalidSetting { setting, .. }) if setting == "min_ttl"
+        ));
+        assert_eq!(rl.ttl_bounds(), (Duration::ZERO, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_from_env() {
+        std::env::set_var(
+            "RSLOCK_TEST_ENDPOINTS",
+            "127.0.0.1:1,127.0.0.1:2,127.0.0.1:3",
+        );
+        std::env::set_var("RSLOCK_TEST_RETRY_DELAY", "2s");
+        let rl = LockManager::from_env("RSLOCK_TEST_").unwrap();
+        assert_eq!(rl.servers.len(), 3);
+        assert_eq!(rl.retry_delay(), Duration::from_secs(2));
+
+        assert!(LockManager::from_env("RSLOCK_MISSING_").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! The Redlock algorithm, independent of Redis.
+//!
+//! Quorum math, validity computation and the interpretation of per-instance results,
+//! as pure functions. `LockManager` combines them with the Redis transport. They are public
+//! so that the algorithm can be tested and reasoned about with simulated instances.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Version byte of versioned lock values, see `value_token`.
+pub const VALUE_VERSION_1: u8 = 1;
+
+/// The outcome of releasing a lock with `LockManager::unlock`.
+///
+/// Unreachable instances are not taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockOutcome {
+    /// The lock was still held on at least one instance and has been released.
+    Released,
+    /// The lock was not held anymore, because it was released before or has expired.
+    AlreadyReleased,
+    /// A quorum of instances hold the resource with another value:
+    /// the lock expired and has been acquired by someone else.
+    NotOwned,
+}
+
+/// Number of instances that must agree for a lock to be acquired: a majority of `instances`.
+pub fn quorum(instances: usize) -> u32 {
+    (instances as u32) / 2 + 1
+}
+
+/// Allowance for clock drift between the client and the instances for a lock of `ttl`.
+pub fn clock_drift(ttl: Duration, factor: f32, padding: Duration) -> Duration {
+    Duration::from_millis((ttl.as_millis() as f32 * factor) as u64) + padding
+}
+
+/// Time a lock is valid for after a round of acquisition that took `elapsed`.
+///
+/// Returns `None` if the round and the drift allowance used up the whole `ttl`.
+pub fn validity(ttl: Duration, elapsed: Duration, drift: Duration) -> Option<Duration> {
+    ttl.checked_sub(elapsed + drift).filter(|v| !v.is_zero())
+}
+
+/// Per-instance results of one round of locking or extending.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    /// Instances that performed the operation.
+    pub succeeded: u32,
+    /// Instances that refused it, because the key is held by someone else.
+    pub rejected: u32,
+    /// Instances that could not be reached or failed.
+    pub unreachable: u32,
+}
+
+impl Tally {
+    /// Count per-instance results, `Ok(false)` meaning the instance refused the operation.
+    pub fn count<E>(results: impl IntoIterator<Item = Result<bool, E>>) -> Tally {
+        results
+            .into_iter()
+            .fold(Tally::default(), |mut tally, result| {
+                match result {
+                    Ok(true) => tally.succeeded += 1,
+                    Ok(false) => tally.rejected += 1,
+                    Err(_) => tally.unreachable += 1,
+                }
+                tally
+            })
+    }
+
+    /// Number of instances in the round.
+    pub fn total(&self) -> u32 {
+        self.succeeded + self.rejected + self.unreachable
+    }
+
+    /// Whether enough instances performed the operation.
+    pub fn has_quorum(&self, quorum: u32) -> bool {
+        self.succeeded >= quorum
+    }
+
+    /// Whether so many instances refused an extension that a quorum cannot hold the lock anymore.
+    pub fn is_lost(&self, quorum: u32) -> bool {
+        self.rejected + quorum > self.total()
+    }
+}
+
+/// Estimate when enough instances are free again to reach a quorum,
+/// given how long the keys held by others on each instance remain.
+pub fn retry_after(free_in: impl IntoIterator<Item = Duration>, quorum: u32) -> Option<Duration> {
+    let mut free_in: Vec<Duration> = free_in.into_iter().collect();
+    free_in.sort();
+
+    free_in
+        .get((quorum as usize).checked_sub(1)?)
+        .copied()
+        .filter(|retry_after| !retry_after.is_zero())
+}
+
+/// Combine the per-instance outcomes of an unlock, `None` standing for an unreachable instance.
+pub fn unlock_outcome(results: &[Option<UnlockOutcome>], quorum: u32) -> UnlockOutcome {
+    let count = |outcome| results.iter().filter(|&&r| r == Some(outcome)).count() as u32;
+    if count(UnlockOutcome::NotOwned) >= quorum {
+        UnlockOutcome::NotOwned
+    } else if count(UnlockOutcome::Released) > 0 {
+        UnlockOutcome::Released
+    } else {
+        UnlockOutcome::AlreadyReleased
+    }
+}
+
+/// A random delay of up to `max`, in whole milliseconds, to spread out retries.
+pub fn jitter(max: Duration, rng: &mut impl Rng) -> Duration {
+    match u64::try_from(max.as_millis()).unwrap_or(u64::MAX) {
+        0 => Duration::ZERO,
+        max => Duration::from_millis(rng.gen_range(0..max)),
+    }
+}
+
+/// Extract the token from a lock value.
+///
+/// Lock values are either a plain token, or versioned as `[1, len, token.., metadata..]`
+/// with `len` being the token's length. A versioned value with an empty token is plain,
+/// as no lock has an empty token.
+pub fn value_token(value: &[u8]) -> &[u8] {
+    match value {
+        [VALUE_VERSION_1, len @ 1..=u8::MAX, rest @ ..] if rest.len() >= *len as usize => {
+            &rest[..*len as usize]
+        }
+        _ => value,
+    }
+}
+
+/// A lock value, decoded by `decode_value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockValue<'a> {
+    /// A value without metadata, the token itself.
+    Plain(&'a [u8]),
+    /// A versioned value: `[1, len, token.., metadata..]`.
+    V1 { token: &'a [u8], metadata: &'a [u8] },
+}
+
+impl<'a> LockValue<'a> {
+    /// The token identifying the lock, see `owns`.
+    pub fn token(&self) -> &'a [u8] {
+        match *self {
+            LockValue::Plain(token) => token,
+            LockValue::V1 { token, .. } => token,
+        }
+    }
+
+    /// The metadata of a versioned value, `None` for plain values.
+    pub fn metadata(&self) -> Option<&'a [u8]> {
+        match *self {
+            LockValue::Plain(_) => None,
+            LockValue::V1 { metadata, .. } => Some(metadata),
+        }
+    }
+}
+
+/// Why a lock value could not be encoded or decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ValueError {
+    #[error("Lock value is empty")]
+    Empty,
+
+    #[error("Lock token is empty")]
+    EmptyToken,
+
+    #[error("Lock token of {len} bytes is longer than 255 bytes")]
+    TokenTooLong { len: usize },
+}
+
+/// Encode a versioned lock value carrying `metadata` next to `token`.
+///
+/// Fails if the token is empty or longer than 255 bytes.
+pub fn encode_value(token: &[u8], metadata: &[u8]) -> Result<Vec<u8>, ValueError> {
+    let len = match u8::try_from(token.len()) {
+        Ok(0) => return Err(ValueError::EmptyToken),
+        Ok(len) => len,
+        Err(_) => return Err(ValueError::TokenTooLong { len: token.len() }),
+    };
+    let mut value = Vec::with_capacity(2 + token.len() + metadata.len());
+    value.extend_from_slice(&[VALUE_VERSION_1, len]);
+    value.extend_from_slice(token);
+    value.extend_from_slice(metadata);
+    Ok(value)
+}
+
+/// Decode a lock value read from Redis.
+///
+/// Never panics, whatever the input: other clients sharing the instances may write arbitrary
+/// bytes. Values that don't parse as versioned are plain, like `value_token` and the lock
+/// scripts treat them, so the decoded token always equals `value_token(value)`.
+/// Fails for empty values and versioned values with an empty token, which no lock has.
+pub fn decode_value(value: &[u8]) -> Result<LockValue<'_>, ValueError> {
+    match value {
+        [] => Err(ValueError::Empty),
+        [VALUE_VERSION_1, 0, ..] => Err(ValueError::EmptyToken),
+        [VALUE_VERSION_1, len, rest @ ..] if rest.len() >= *len as usize => {
+            let (token, metadata) = rest.split_at(*len as usize);
+            Ok(LockValue::V1 { token, metadata })
+        }
+        _ => Ok(LockValue::Plain(value)),
+    }
+}
+
+/// Whether a stored lock value belongs to the same lock as `ours`.
+///
+/// Ownership is decided by the token alone, so metadata may change and future versions
+/// of the value format can be rolled out while older clients still hold locks.
+/// Plain tokens that parse as versioned are identified by a part of themselves,
+/// which is why the tokens `LockManager` generates never start with `VALUE_VERSION_1`.
+pub fn owns(stored: &[u8], ours: &[u8]) -> bool {
+    stored == ours || value_token(stored) == value_token(ours)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_quorum() {
+        assert_eq!(quorum(0), 1);
+        assert_eq!(quorum(1), 1);
+        assert_eq!(quorum(3), 2);
+        assert_eq!(quorum(4), 3);
+        assert_eq!(quorum(5), 3);
+    }
+
+    #[test]
+    fn test_validity() {
+        let drift = clock_drift(1000 * MS, 0.01, 2 * MS);
+        assert_eq!(drift, 12 * MS);
+        assert_eq!(validity(1000 * MS, 88 * MS, drift), Some(900 * MS));
+        assert_eq!(validity(1000 * MS, 988 * MS, drift), None);
+        assert_eq!(validity(1000 * MS, 2000 * MS, drift), None);
+    }
+
+    #[test]
+    fn test_tally() {
+        let tally = Tally::count([Ok(true), Ok(false), Err(()), Ok(true), Ok(false)]);
+        assert_eq!(
+            tally,
+            Tally {
+                succeeded: 2,
+                rejected: 2,
+                unreachable: 1
+            }
+        );
+        assert!(!tally.has_quorum(3));
+        assert!(!tally.is_lost(3));
+        assert!(Tally::count([Ok::<_, ()>(false), Ok(false), Ok(true)]).is_lost(2));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let free_in = [300 * MS, 100 * MS, 200 * MS];
+        assert_eq!(retry_after(free_in, 2), Some(200 * MS));
+        assert_eq!(retry_after(free_in, 4), None);
+        assert_eq!(retry_after([Duration::ZERO, Duration::ZERO], 2), None);
+    }
+
+    #[test]
+    fn test_encode_decode_value() {
+        let value = encode_value(b"token", b"meta").unwrap();
+        assert_eq!(
+            decode_value(&value),
+            Ok(LockValue::V1 {
+                token: b"token",
+                metadata: b"meta"
+            })
+        );
+        assert_eq!(decode_value(b"plain"), Ok(LockValue::Plain(b"plain")));
+        assert_eq!(decode_value(&[1, 9, 1]), Ok(LockValue::Plain(&[1, 9, 1])));
+        assert_eq!(decode_value(b""), Err(ValueError::Empty));
+        assert_eq!(decode_value(&[1, 0, 7]), Err(ValueError::EmptyToken));
+        assert_eq!(encode_value(b"", b""), Err(ValueError::EmptyToken));
+        assert_eq!(
+            encode_value(&[0; 256], b""),
+            Err(ValueError::TokenTooLong { len: 256 })
+        );
+    }
+
+    #[test]
+    fn test_decode_value_arbitrary_bytes() {
+        let mut rng = StdRng::seed_from_u64(489);
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0..8);
+            let mut value: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+            if let Some(first) = value.first_mut() {
+                *first = rng.gen_range(0..2);
+            }
+            match decode_value(&value) {
+                Ok(decoded) => assert_eq!(decoded.token(), value_token(&value)),
+                Err(_) => assert!(value.is_empty() || value.starts_with(&[1, 0])),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unlock_outcome() {
+        use UnlockOutcome::*;
+        assert_eq!(
+            unlock_outcome(&[Some(Released), None, Some(AlreadyReleased)], 2),
+            Released
+        );
+        assert_eq!(
+            unlock_outcome(&[Some(NotOwned), Some(Released), Some(NotOwned)], 2),
+            NotOwned
+        );
+        assert_eq!(unlock_outcome(&[None, None, None], 2), AlreadyReleased);
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(
+            jitter(Duration::ZERO, &mut StepRng::new(5, 1)),
+            Duration::ZERO
+        );
+        assert!(jitter(200 * MS, &mut StepRng::new(u64::MAX, 1)) < 200 * MS);
+    }
+
+    #[test]
+    fn test_value_token() {
+        assert_eq!(value_token(b"plain"), b"plain");
+        assert_eq!(value_token(b"\x01\x03tokmeta"), b"tok");
+        assert_eq!(value_token(b"\x01\x09tok"), b"\x01\x09tok");
+        assert!(owns(b"\x01\x03tokmeta", b"tok"));
+        assert!(owns(b"tok", b"\x01\x03tok"));
+        assert!(!owns(b"\x01\x03tokmeta", b"other"));
+
+        // Plain tokens starting with the version byte
+        assert_eq!(value_token(b"\x01\x00tok"), b"\x01\x00tok");
+        assert!(!owns(b"\x01\x00tok", b"\x01\x00oth"));
+        assert_eq!(value_token(b"\x01\x02tok"), b"to");
+        assert!(owns(b"\x01\x02tok", b"\x01\x02toy"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Parsing of Redis endpoints given as text, see `LockManager::from_endpoints`.
+
+use redis::{ConnectionInfo, IntoConnectionInfo};
+
+use crate::lock::LockError;
+
+// Parses a comma-separated list of endpoints.
+//
+// Entries are either complete URIs, or `host[:port]` with the `redis` scheme implied.
+// If only the first entry has a scheme, the list is a host list sharing the scheme and
+// credentials of the first entry and the database and parameters of the last one.
+pub(crate) fn parse_endpoints(spec: &str) -> Result<Vec<ConnectionInfo>, LockError> {
+    let entries: Vec<&str> = spec.split(',').map(str::trim).collect();
+    let shared = entries.len() > 1
+        && entries[0].contains("://")
+        && entries[1..].iter().all(|entry| !entry.contains("://"));
+    if !shared {
+        return entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| parse_endpoint(index, entry))
+            .collect();
+    }
+
+    let (scheme, first) = entries[0].split_once("://").expect("checked above");
+    let (credentials, first) = match first.rsplit_once('@') {
+        Some((credentials, host)) => (format!("{}@", credentials), host),
+        None => (String::new(), first),
+    };
+    let last = entries.len() - 1;
+    let (_, suffix) = split_suffix(entries[last]);
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let host = if index == 0 { first } else { entry };
+            let (host, own_suffix) = split_suffix(host);
+            if index < last && !own_suffix.is_empty() {
+                return Err(invalid(
+                    index,
+                    entry,
+                    "only the last host of a list may have a database or parameters",
+                ));
+            }
+            let uri = format!("{}://{}{}{}", scheme, credentials, host, suffix);
+            parse_uri(index, entry, &uri)
+        })
+        .collect()
+}
+
+// Parses a single URI or `host[:port]`
+pub(crate) fn parse_endpoint(index: usize, entry: &str) -> Result<ConnectionInfo, LockError> {
+    if entry.is_empty() {
+        return Err(invalid(index, entry, "empty entry"));
+    }
+    if entry.contains("://") {
+        parse_uri(index, entry, entry)
+    } else {
+        parse_uri(index, entry, &format!("redis://{}", entry))
+    }
+}
+
+fn parse_uri(index: usize, entry: &str, uri: &str) -> Result<ConnectionInfo, LockError> {
+    let uri = bracket_ipv6(uri);
+    let mut info = uri
+        .as_str()
+        .into_connection_info()
+        .map_err(|e| invalid(index, entry, &e.to_string()))?;
+
+    // The database and credentials may be given as parameters for every scheme,
+    // not only for unix sockets
+    let query = uri.split_once('?').map_or("", |(_, query)| query);
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        let value = percent_decode(value)
+            .ok_or_else(|| invalid(index, entry, &format!("invalid encoding of `{}`", key)))?;
+        match key {
+            "db" => {
+                info.redis.db = value
+                    .parse()
+                    .map_err(|_| invalid(index, entry, &format!("invalid database `{}`", value)))?
+            }
+            "user" | "username" => info.redis.username = Some(value),
+            "pass" | "password" => info.redis.password = Some(value),
+            _ => {
+                return Err(invalid(
+                    index,
+                    entry,
+                    &format!("unknown parameter `{}`", key),
+                ))
+            }
+        }
+    }
+    Ok(info)
+}
+
+// Splits `host[:port]` from a following `/db` and `?parameters`
+fn split_suffix(s: &str) -> (&str, &str) {
+    s.split_at(s.find(['/', '?']).unwrap_or(s.len()))
+}
+
+// Wraps a bare IPv6 literal without a port in brackets, so it parses as a URI host
+fn bracket_ipv6(uri: &str) -> String {
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return uri.to_string();
+    };
+    let (authority, suffix) = split_suffix(rest);
+    let (credentials, host) = match authority.rsplit_once('@') {
+        Some((credentials, host)) => (format!("{}@", credentials), host),
+        None => (String::new(), authority),
+    };
+    if host.starts_with('[') || host.matches(':').count() < 2 {
+        return uri.to_string();
+    }
+    format!("{}://{}[{}]{}", scheme, credentials, host, suffix)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+// The error for an entry, with any password in it masked
+fn invalid(index: usize, entry: &str, reason: &str) -> LockError {
+    let entry = match entry.split_once("://") {
+        Some((scheme, rest)) => match rest.rsplit_once('@') {
+            Some((credentials, host)) if credentials.contains(':') => {
+                let user = credentials.split(':').next().unwrap_or_default();
+                format!("{}://{}:***@{}", scheme, user, host)
+            }
+            _ => entry.to_string(),
+        },
+        None => entry.to_string(),
+    };
+    LockError::InvalidEndpoint {
+        index,
+        reason: format!("`{}`: {}", entry, reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::ConnectionAddr;
+
+    use super::*;
+
+    fn tcp(host: &str, port: u16) -> ConnectionAddr {
+        ConnectionAddr::Tcp(host.to_string(), port)
+    }
+
+    #[test]
+    fn test_parse_endpoints() {
+        let infos =
+            parse_endpoints("[::1]:6380, redis://127.0.0.1:6379/2, ::1, host?db=1").unwrap();
+        let addrs: Vec<_> = infos.iter().map(|info| info.addr.clone()).collect();
+        assert_eq!(
+            addrs,
+            [
+                tcp("::1", 6380),
+                tcp("127.0.0.1", 6379),
+                tcp("::1", 6379),
+                tcp("host", 6379)
+            ]
+        );
+        let dbs: Vec<_> = infos.iter().map(|info| info.redis.db).collect();
+        assert_eq!(dbs, [0, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_parse_endpoints_host_list() {
+        let infos = parse_endpoints("redis://user:secret@a:1,[::1]:2,c/3?password=p%40ss").unwrap();
+        let addrs: Vec<_> = infos.iter().map(|info| info.addr.clone()).collect();
+        assert_eq!(addrs, [tcp("a", 1), tcp("::1", 2), tcp("c", 6379)]);
+        for info in &infos {
+            assert_eq!(info.redis.db, 3);
+            assert_eq!(info.redis.username.as_deref(), Some("user"));
+            assert_eq!(info.redis.password.as_deref(), Some("p@ss"));
+        }
+    }
+
+    #[test]
+    fn test_parse_endpoints_errors() {
+        let reason = |spec| match parse_endpoints(spec) {
+            Err(LockError::InvalidEndpoint { index, reason }) => (index, reason),
+            r => panic!("Expected LockError::InvalidEndpoint, got {:?}", r),
+        };
+
+        assert_eq!(reason("a,,b"), (1, "``: empty entry".to_string()));
+        assert_eq!(
+            reason("a, b?timeout=1"),
+            (1, "`b?timeout=1`: unknown parameter `timeout`".to_string())
+        );
+        assert_eq!(
+            reason("redis://u:secret@a/1,b"),
+            (
+                0,
+                "`redis://u:***@a/1`: only the last host of a list may have a database or parameters"
+                    .to_string()
+            )
+        );
+        assert_eq!(reason("a,redis://b:99999").0, 1);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Noteworthy occurrences reported by the lock manager.
+///
+/// Events are passed to the handler installed with `LockManager::set_event_handler`,
+/// which can forward them to logs or metrics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A lock has been held for longer than the limit configured
+    /// with `LockManager::set_max_hold_time`, extensions included.
+    HoldTimeExceeded {
+        resource: Vec<u8>,
+        held: Duration,
+        limit: Duration,
+    },
+    /// The monotonic and the wall clock disagree on how long a lock has been held
+    /// by more than `LockManager::set_max_clock_discrepancy` allows.
+    ClockDiscrepancy {
+        resource: Vec<u8>,
+        discrepancy: Duration,
+    },
+    /// A Redis instance's `maxmemory-policy` may evict lock keys before they expire,
+    /// detected by `LockManager::provision`.
+    EvictionPolicy { address: String, policy: String },
+    /// A lock expired without ever being released, e.g. because it was dropped
+    /// without calling `LockManager::unlock`.
+    ///
+    /// Only raised with the `leak-detection` feature enabled. Leaks are detected lazily when
+    /// the next lock is acquired or extended through the same manager or one of its clones.
+    /// `backtrace` shows where the leaked lock was acquired.
+    LockLeaked {
+        resource: Vec<u8>,
+        backtrace: String,
+    },
+    /// A `LockGuard` or `LockScope` was dropped within a tokio runtime without the `tokio-comp`
+    /// feature, where its release on drop would otherwise block.
+    ///
+    /// Blocking would stall the runtime's worker thread, so the lock is released in a spawned
+    /// task instead and may still be held for a moment. Enable `tokio-comp` when using tokio,
+    /// or release locks explicitly.
+    BlockingDrop { resource: Vec<u8> },
+    /// Too many instances answered that they no longer hold a monitored lock for a quorum
+    /// to remain, detected by `LockManager::monitor_quorum`. `holding` instances still hold it.
+    QuorumLost { resource: Vec<u8>, holding: u32 },
+    /// An instance reported a new run id to `LockManager::check_restarts`, so it restarted
+    /// or its address now leads to another node, e.g. after a failover, and it may have
+    /// forgotten its locks. It is excluded from quorums for `quarantined_for`,
+    /// if a quarantine is configured with `LockManager::set_restart_quarantine`.
+    InstanceRestarted {
+        address: String,
+        run_id: String,
+        quarantined_for: Option<Duration>,
+    },
+    /// A lock kept alive with `LockManager::keep_alive` or `LockGuard::into_auto_extended`
+    /// could not be renewed before it expired, or a quorum of instances no longer held it.
+    RenewalFailed { resource: Vec<u8> },
+    /// What a lock acquisition in shadow mode would have resulted in,
+    /// see `LockManager::set_shadow_mode`. `succeeded` instances granted the lock, which
+    /// was released right away, `contended` tells whether it was held by another client.
+    ShadowAcquisition {
+        resource: Vec<u8>,
+        acquired: bool,
+        contended: bool,
+        succeeded: u32,
+        elapsed: Duration,
+    },
+}
+
+/// Callback receiving the manager's events.
+#[derive(Clone)]
+pub(crate) struct EventHandler(Arc<dyn Fn(&Event) + Send + Sync>);
+
+impl EventHandler {
+    pub(crate) fn new(handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        EventHandler(Arc::new(handler))
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        (self.0)(&event)
+    }
+}
+
+impl fmt::Debug for EventHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventHandler")
+    }
+}
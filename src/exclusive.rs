@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::Future;
+
+use crate::lock::{AcquireOptions, LockError, LockManager};
+use crate::resource::LockResource;
+
+/// The outcome of `LockManager::run_exclusive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exclusive<T> {
+    /// The lock was acquired and the future ran to completion, with this output.
+    Ran(T),
+    /// The resource was held by someone else, so the future was not run.
+    Skipped,
+}
+
+impl LockManager {
+    /// Run `fut` while holding the lock for the given resource, unless someone else holds it.
+    ///
+    /// A single acquisition attempt is made. If it fails because the resource is taken,
+    /// `Exclusive::Skipped` is returned right away, which is what a job that only one replica
+    /// should run needs. Otherwise the lock is kept alive with `keep_alive` while `fut` runs,
+    /// extending it by `ttl` as often as `set_renewal_interval` sets, and released afterwards.
+    ///
+    /// If the lock is lost, see `Renewal::lost`, `fut` is dropped at its next suspension point
+    /// and `LockError::LockLost` is returned, so that the work never continues without the lock.
+    /// Requires a running runtime to renew the lock on, like `keep_alive`.
+    pub async fn run_exclusive<R, F, T>(
+        &self,
+        resource: &R,
+        ttl: Duration,
+        fut: F,
+    ) -> Result<Exclusive<T>, LockError>
+    where
+        R: LockResource + ?Sized,
+        F: Future<Output = T>,
+    {
+        let options = AcquireOptions::new().retry(1, Duration::ZERO);
+        let lock = match self.lock_with_options(resource, ttl, &options).await {
+            Ok(lock) => lock,
+            Err(LockError::Unavailable | LockError::Contended { .. }) => {
+                return Ok(Exclusive::Skipped)
+            }
+            Err(e) => return Err(e),
+        };
+
+        let renewal = match self.keep_alive(&lock, ttl) {
+            Ok(renewal) => renewal,
+            Err(e) => {
+                self.unlock(&lock).await;
+                return Err(e);
+            }
+        };
+
+        let output = {
+            let lost = renewal.lost();
+            futures::pin_mut!(fut, lost);
+            match future::select(fut, lost).await {
+                Either::Left((output, _)) => Some(output),
+                Either::Right(((), _)) => None,
+            }
+        };
+        drop(renewal);
+        // Also releases what may be left of a lost lock
+        self.unlock(&lock).await;
+
+        match output {
+            Some(output) => Ok(Exclusive::Ran(output)),
+            None => Err(LockError::LockLost),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_run_exclusive_extends_and_skips() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let rl2 = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(300);
+
+        let job = async {
+            tokio::time::sleep(Duration::from_millis(700)).await;
+            rl2.run_exclusive(&key, ttl, async {}).await.unwrap()
+        };
+        assert_eq!(
+            rl.run_exclusive(&key, ttl, job).await?,
+            Exclusive::Ran(Exclusive::Skipped)
+        );
+        assert_eq!(
+            rl2.run_exclusive(&key, ttl, async { 1 }).await?,
+            Exclusive::Ran(1)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_exclusive_cancels_on_lock_loss() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let finished = std::sync::atomic::AtomicBool::new(false);
+
+        let job = async {
+            // Taken over by someone else
+            for server in &rl.servers {
+                let mut con = server.get_connection().unwrap();
+                redis::cmd("SET").arg(&key).arg("other").execute(&mut con);
+            }
+            tokio::time::sleep(Duration::from_millis(2000)).await;
+            finished.store(true, std::sync::atomic::Ordering::SeqCst);
+        };
+        match rl
+            .run_exclusive(&key, Duration::from_millis(300), job)
+            .await
+        {
+            Err(LockError::LockLost) => (),
+            r => panic!("Expected LockError::LockLost, got {:?}", r),
+        }
+        assert!(!finished.load(std::sync::atomic::Ordering::SeqCst));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use futures::stream::{self, Stream};
+use futures::{future, FutureExt};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::lock::{Bytes, LockError, LockManager, ReleaseHandle};
+use crate::renewal::Renewal;
+use crate::resource::LockResource;
+
+/// A change of leadership, reported by `LeaderElector::events`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeadershipEvent {
+    /// `campaign` won the lease of `key`.
+    Elected { key: Vec<u8> },
+    /// The lease could not be renewed, so another candidate may be elected.
+    Lost { key: Vec<u8> },
+    /// The lease was given up with `resign`.
+    Resigned { key: Vec<u8> },
+}
+
+/// Leader election on top of the lock primitives, created with `LeaderElector::new`.
+///
+/// The leader is whoever holds the lock on the election's key, the lease. `campaign` waits
+/// until it is acquired, after which it is renewed in the background, see
+/// `LockManager::keep_alive`, until `resign` releases it or a renewal fails. Changes are
+/// reported by `events`, so the work done as leader can be stopped once the lease is lost.
+/// A lost lease is not campaigned for again automatically.
+///
+/// Dropping the elector stops renewing the lease, which is then held until it expires.
+pub struct LeaderElector {
+    lock_manager: LockManager,
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    next_term: u64,
+    term: Option<Term>,
+    subscribers: Vec<mpsc::UnboundedSender<LeadershipEvent>>,
+}
+
+// A won lease, renewed by a task of the manager's `TaskSet` until the term is dropped
+struct Term {
+    id: u64,
+    release: ReleaseHandle,
+    _resign: oneshot::Sender<()>,
+}
+
+impl Shared {
+    fn emit(&mut self, event: LeadershipEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+fn locked(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl LeaderElector {
+    /// Create an elector campaigning through `lock_manager`.
+    pub fn new(lock_manager: LockManager) -> LeaderElector {
+        LeaderElector {
+            lock_manager,
+            shared: Arc::default(),
+        }
+    }
+
+    /// Whether the elector currently holds a lease.
+    pub fn is_leader(&self) -> bool {
+        locked(&self.shared).term.is_some()
+    }
+
+    /// The key of the lease held, if any.
+    pub fn leader_key(&self) -> Option<Vec<u8>> {
+        let shared = locked(&self.shared);
+        Some(shared.term.as_ref()?.release.resource().to_vec())
+    }
+
+    /// Leadership changes from now on. The stream ends when the elector is dropped.
+    pub fn events(&self) -> impl Stream<Item = LeadershipEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        locked(&self.shared).subscribers.push(sender);
+        stream::unfold(receiver, |mut receiver| async move {
+            let event = receiver.recv().await?;
+            Some((event, receiver))
+        })
+    }
+
+    /// Wait until elected leader for `key` with a lease of `lease`, which is then renewed
+    /// in the background.
+    ///
+    /// While another candidate holds the lease, the key is polled until it is free, see
+    /// `LockManager::wait_until_free`, and acquiring it is retried. Returns immediately if
+    /// the elector already holds the lease of `key`, and resigns first if it holds the lease
+    /// of another key. Of concurrent campaigns, the one elected last keeps its lease and the
+    /// others are resigned. Fails with errors that retrying cannot resolve,
+    /// e.g. `LockError::TtlTooLarge`, and with `LockError::MaintenanceMode` in maintenance mode.
+    /// Requires a running runtime to renew the lease on.
+    pub async fn campaign<R: LockResource + ?Sized>(
+        &self,
+        key: &R,
+        lease: Duration,
+    ) -> Result<(), LockError> {
+        let resource = key.key();
+        let lock = loop {
+            match self.leader_key() {
+                Some(leading) if leading[..] == resource[..] => return Ok(()),
+                Some(_) => self.resign().await,
+                None => (),
+            }
+            match self.lock_manager.lock(key, lease).await {
+                Ok(lock) => break lock,
+                Err(LockError::Unavailable | LockError::Contended { .. }) => {
+                    self.lock_manager.wait_until_free(key, lease).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let renewal = match self.lock_manager.keep_alive(&lock, lease) {
+            Ok(renewal) => renewal,
+            Err(e) => {
+                self.lock_manager.unlock(&lock).await;
+                return Err(e);
+            }
+        };
+
+        let (resign, resigned) = oneshot::channel();
+        let (id, previous) = {
+            let mut shared = locked(&self.shared);
+            // A concurrent campaign may have been elected in the meantime, its term is resigned
+            let previous = shared.term.take();
+            if let Some(term) = &previous {
+                shared.emit(LeadershipEvent::Resigned {
+                    key: term.release.resource().to_vec(),
+                });
+            }
+            let id = shared.next_term;
+            shared.next_term += 1;
+            shared.term = Some(Term {
+                id,
+                release: lock.split_release_handle(),
+                _resign: resign,
+            });
+            shared.emit(LeadershipEvent::Elected {
+                key: lock.resource.clone(),
+            });
+            (id, previous)
+        };
+
+        let weak = Arc::downgrade(&self.shared);
+        self.lock_manager
+            .tasks_arc()
+            .spawn(watch_term(weak, id, renewal, resigned));
+        if let Some(term) = previous {
+            term.release.release().await;
+        }
+        Ok(())
+    }
+
+    /// Give up the lease, if any, releasing it so that another candidate can be elected.
+    pub async fn resign(&self) {
+        let Some(term) = locked(&self.shared).term.take() else {
+            return;
+        };
+        let key = term.release.resource().to_vec();
+        term.release.release().await;
+        locked(&self.shared).emit(LeadershipEvent::Resigned { key });
+    }
+}
+
+// Reports the lease as lost once renewing it fails, unless resigned before
+async fn watch_term(
+    shared: Weak<Mutex<Shared>>,
+    id: u64,
+    renewal: Renewal,
+    resigned: oneshot::Receiver<()>,
+) {
+    let lost = renewal.lost().map(|()| true);
+    let resigned = resigned.map(|_| false);
+    futures::pin_mut!(lost);
+    if !future::select(lost, resigned).await.factor_first().0 {
+        return;
+    }
+    let Some(shared) = shared.upgrade() else {
+        return;
+    };
+    let mut shared = locked(&shared);
+    if shared.term.as_ref().is_some_and(|term| term.id == id) {
+        shared.term = None;
+        shared.emit(LeadershipEvent::Lost {
+            key: renewal.resource().to_vec(),
+        });
+    }
+}
+
+impl fmt::Debug for LeaderElector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let leader_key = self.leader_key();
+        f.debug_struct("LeaderElector")
+            .field("leader_key", &leader_key.map(|key| Bytes(&key).to_string()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_leader_election() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let lease = Duration::from_millis(500);
+        let leader = LeaderElector::new(rl.clone());
+        let candidate = LeaderElector::new(LockManager::new(addresses.clone()));
+        let events = leader.events();
+        futures::pin_mut!(events);
+
+        leader.campaign(&key, lease).await?;
+        assert!(leader.is_leader());
+        assert_eq!(
+            events.next().await,
+            Some(LeadershipEvent::Elected { key: key.clone() })
+        );
+
+        // The lease is renewed while the candidate waits for it
+        let campaign = candidate.campaign(&key, lease);
+        assert!(tokio::time::timeout(Duration::from_millis(1500), campaign)
+            .await
+            .is_err());
+        assert!(leader.is_leader());
+
+        let (elected, _) = tokio::join!(candidate.campaign(&key, lease), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            leader.resign().await
+        });
+        elected?;
+        assert!(!leader.is_leader());
+        assert!(candidate.is_leader());
+        assert_eq!(
+            events.next().await,
+            Some(LeadershipEvent::Resigned { key: key.clone() })
+        );
+
+        // A lease taken away is reported as lost
+        let events = candidate.events();
+        futures::pin_mut!(events);
+        for server in &rl.servers {
+            let mut con = server.get_connection()?;
+            redis::cmd("DEL").arg(&key).execute(&mut con);
+        }
+        assert_eq!(
+            tokio::time::timeout(Duration::from_secs(2), events.next()).await?,
+            Some(LeadershipEvent::Lost { key: key.clone() })
+        );
+        assert!(!candidate.is_leader());
+
+        // Campaigning for another key gives up the lease held
+        let events = leader.events();
+        futures::pin_mut!(events);
+        let other = rl.get_unique_lock_id()?;
+        leader.campaign(&key, lease).await?;
+        leader.campaign(&other, lease).await?;
+        assert_eq!(leader.leader_key(), Some(other.clone()));
+        assert_eq!(
+            events.next().await,
+            Some(LeadershipEvent::Elected { key: key.clone() })
+        );
+        assert_eq!(
+            events.next().await,
+            Some(LeadershipEvent::Resigned { key: key.clone() })
+        );
+        assert_eq!(
+            events.next().await,
+            Some(LeadershipEvent::Elected { key: other.clone() })
+        );
+        leader.resign().await;
+
+        // Of concurrent campaigns only the last one elected keeps its lease
+        let (first, second) =
+            tokio::join!(leader.campaign(&key, lease), leader.campaign(&other, lease));
+        first?;
+        second?;
+        let lost = match leader.leader_key() {
+            Some(leading) if leading == key => other,
+            Some(leading) if leading == other => key,
+            leading => panic!("Expected one of the keys to be led, got {:?}", leading),
+        };
+        let lock = rl.lock(&lost, lease).await?;
+        rl.unlock(&lock).await;
+
+        Ok(())
+    }
+}
@@ -1,5 +1,155 @@
 #[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod attempt;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod builder;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod cache;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod child;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod cleanup;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod composite;
+#[cfg(all(
+    feature = "humantime",
+    any(feature = "async-std-comp", feature = "tokio-comp")
+))]
+mod config;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub mod core;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod endpoint;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod event;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod exclusive;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod leader;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
 mod lock;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod migrate;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod monitor;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod mutex;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod optimistic;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod owned;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod protect;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod protected;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod provision;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod registry;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod release;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod renewal;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod resource;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod restart;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod rt;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod rwlock;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod scope;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod session;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod shard;
+#[cfg(all(
+    feature = "simulation",
+    any(feature = "async-std-comp", feature = "tokio-comp")
+))]
+pub mod simulation;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod state;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod tasks;
+#[cfg(all(
+    feature = "test-support",
+    any(feature = "async-std-comp", feature = "tokio-comp")
+))]
+pub mod testing;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod timed;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod transport;
+#[cfg(all(
+    feature = "test-support",
+    any(feature = "async-std-comp", feature = "tokio-comp")
+))]
+pub mod vectors;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod votes;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+mod watch;
 
 #[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
-pub use crate::lock::{Lock, LockError, LockGuard, LockManager};
+pub use crate::attempt::{Acquire, AcquireAttempt, AcquireStep};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::builder::LockManagerBuilder;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::cleanup::{CleanupReport, Inconsistency};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::composite::{join_guards, CompositeGuard};
+#[cfg(all(
+    feature = "humantime",
+    any(feature = "async-std-comp", feature = "tokio-comp")
+))]
+pub use crate::config::parse_duration;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::core::UnlockOutcome;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::event::Event;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::exclusive::Exclusive;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::leader::{LeaderElector, LeadershipEvent};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::lock::{
+    AcquireOptions, AcquisitionDetails, Compatibility, ConnectStrategy, ExtensionHandle, Lock,
+    LockError, LockGuard, LockManager, ReleaseHandle, RetryAdvice,
+};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::migrate::{migrate_locks, MigrationFailure, MigrationReport};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::mutex::{DistributedMutex, DistributedMutexGuard};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::owned::{OwnedLock, OwnedLockGuard};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::protect::protect;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::provision::{CapabilityReport, InstanceCapabilities};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::registry::ConnectionRegistry;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::release::ReleaseService;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::renewal::{AutoExtendedGuard, Renewal};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::resource::LockResource;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::scope::LockScope;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::session::LockSession;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::shard::ShardedLockManager;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::state::{
+    ActiveLockState, ConfigSnapshot, InstanceState, StateSnapshot, StatsSnapshot,
+};
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::tasks::TaskSet;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::timed::TimedGuard;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::votes::Votes;
+#[cfg(any(feature = "async-std-comp", feature = "tokio-comp"))]
+pub use crate::watch::LockEvent;
@@ -1,33 +1,32 @@
+use std::fmt;
 use std::io;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use futures::future::join_all;
 use futures::Future;
-use rand::{thread_rng, Rng, RngCore};
-use redis::Value::Okay;
-use redis::{Client, IntoConnectionInfo, RedisResult, Value};
+use rand::{thread_rng, RngCore};
+use redis::{Client, IntoConnectionInfo, RedisResult};
+use tokio::sync::Semaphore;
+
+use crate::core::{self, Tally, UnlockOutcome};
+use crate::event::{Event, EventHandler};
+use crate::registry::ConnectionRegistry;
+use crate::renewal::Renewals;
+use crate::resource::LockResource;
+use crate::state::{ConfigSnapshot, InstanceState, StateSnapshot, Tracker};
+use crate::tasks::TaskSet;
+use crate::transport::{ConnectionSettings, Instance};
+use crate::{endpoint, rt, rwlock, transport};
 
 const DEFAULT_RETRY_COUNT: u32 = 3;
 const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(200);
 const CLOCK_DRIFT_FACTOR: f32 = 0.01;
-const UNLOCK_SCRIPT: &str = r#"
-if redis.call("GET", KEYS[1]) == ARGV[1] then
-  return redis.call("DEL", KEYS[1])
-else
-  return 0
-end
-"#;
-const EXTEND_SCRIPT: &str = r#"
-if redis.call("get", KEYS[1]) ~= ARGV[1] then
-  return 0
-else
-  if redis.call("set", KEYS[1], ARGV[1], "PX", ARGV[2]) ~= nil then
-    return 1
-  else
-    return 0
-  end
-end
-"#;
+const CLOCK_DRIFT_PADDING: Duration = Duration::from_millis(2);
+// Retry policy of `acquire_at`: just enough to break ties between simultaneous attempts
+const SCHEDULED_RETRY_COUNT: u32 = 3;
+const SCHEDULED_RETRY_DELAY: Duration = Duration::from_millis(10);
 
 #[derive(Debug, thiserror::Error)]
 pub enum LockError {
@@ -40,11 +39,226 @@ pub enum LockError {
     #[error("Resource is unavailable")]
     Unavailable,
 
+    #[error("Resource is locked by another client, retry after {retry_after:?}")]
+    Contended { retry_after: Duration },
+
     #[error("TTL exceeded")]
     TtlExceeded,
 
     #[error("TTL too large")]
     TtlTooLarge,
+
+    #[error("TTL outside of the permitted range")]
+    TtlOutOfRange,
+
+    #[error("Lock is held by another client")]
+    LockLost,
+
+    #[error("Lock validity has expired")]
+    ValidityExpired,
+
+    #[error("Lock manager is in maintenance mode")]
+    MaintenanceMode,
+
+    #[error("Quota of {limit} held locks exceeded for prefix {prefix:?}")]
+    QuotaExceeded { prefix: String, limit: usize },
+
+    #[error("Time budget exhausted")]
+    BudgetExhausted,
+
+    #[error("Lock not acquired within the time allowed")]
+    AcquireTimeout,
+
+    #[error("Not enough compatible Redis instances: {0}")]
+    Incompatible(String),
+
+    #[error("Permission denied for {command}")]
+    PermissionDenied { command: String },
+
+    #[error("Invalid endpoint at position {index}: {reason}")]
+    InvalidEndpoint { index: usize, reason: String },
+
+    #[error("No Redis instances configured")]
+    NoServers,
+
+    #[error("No instance at index {index}")]
+    NoSuchInstance { index: usize },
+
+    #[error("Invalid setting {setting}: {reason}")]
+    InvalidSetting { setting: String, reason: String },
+}
+
+/// Whether and when a failed operation is worth retrying, see `LockError::retry_advice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum RetryAdvice {
+    /// The failure is likely transient, e.g. instances were unreachable
+    /// or the manager was in maintenance mode.
+    RetryNow,
+    /// The resource is expected to be free after this long.
+    RetryAfter(Duration),
+    /// Retrying the same call fails the same way, e.g. because of the configuration,
+    /// permissions, a TTL too short for the round trips, or a lock that is lost
+    /// and must be acquired again.
+    Fatal,
+}
+
+impl LockError {
+    /// How retry middleware should treat this error, derived from its cause.
+    ///
+    /// Redis errors are retryable if they are I/O errors, timeouts or dropped connections,
+    /// or errors Redis itself asks to retry, like `TRYAGAIN` and `LOADING`.
+    /// `TtlExceeded` is fatal, as a TTL that the round trips and the clock drift already
+    /// used up is exceeded again by the same call.
+    pub fn retry_advice(&self) -> RetryAdvice {
+        match self {
+            LockError::Io(_) | LockError::Unavailable | LockError::MaintenanceMode => {
+                RetryAdvice::RetryNow
+            }
+            LockError::Redis(e) => {
+                let transient = e.is_io_error()
+                    || e.is_timeout()
+                    || e.is_connection_dropped()
+                    || e.is_connection_refusal()
+                    || matches!(
+                        e.kind(),
+                        redis::ErrorKind::TryAgain
+                            | redis::ErrorKind::BusyLoadingError
+                            | redis::ErrorKind::ClusterDown
+                            | redis::ErrorKind::MasterDown
+                    );
+                if transient {
+                    RetryAdvice::RetryNow
+                } else {
+                    RetryAdvice::Fatal
+                }
+            }
+            LockError::Contended { retry_after } => RetryAdvice::RetryAfter(*retry_after),
+            LockError::TtlExceeded
+            | LockError::TtlTooLarge
+            | LockError::TtlOutOfRange
+            | LockError::LockLost
+            | LockError::ValidityExpired
+            | LockError::QuotaExceeded { .. }
+            | LockError::BudgetExhausted
+            | LockError::AcquireTimeout
+            | LockError::Incompatible(_)
+            | LockError::PermissionDenied { .. }
+            | LockError::InvalidEndpoint { .. }
+            | LockError::NoServers
+            | LockError::NoSuchInstance { .. }
+            | LockError::InvalidSetting { .. } => RetryAdvice::Fatal,
+        }
+    }
+}
+
+/// When the manager connects to the instances, see `LockManager::set_connect_strategy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ConnectStrategy {
+    /// Connect on first use, so the first operations absorb the connection setup.
+    #[default]
+    Lazy,
+    /// Keep connections open, established ahead of use by `LockManager::warm_up`.
+    Eager,
+}
+
+/// Value conventions of other Redlock implementations that locks can be shared with.
+///
+/// All of them lock with `SET NX PX` and release and extend with scripts comparing the stored value,
+/// using the resource name as the key, so mutual exclusion already holds across implementations.
+/// The modes make the lock values look like the ones the other implementation generates,
+/// for tooling and services that read the keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Compatibility {
+    /// 20 random bytes.
+    #[default]
+    Native,
+    /// [node-redlock](https://github.com/mike-marcacci/node-redlock): 16 random bytes as lowercase hex.
+    NodeRedlock,
+    /// [redsync](https://github.com/go-redsync/redsync): 16 random bytes as standard base64.
+    Redsync,
+}
+
+impl Compatibility {
+    fn encode_token(self, token: Vec<u8>) -> Vec<u8> {
+        match self {
+            // Never mistaken for a versioned value, see `core::owns`
+            Compatibility::Native => match token.split_first() {
+                Some((&core::VALUE_VERSION_1, rest)) => [&[!core::VALUE_VERSION_1], rest].concat(),
+                _ => token,
+            },
+            Compatibility::NodeRedlock => Hex(&token[..16]).to_string().into_bytes(),
+            Compatibility::Redsync => base64(&token[..16]).into_bytes(),
+        }
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Per-call overrides of the manager's acquisition settings.
+///
+/// Unset fields fall back to the manager's configuration.
+#[derive(Debug, Clone, Default)]
+pub struct AcquireOptions {
+    /// Number of attempts per `lock` call, see `LockManager::set_retry`.
+    pub retry_count: Option<u32>,
+    /// Upper bound of the random delay between attempts, see `LockManager::set_retry`.
+    pub retry_delay: Option<Duration>,
+    /// Stop retrying once this much time has passed since the call.
+    /// An attempt that is in progress is never interrupted.
+    pub timeout: Option<Duration>,
+    /// Compute the validity from the start of the call instead of the successful attempt,
+    /// so that time spent on failed attempts counts against the TTL.
+    /// Always enabled if set for the manager with `LockManager::set_validity_from_call`.
+    pub validity_from_call: bool,
+}
+
+impl AcquireOptions {
+    pub fn new() -> AcquireOptions {
+        AcquireOptions::default()
+    }
+
+    /// Override retry count and retry delay.
+    pub fn retry(mut self, count: u32, delay: Duration) -> AcquireOptions {
+        self.retry_count = Some(count);
+        self.retry_delay = Some(delay);
+        self
+    }
+
+    /// Stop retrying after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> AcquireOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Bound the lock's validity relative to the start of the call, across retries.
+    ///
+    /// If the retries used up the whole TTL, the lock is released again and acquisition fails
+    /// with `LockError::TtlExceeded`.
+    pub fn validity_from_call(mut self, enabled: bool) -> AcquireOptions {
+        self.validity_from_call = enabled;
+        self
+    }
 }
 
 /// The lock manager.
@@ -58,9 +272,79 @@ pub struct LockManager {
     quorum: u32,
     retry_count: u32,
     retry_delay: Duration,
+    min_ttl: Duration,
+    max_ttl: Duration,
+    verify_after_lock: bool,
+    fencing: bool,
+    max_hold_times: Vec<(Vec<u8>, Duration)>,
+    lock_quotas: Vec<(Vec<u8>, usize)>,
+    event_handler: Option<EventHandler>,
+    max_in_flight: Option<usize>,
+    in_flight_limits: Option<Vec<Arc<Semaphore>>>,
+    token_pool: Option<Arc<TokenPool>>,
+    compatibility: Compatibility,
+    tracker: Arc<Tracker>,
+    renewals: Arc<Renewals>,
+    tasks: Arc<TaskSet>,
+    maintenance: Arc<AtomicBool>,
+    shadow_mode: bool,
+    max_clock_discrepancy: Option<Duration>,
+    require_noeviction: bool,
+    clock_drift_factor: f32,
+    clock_drift_padding: Duration,
+    round_budget: Option<Duration>,
+    round_concurrency: Option<usize>,
+    latency_ordering: bool,
+    restart_quarantine: Option<Duration>,
+    reset_on_restart: bool,
+    contention_threshold: Option<u32>,
+    retry_warmup: Option<(Duration, Duration)>,
+    quorum_margin: u32,
+    validity_from_call: bool,
+    renewal_interval: Option<Duration>,
+    registry: Option<Arc<ConnectionRegistry>>,
+    connect_strategy: ConnectStrategy,
+    // Whether `registry` was created for eager connections, not set by the user
+    own_registry: bool,
+    connection_settings: ConnectionSettings,
 }
 
-#[derive(Debug, Clone)]
+// Lock tokens generated in batches, shared by all clones of a manager
+#[derive(Debug)]
+struct TokenPool {
+    batch_size: usize,
+    tokens: Mutex<Vec<Vec<u8>>>,
+}
+
+impl TokenPool {
+    fn take(&self) -> Vec<u8> {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        if tokens.is_empty() {
+            let mut buf = vec![0u8; 20 * self.batch_size];
+            thread_rng().fill_bytes(&mut buf);
+            tokens.extend(buf.chunks_exact(20).map(<[u8]>::to_vec));
+        }
+        tokens.pop().expect("token pool was just refilled")
+    }
+}
+
+/// The parameters a lock was granted under, see `Lock::acquisition_details`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AcquisitionDetails {
+    /// The TTL requested.
+    pub ttl: Duration,
+    /// The clock drift allowance subtracted from the TTL.
+    pub drift: Duration,
+    /// Number of instances that had to agree.
+    pub quorum: u32,
+    /// Number of instances that agreed.
+    pub succeeded: u32,
+    /// Number of instances the manager talks to.
+    pub instances: u32,
+}
+
+#[derive(Clone)]
 pub struct Lock<'a> {
     /// The resource to lock. Will be used as the key in Redis.
     pub resource: Vec<u8>,
@@ -69,62 +353,581 @@ pub struct Lock<'a> {
     /// Time the lock is still valid.
     /// Should only be slightly smaller than the requested TTL.
     pub validity_time: usize,
+    /// Point in time at which `validity_time` was computed.
+    pub acquired_at: Instant,
+    /// Wall-clock time at which `validity_time` was computed, see `Lock::clock_discrepancy`.
+    pub acquired_at_wall: SystemTime,
+    /// Point in time at which the lock was first acquired. Kept across extensions.
+    pub held_since: Instant,
+    /// Number of instances the value was read back from right after acquisition.
+    /// `None` unless verification is enabled with `LockManager::set_verify_after_lock`.
+    pub verified_on: Option<u32>,
+    /// The fencing token handed out with the lock, kept across extensions.
+    /// `None` unless fencing is enabled with `LockManager::set_fencing`.
+    pub fencing_token: Option<u64>,
+    /// The parameters of the round that computed `validity_time`.
+    pub acquisition: AcquisitionDetails,
     /// Used to limit the lifetime of a lock to its lock manager.
     pub lock_manager: &'a LockManager,
 }
 
-/// Upon dropping the guard, `LockManager::unlock` will be ran synchronously on the executor.
+impl Lock<'_> {
+    /// Create a handle that can release this lock independently of the lock itself.
+    ///
+    /// The handle is `Send + 'static`, so it can be moved to a supervisor task that releases
+    /// the lock if the task holding it is cancelled. Releasing is idempotent: whoever releases
+    /// first frees the resource, later releases through the lock or the handle are no-ops.
+    /// The handle cannot release a lock that has since been acquired by someone else.
+    pub fn split_release_handle(&self) -> ReleaseHandle {
+        ReleaseHandle {
+            lock_manager: self.lock_manager.clone(),
+            resource: self.resource.clone(),
+            val: self.val.clone(),
+        }
+    }
+
+    /// Create a handle that can extend, but not release, this lock.
+    ///
+    /// Like `ReleaseHandle`, the handle is `Send + 'static`, so a supervisor task can keep
+    /// many locks alive while the tasks holding them keep the sole right to release them.
+    pub fn extension_handle(&self) -> ExtensionHandle {
+        ExtensionHandle {
+            lock_manager: self.lock_manager.clone(),
+            resource: self.resource.clone(),
+            val: self.val.clone(),
+            held_since: self.held_since,
+        }
+    }
+
+    /// Time left until the lock expires, as seen by the local clock.
+    ///
+    /// If clock checks are enabled with `LockManager::set_max_clock_discrepancy`,
+    /// the larger of the monotonic and the wall-clock elapsed time is used.
+    pub fn remaining_validity(&self) -> Duration {
+        let mut elapsed = rt::elapsed(self.acquired_at);
+        if self.lock_manager.max_clock_discrepancy.is_some() {
+            elapsed = elapsed.max(self.wall_clock_elapsed());
+        }
+        Duration::from_millis(self.validity_time as u64).saturating_sub(elapsed)
+    }
+
+    fn wall_clock_elapsed(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.acquired_at_wall)
+            .unwrap_or_default()
+    }
+
+    /// Difference between the time elapsed since acquisition according to the monotonic
+    /// and to the wall clock.
+    ///
+    /// The monotonic clock pauses while a VM is suspended or hibernated, while the wall clock
+    /// does not, so a large discrepancy hints that the lock may have expired unnoticed.
+    /// Wall-clock adjustments, e.g. by NTP, cause discrepancies as well.
+    pub fn clock_discrepancy(&self) -> Duration {
+        let monotonic = rt::elapsed(self.acquired_at);
+        let wall = self.wall_clock_elapsed();
+        monotonic.abs_diff(wall)
+    }
+
+    /// The TTL requested, the drift allowance applied and the quorum achieved by the round
+    /// that granted the lock, or that last extended it, for auditing under which parameters
+    /// exclusivity was granted.
+    pub fn acquisition_details(&self) -> AcquisitionDetails {
+        self.acquisition
+    }
+
+    /// Whether the clock discrepancy exceeds the limit set with
+    /// `LockManager::set_max_clock_discrepancy`. Suspect locks should not be relied upon.
+    pub fn is_suspect(&self) -> bool {
+        self.lock_manager
+            .max_clock_discrepancy
+            .is_some_and(|max| self.clock_discrepancy() > max)
+    }
+}
+
+/// Shows the resource as UTF-8 when possible, a short hex prefix of the token
+/// and the remaining validity, e.g. `mutex (token 9f86d081, 994ms left)`.
+impl fmt::Display for Lock<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (token {}, {}ms left)",
+            Bytes(&self.resource),
+            Hex(&self.val[..self.val.len().min(4)]),
+            self.remaining_validity().as_millis()
+        )
+    }
+}
+
+/// Omits the lock manager and renders the byte vectors readably,
+/// so that locks can be logged as is.
+impl fmt::Debug for Lock<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lock")
+            .field("resource", &format_args!("{}", Bytes(&self.resource)))
+            .field("val", &format_args!("{}", Hex(&self.val)))
+            .field("validity_time", &self.validity_time)
+            .field("remaining", &self.remaining_validity())
+            .finish()
+    }
+}
+
+// Formats bytes as UTF-8 if they are printable text, hex otherwise
+pub(crate) struct Bytes<'a>(pub(crate) &'a [u8]);
+
+impl fmt::Display for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match std::str::from_utf8(self.0) {
+            Ok(s) if !s.chars().any(char::is_control) => f.write_str(s),
+            _ => write!(f, "0x{}", Hex(self.0)),
+        }
+    }
+}
+
+struct Hex<'a>(&'a [u8]);
+
+// Inverted bounds would reject every TTL, see `LockManager::set_ttl_bounds`
+pub(crate) fn check_ttl_bounds(min: Duration, max: Duration) -> Result<(), String> {
+    if min > max {
+        return Err(format!(
+            "minimum TTL {:?} is greater than maximum TTL {:?}",
+            min, max
+        ));
+    }
+    Ok(())
+}
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+/// Upon dropping the guard, the lock is released with `LockManager::unlock`.
 ///
-/// This is known to block the tokio runtime if this happens inside of the context of a tokio runtime
-/// if `tokio-comp` is enabled as a feature on this crate or the `redis` crate.
+/// Within a tokio runtime the release runs in a spawned task, so the lock may still be held
+/// for a moment after the drop. Outside of one it runs synchronously, on a temporary
+/// runtime with `tokio-comp`. Call `LockManager::unlock` on the inner `lock` to know when,
+/// and whether, the lock was released.
 ///
-/// To eliminate this risk, if the `tokio-comp` flag is enabled, the `Drop` impl will not be compiled,
-/// meaning that dropping the `LockGuard` will be a no-op.
-/// Under this circumstance, `LockManager::unlock` can be called manually using the inner `lock` at the appropriate
-/// point to release the lock taken in `Redis`.
-#[derive(Debug, Clone)]
+/// The guard is not `Clone`, as each clone would release the lock when dropped.
+/// Clone the inner `lock` instead.
+#[derive(Debug)]
 pub struct LockGuard<'a> {
     pub lock: Lock<'a>,
 }
 
-/// Dropping this guard inside the context of a tokio runtime if `tokio-comp` is enabled
-/// will block the tokio runtime.
-/// Because of this, the guard is not compiled if `tokio-comp` is enabled.
-#[cfg(not(feature = "tokio-comp"))]
+impl fmt::Display for LockGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.lock.fmt(f)
+    }
+}
+
+impl<'a> LockGuard<'a> {
+    /// See `Lock::split_release_handle`.
+    pub fn split_release_handle(&self) -> ReleaseHandle {
+        self.lock.split_release_handle()
+    }
+
+    /// See `Lock::extension_handle`.
+    pub fn extension_handle(&self) -> ExtensionHandle {
+        self.lock.extension_handle()
+    }
+
+    /// Disarm the guard, returning the lock without releasing it.
+    pub fn into_lock(mut self) -> Lock<'a> {
+        Lock {
+            resource: std::mem::take(&mut self.lock.resource),
+            val: std::mem::take(&mut self.lock.val),
+            ..self.lock.clone()
+        }
+    }
+}
+
+/// A detached handle releasing a lock, created with `Lock::split_release_handle`.
+///
+/// It owns everything needed for the release, so unlike `Lock` it can be moved into spawned tasks.
+#[derive(Clone)]
+pub struct ReleaseHandle {
+    lock_manager: LockManager,
+    resource: Vec<u8>,
+    val: Vec<u8>,
+}
+
+impl ReleaseHandle {
+    /// The resource of the lock this handle releases.
+    pub fn resource(&self) -> &[u8] {
+        &self.resource
+    }
+
+    /// Release the lock. Like `LockManager::unlock`, this is best effort.
+    pub async fn release(&self) -> UnlockOutcome {
+        self.lock_manager.release_children(&self.val).await;
+        let results = self
+            .lock_manager
+            .unlock_key(&self.resource, &self.val)
+            .await;
+        self.lock_manager.tracker.released(&self.val);
+        core::unlock_outcome(&results, self.lock_manager.quorum)
+    }
+}
+
+impl fmt::Debug for ReleaseHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReleaseHandle")
+            .field("resource", &format_args!("{}", Bytes(&self.resource)))
+            .field("val", &format_args!("{}", Hex(&self.val)))
+            .finish()
+    }
+}
+
+// Releases a lock value when dropped unless disarmed, so that an acquisition cancelled
+// while its round is in flight doesn't leave the instances it reached locked until the
+// TTL expires
+pub(crate) struct CancelGuard<'a> {
+    lock_manager: &'a LockManager,
+    resource: &'a [u8],
+    val: &'a [u8],
+    armed: bool,
+}
+
+impl<'a> CancelGuard<'a> {
+    pub(crate) fn new(lock_manager: &'a LockManager, resource: &'a [u8], val: &'a [u8]) -> Self {
+        CancelGuard {
+            lock_manager,
+            resource,
+            val,
+            armed: true,
+        }
+    }
+
+    pub(crate) fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let release = ReleaseHandle {
+            lock_manager: self.lock_manager.clone(),
+            resource: self.resource.to_vec(),
+            val: self.val.to_vec(),
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move { release.release().await });
+            }
+            #[cfg(not(feature = "tokio-comp"))]
+            Err(_) => rt::spawn(async move {
+                release.release().await;
+            }),
+            #[cfg(feature = "tokio-comp")]
+            Err(_) => rt::block_on(release.release()),
+        }
+    }
+}
+
+/// A detached handle extending a lock, created with `Lock::extension_handle`.
+#[derive(Clone)]
+pub struct ExtensionHandle {
+    lock_manager: LockManager,
+    resource: Vec<u8>,
+    val: Vec<u8>,
+    held_since: Instant,
+}
+
+impl ExtensionHandle {
+    /// The resource of the lock this handle extends.
+    pub fn resource(&self) -> &[u8] {
+        &self.resource
+    }
+
+    /// Extend the lock to `ttl`, returning its new validity time. See `LockManager::extend`.
+    ///
+    /// The `Lock` the handle was created from is not updated, so its `remaining_validity`
+    /// does not account for extensions made through the handle.
+    pub async fn extend(&self, ttl: Duration) -> Result<Duration, LockError> {
+        let lock = Lock {
+            lock_manager: &self.lock_manager,
+            resource: self.resource.clone(),
+            val: self.val.clone(),
+            // Unknown to the handle, the instances tell whether the lock is still held
+            validity_time: usize::MAX,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: self.held_since,
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+        let extended = self.lock_manager.extend(&lock, ttl).await?;
+        Ok(Duration::from_millis(extended.validity_time as u64))
+    }
+}
+
+impl fmt::Debug for ExtensionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionHandle")
+            .field("resource", &format_args!("{}", Bytes(&self.resource)))
+            .field("val", &format_args!("{}", Hex(&self.val)))
+            .finish()
+    }
+}
+
+/// Dropping this guard within a tokio runtime releases the lock in a spawned task instead of
+/// blocking the worker thread. Without `tokio-comp`, where the release would otherwise run
+/// synchronously, `Event::BlockingDrop` is raised then.
 impl Drop for LockGuard<'_> {
     fn drop(&mut self) {
-        futures::executor::block_on(self.lock.lock_manager.unlock(&self.lock));
+        // Disarmed by `into_lock`
+        if self.lock.val.is_empty() {
+            return;
+        }
+        if !release_in_tokio(&[&self.lock]) {
+            rt::block_on(self.lock.lock_manager.unlock(&self.lock));
+        }
     }
 }
 
+// Releases the locks in a task if called within a tokio runtime, where blocking on the
+// release would stall the worker thread, or deadlock a single-threaded runtime.
+// Returns whether the locks were handed to a task.
+pub(crate) fn release_in_tokio(locks: &[&Lock<'_>]) -> bool {
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return false;
+    };
+    let releases: Vec<ReleaseHandle> = locks
+        .iter()
+        .map(|lock| {
+            // Expected with tokio-comp, a misconfiguration without it
+            #[cfg(not(feature = "tokio-comp"))]
+            lock.lock_manager.emit(Event::BlockingDrop {
+                resource: lock.resource.clone(),
+            });
+            lock.split_release_handle()
+        })
+        .collect();
+    handle.spawn(async move {
+        for release in releases {
+            release.release().await;
+        }
+    });
+    true
+}
+
 impl LockManager {
     /// Create a new lock manager instance, defined by the given Redis connection uris.
     /// Quorum is defined to be N/2+1, with N being the number of given Redis instances.
     ///
     /// Sample URI: `"redis://127.0.0.1:6379"`
+    ///
+    /// Panics if no URI is given or a URI is invalid, see `try_new` and `from_endpoints`.
     pub fn new<T: IntoConnectionInfo>(uris: Vec<T>) -> LockManager {
-        let quorum = (uris.len() as u32) / 2 + 1;
+        LockManager::try_new(uris).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `new`, but fails with `LockError::InvalidEndpoint` naming the position of the first
+    /// invalid URI, or with `LockError::NoServers` if no URI is given, instead of panicking.
+    ///
+    /// With fewer than three instances, no instance may fail without locking becoming
+    /// unavailable, and a single instance losing its keys breaks mutual exclusion,
+    /// see `fault_tolerance`.
+    pub fn try_new<T: IntoConnectionInfo>(uris: Vec<T>) -> Result<LockManager, LockError> {
+        if uris.is_empty() {
+            return Err(LockError::NoServers);
+        }
+        let quorum = core::quorum(uris.len());
 
-        let servers: Vec<Client> = uris
+        let servers = uris
             .into_iter()
-            .map(|uri| Client::open(uri).unwrap())
-            .collect();
+            .enumerate()
+            .map(|(index, uri)| {
+                Client::open(uri).map_err(|e| LockError::InvalidEndpoint {
+                    index,
+                    reason: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<Client>, LockError>>()?;
+
+        let lock_manager = LockManager::with_servers(servers, quorum);
+        #[cfg(feature = "tracing")]
+        if lock_manager.fault_tolerance() == 0 {
+            tracing::warn!(
+                instances = lock_manager.servers.len(),
+                "rslock manager cannot tolerate the failure of any instance"
+            );
+        }
+        Ok(lock_manager)
+    }
+
+    /// Create a lock manager from a comma-separated list of endpoints, e.g. read from
+    /// configuration or an environment variable.
+    ///
+    /// Entries are URIs like `redis://user:pass@[::1]:6379/2`, or `host[:port]` with the
+    /// `redis` scheme implied. IPv6 literals may be given without brackets if they have no port.
+    /// If only the first entry has a scheme, the entries form a host list sharing the scheme
+    /// and credentials of the first entry and the database and parameters of the last:
+    /// `redis://:pass@10.0.0.1,10.0.0.2:6380,[::1]/2`.
+    ///
+    /// For all schemes, the parameters `db`, `user` and `password` (or `pass`) override
+    /// the database and credentials of the URI. Other parameters are rejected.
+    ///
+    /// Fails with `LockError::InvalidEndpoint` naming the first invalid entry,
+    /// with its password masked.
+    pub fn from_endpoints(spec: &str) -> Result<LockManager, LockError> {
+        LockManager::try_new(endpoint::parse_endpoints(spec)?)
+    }
 
+    fn with_servers(servers: Vec<Client>, quorum: u32) -> LockManager {
         LockManager {
             servers,
             quorum,
             retry_count: DEFAULT_RETRY_COUNT,
             retry_delay: DEFAULT_RETRY_DELAY,
+            min_ttl: Duration::ZERO,
+            max_ttl: Duration::MAX,
+            verify_after_lock: false,
+            fencing: false,
+            max_hold_times: Vec::new(),
+            lock_quotas: Vec::new(),
+            event_handler: None,
+            max_in_flight: None,
+            in_flight_limits: None,
+            token_pool: None,
+            compatibility: Compatibility::Native,
+            tracker: Arc::new(Tracker::default()),
+            renewals: Arc::new(Renewals::default()),
+            tasks: Arc::new(TaskSet::default()),
+            maintenance: Arc::new(AtomicBool::new(false)),
+            shadow_mode: false,
+            max_clock_discrepancy: None,
+            require_noeviction: false,
+            clock_drift_factor: CLOCK_DRIFT_FACTOR,
+            clock_drift_padding: CLOCK_DRIFT_PADDING,
+            round_budget: None,
+            round_concurrency: None,
+            latency_ordering: false,
+            restart_quarantine: None,
+            reset_on_restart: false,
+            contention_threshold: None,
+            retry_warmup: None,
+            quorum_margin: 0,
+            validity_from_call: false,
+            renewal_interval: None,
+            registry: None,
+            connect_strategy: ConnectStrategy::Lazy,
+            own_registry: false,
+            connection_settings: ConnectionSettings::default(),
+        }
+    }
+
+    /// ACL rules for the commands the manager runs with its current settings, e.g. for
+    /// `ACL SETUSER locker on >password ~locks:* +set +get ...`.
+    ///
+    /// Scripts are run with `EVALSHA`, and the commands they call need permissions as well.
+    /// The list covers every operation, including `provision`, `check_restarts`,
+    /// `inspect_namespace` and `repair_namespace`, as well as the transactions used by
+    /// `set_scriptless` and naming connections with `set_client_name` when enabled.
+    /// Instances denying a command fail operations with `LockError::PermissionDenied`.
+    pub fn required_acl(&self) -> Vec<&'static str> {
+        let mut acl = transport::REQUIRED_ACL.to_vec();
+        if self.connection_settings.is_scriptless() {
+            acl.extend_from_slice(transport::SCRIPTLESS_ACL);
         }
+        if self.connection_settings.client_name.is_some() {
+            acl.push(transport::CLIENT_NAME_ACL);
+        }
+        acl
+    }
+
+    /// Number of instances that must agree for a lock to be acquired.
+    pub fn quorum(&self) -> u32 {
+        self.quorum
+    }
+
+    // Overrides the majority quorum, validated by `LockManagerBuilder::build`
+    pub(crate) fn set_quorum(&mut self, quorum: u32) {
+        self.quorum = quorum;
+    }
+
+    /// Require `margin` instances on top of the quorum to grant or extend a lock before it is
+    /// considered held, capped at the number of instances.
+    ///
+    /// Trades availability for safety: a lock then survives `margin` of its instances failing
+    /// or restarting without persistence, but fewer failed instances make locking unavailable,
+    /// see `fault_tolerance`. A held lock is only reported as lost, and releases only fail,
+    /// once it is below the bare quorum. Defaults to `0`.
+    pub fn set_quorum_margin(&mut self, margin: u32) {
+        self.quorum_margin = margin;
+    }
+
+    // Number of instances that must grant a lock, the quorum plus the margin
+    pub(crate) fn acquire_quorum(&self) -> u32 {
+        (self.quorum + self.quorum_margin).min(self.servers.len() as u32)
+    }
+
+    /// Number of instances that may fail while locks can still be acquired.
+    ///
+    /// Reduced by the quorum margin, see `set_quorum_margin`.
+    ///
+    /// `0` with one or two instances: then a single unreachable instance makes locking
+    /// unavailable, and a single instance restarting without persistence can grant a lock
+    /// twice. Redlock needs at least three instances to tolerate a failure.
+    pub fn fault_tolerance(&self) -> u32 {
+        (self.servers.len() as u32).saturating_sub(self.acquire_quorum())
+    }
+
+    pub(crate) fn retry_delay(&self) -> Duration {
+        self.retry_delay
+    }
+
+    #[cfg(feature = "humantime")]
+    pub(crate) fn retry_count(&self) -> u32 {
+        self.retry_count
+    }
+
+    #[cfg(feature = "humantime")]
+    pub(crate) fn ttl_bounds(&self) -> (Duration, Duration) {
+        (self.min_ttl, self.max_ttl)
     }
 
     /// Get 20 random bytes from the pseudorandom interface.
     pub fn get_unique_lock_id(&self) -> io::Result<Vec<u8>> {
-        || -> Result<Vec<u8>, io::Error> {
-            let mut buf = [0u8; 20];
-            thread_rng().fill_bytes(&mut buf);
-            Ok(buf.to_vec())
-        }()
+        let mut buf = [0u8; 20];
+        thread_rng().fill_bytes(&mut buf);
+        Ok(buf.to_vec())
+    }
+
+    /// Generate lock tokens `batch_size` at a time and hand them out from a pool.
+    ///
+    /// This takes token generation off the path of most `lock` calls.
+    /// A `batch_size` of `0` disables the pool, which is the default.
+    pub fn set_token_pool(&mut self, batch_size: usize) {
+        self.token_pool = (batch_size > 0).then(|| {
+            Arc::new(TokenPool {
+                batch_size,
+                tokens: Mutex::new(Vec::with_capacity(batch_size)),
+            })
+        });
+    }
+
+    /// Generate lock values following the conventions of another Redlock implementation.
+    ///
+    /// Defaults to `Compatibility::Native`.
+    pub fn set_compatibility(&mut self, compatibility: Compatibility) {
+        self.compatibility = compatibility;
+    }
+
+    // Takes a token from the pool if enabled
+    pub(crate) fn next_lock_id(&self) -> io::Result<Vec<u8>> {
+        let token = match &self.token_pool {
+            Some(pool) => pool.take(),
+            None => self.get_unique_lock_id()?,
+        };
+        Ok(self.compatibility.encode_token(token))
     }
 
     /// Set retry count and retry delay.
@@ -136,379 +939,2238 @@ impl LockManager {
         self.retry_delay = delay;
     }
 
-    async fn lock_instance(
-        client: &redis::Client,
-        resource: &[u8],
-        val: Vec<u8>,
-        ttl: usize,
-    ) -> bool {
-        let mut con = match client.get_async_connection().await {
-            Err(_) => return false,
-            Ok(val) => val,
-        };
-        let result: RedisResult<Value> = redis::cmd("SET")
-            .arg(resource)
-            .arg(val)
-            .arg("NX")
-            .arg("PX")
-            .arg(ttl)
-            .query_async(&mut con)
-            .await;
-
-        match result {
-            Ok(Okay) => true,
-            Ok(_) | Err(_) => false,
-        }
+    /// Retry quickly at first: during the first `window` of a `lock` call, attempts are
+    /// at most `delay` apart, instead of the retry delay, and don't count against the retry
+    /// count. Afterwards the retry count and delay set with `set_retry` apply.
+    ///
+    /// Most contended locks are released quickly, while some are held for long stretches.
+    /// A short warm-up with a small delay catches the former early, and a longer retry delay
+    /// keeps waiting for the latter cheap. The warm-up never makes retries slower,
+    /// and calls overriding the retry policy with `AcquireOptions::retry` skip it.
+    pub fn set_retry_warmup(&mut self, window: Duration, delay: Duration) {
+        self.retry_warmup = Some((window, delay));
     }
 
-    async fn extend_lock_instance(
-        client: &redis::Client,
-        resource: &[u8],
-        val: &[u8],
-        ttl: usize,
-    ) -> bool {
-        let mut con = match client.get_async_connection().await {
-            Err(_) => return false,
-            Ok(val) => val,
-        };
-        let script = redis::Script::new(EXTEND_SCRIPT);
-        let result: RedisResult<i32> = script
-            .key(resource)
-            .arg(val)
-            .arg(ttl)
-            .invoke_async(&mut con)
-            .await;
-        match result {
-            Ok(val) => val == 1,
-            Err(_) => false,
+    /// Set the minimum and maximum TTL accepted by `lock` and `extend`.
+    ///
+    /// Requests outside of `min..=max` fail with `LockError::TtlOutOfRange`.
+    /// By default any TTL is accepted.
+    ///
+    /// Panics if `min` is greater than `max`, which would reject every TTL.
+    /// `LockManagerBuilder::build` and `configure` fail with `LockError::InvalidSetting` instead.
+    pub fn set_ttl_bounds(&mut self, min: Duration, max: Duration) {
+        if let Err(reason) = check_ttl_bounds(min, max) {
+            panic!("Invalid TTL bounds: {}", reason);
         }
+        self.min_ttl = min;
+        self.max_ttl = max;
     }
 
-    async fn unlock_instance(client: &redis::Client, resource: &[u8], val: &[u8]) -> bool {
-        let mut con = match client.get_async_connection().await {
-            Err(_) => return false,
-            Ok(val) => val,
-        };
-        let script = redis::Script::new(UNLOCK_SCRIPT);
-        let result: RedisResult<i32> = script.key(resource).arg(val).invoke_async(&mut con).await;
-        match result {
-            Ok(val) => val == 1,
-            Err(_) => false,
-        }
+    /// Read the value back from all instances after each successful `lock`.
+    ///
+    /// The number of instances holding the value is stored in `Lock::verified_on`.
+    /// This costs one extra round-trip per acquisition and is disabled by default.
+    pub fn set_verify_after_lock(&mut self, verify: bool) {
+        self.verify_after_lock = verify;
     }
 
-    // Can be used for creating or extending a lock
-    async fn exec_or_retry<'a, T, Fut>(
-        &'a self,
-        resource: &[u8],
-        value: &[u8],
-        ttl: usize,
-        lock: T,
-    ) -> Result<Lock<'a>, LockError>
-    where
-        T: Fn(&'a Client) -> Fut,
-        Fut: Future<Output = bool>,
-    {
-        for _ in 0..self.retry_count {
-            let start_time = Instant::now();
-            let n = join_all(self.servers.iter().map(&lock))
-                .await
-                .into_iter()
-                .fold(0, |count, locked| if locked { count + 1 } else { count });
-
-            let drift = (ttl as f32 * CLOCK_DRIFT_FACTOR) as usize + 2;
-            let elapsed = start_time.elapsed();
-            let elapsed_ms =
-                elapsed.as_secs() as usize * 1000 + elapsed.subsec_nanos() as usize / 1_000_000;
-            if ttl <= drift + elapsed_ms {
-                return Err(LockError::TtlExceeded);
-            }
-            let validity_time = ttl
-                - drift
-                - elapsed.as_secs() as usize * 1000
-                - elapsed.subsec_nanos() as usize / 1_000_000;
-
-            if n >= self.quorum && validity_time > 0 {
-                return Ok(Lock {
-                    lock_manager: self,
-                    resource: resource.to_vec(),
-                    val: value.to_vec(),
-                    validity_time,
-                });
-            } else {
-                join_all(
-                    self.servers
-                        .iter()
-                        .map(|client| Self::unlock_instance(client, resource, value)),
-                )
-                .await;
-            }
-
-            let retry_delay: u64 = self
-                .retry_delay
-                .as_millis()
-                .try_into()
-                .map_err(|_| LockError::TtlTooLarge)?;
-            let n = thread_rng().gen_range(0..retry_delay);
-            tokio::time::sleep(Duration::from_millis(n)).await
-        }
-
-        Err(LockError::Unavailable)
+    /// Hand out a fencing token with every lock acquired by `lock` and the calls built on it,
+    /// stored in `Lock::fencing_token`.
+    ///
+    /// Each instance keeps a counter per resource, which is incremented when it grants the lock.
+    /// The token is the highest counter of the acquisition, and the counters are raised to it
+    /// afterwards. As any two quorums share an instance, holders acquiring the lock later get
+    /// higher tokens, so storage that remembers the highest token it has seen can reject
+    /// writes from holders whose lock has expired meanwhile. Counters that could not be raised,
+    /// e.g. on unreachable instances, may let a later holder get a lower token, which such
+    /// storage rejects as well.
+    ///
+    /// The counters are stored next to the resources, as `<resource>:fencing`, and don't expire.
+    /// Costs one extra round-trip per acquisition and is disabled by default.
+    pub fn set_fencing(&mut self, fencing: bool) {
+        self.fencing = fencing;
     }
 
-    /// Unlock the given lock.
+    /// Limit the number of commands in flight to each Redis instance at the same time.
     ///
-    /// Unlock is best effort. It will simply try to contact all instances
-    /// and remove the key.
-    pub async fn unlock(&self, lock: &Lock<'_>) {
-        join_all(
+    /// Operations beyond the limit wait for a free slot before connecting,
+    /// so large numbers of concurrent lock attempts don't open as many sockets to every server.
+    /// A `max` of `0` removes the limit, which is the default.
+    pub fn set_max_in_flight(&mut self, max: usize) {
+        self.max_in_flight = (max > 0).then_some(max);
+        self.in_flight_limits = self.max_in_flight.map(|max| {
             self.servers
                 .iter()
-                .map(|client| Self::unlock_instance(client, &lock.resource, &lock.val)),
-        )
-        .await;
+                .map(|_| Arc::new(Semaphore::new(max)))
+                .collect()
+        });
     }
 
-    /// Acquire the lock for the given resource and the requested TTL.
+    /// Set a handler receiving the manager's `Event`s, e.g. to log them or record metrics.
+    pub fn set_event_handler(&mut self, handler: impl Fn(&Event) + Send + Sync + 'static) {
+        self.event_handler = Some(EventHandler::new(handler));
+    }
+
+    /// Set the expected maximum hold duration for resources starting with `prefix`.
     ///
-    /// If it succeeds, a `Lock` instance is returned,
-    /// including the value and the validity time
+    /// Locks held for longer, extensions included, raise `Event::HoldTimeExceeded`
+    /// whenever they are extended or released. The longest matching prefix applies;
+    /// an empty prefix matches every resource.
+    pub fn set_max_hold_time(&mut self, prefix: &[u8], max: Duration) {
+        self.max_hold_times.retain(|(p, _)| p != prefix);
+        self.max_hold_times.push((prefix.to_vec(), max));
+    }
+
+    /// Limit the number of locks on resources starting with `prefix` held at the same time
+    /// through the manager and its clones. An empty prefix limits all locks.
     ///
-    /// If it fails. `None` is returned.
-    /// A user should retry after a short wait time.
+    /// Acquisitions that would exceed any matching quota fail with `LockError::QuotaExceeded`
+    /// without contacting the instances, protecting them and the application from code paths
+    /// that acquire locks without bound. Locks count until they are released or expire.
+    /// The quota is checked before acquiring, so concurrent acquisitions may briefly exceed it.
+    pub fn set_lock_quota(&mut self, prefix: &[u8], max: usize) {
+        self.lock_quotas.retain(|(p, _)| p != prefix);
+        self.lock_quotas.push((prefix.to_vec(), max));
+    }
+
+    pub(crate) fn check_quota(&self, resource: &[u8]) -> Result<(), LockError> {
+        for (prefix, limit) in &self.lock_quotas {
+            if resource.starts_with(prefix) && self.tracker.held(prefix) >= *limit {
+                return Err(LockError::QuotaExceeded {
+                    prefix: Bytes(prefix).to_string(),
+                    limit: *limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Cross-check lock validity against the wall clock.
     ///
-    /// May return `LockError::TtlTooLarge` if `ttl` is too large.
-    pub async fn lock<'a>(&'a self, resource: &[u8], ttl: Duration) -> Result<Lock<'a>, LockError> {
-        let val = self.get_unique_lock_id().map_err(LockError::Io)?;
-        let ttl = ttl
-            .as_millis()
-            .try_into()
-            .map_err(|_| LockError::TtlTooLarge)?;
+    /// Locks whose monotonic and wall-clock elapsed times differ by more than `max`
+    /// are considered suspect (`Lock::is_suspect`) and raise `Event::ClockDiscrepancy`
+    /// when extended or released. Remaining validity is then computed from whichever
+    /// clock advanced more. Disabled by default.
+    pub fn set_max_clock_discrepancy(&mut self, max: Duration) {
+        self.max_clock_discrepancy = Some(max);
+    }
 
-        self.exec_or_retry(resource, &val.clone(), ttl, move |client| {
-            Self::lock_instance(client, resource, val.clone(), ttl)
-        })
-        .await
+    /// Set the allowance for clock drift between this process and the Redis instances.
+    ///
+    /// The validity of a lock is its TTL minus the time the attempt took, minus
+    /// `ttl * factor + padding`. Factor defaults to `0.01`, padding to `2ms`.
+    /// Deployments with high or jittery latency may need a larger allowance.
+    pub fn set_clock_drift(&mut self, factor: f32, padding: Duration) {
+        self.clock_drift_factor = factor;
+        self.clock_drift_padding = padding;
     }
 
-    /// Loops until the lock is acquired.
+    /// The allowance for clock drift made for a lock of `ttl`, see `set_clock_drift`.
+    pub fn clock_drift(&self, ttl: Duration) -> Duration {
+        core::clock_drift(ttl, self.clock_drift_factor, self.clock_drift_padding)
+    }
+
+    /// Consider an attempt failed if it takes longer than `budget`, even if a quorum was reached.
     ///
-    /// The lock is placed in a guard that will unlock the lock when the guard is dropped.
+    /// A slow attempt leaves little validity and hints at an overloaded instance or network,
+    /// so it may be preferable to retry. The attempt is undone as if no quorum was reached.
+    /// By default attempts are accepted as long as some validity is left.
+    pub fn set_round_budget(&mut self, budget: Duration) {
+        self.round_budget = Some(budget);
+    }
+
+    /// Contact at most `limit` instances at a time within one round of operations.
     ///
-    /// May return `LockError::TtlTooLarge` if `ttl` is too large.
-    #[cfg(feature = "async-std-comp")]
-    pub async fn acquire<'a>(
-        &'a self,
-        resource: &[u8],
-        ttl: Duration,
-    ) -> Result<LockGuard<'a>, LockError> {
-        let lock = self.acquire_no_guard(resource, ttl).await?;
-        Ok(LockGuard { lock })
+    /// With many instances, driving all of their requests at once can monopolize a busy
+    /// executor. With a limit, instances are contacted in chunks of `limit`, yielding to
+    /// the executor between chunks. This makes rounds take longer, which shortens the
+    /// validity of acquired locks. Unlimited by default.
+    pub fn set_round_concurrency(&mut self, limit: usize) {
+        self.round_concurrency = Some(limit.max(1));
     }
 
-    /// Loops until the lock is acquired.
+    /// Contact the instances in the order of their average response times, fastest first.
     ///
-    /// Either lock's value must expire after the ttl has elapsed,
-    /// or `LockManager::unlock` must be called to allow other clients to lock the same resource.
+    /// The manager keeps a moving average of how long operations on each instance take.
+    /// With a round concurrency limit, ordering by it lets the first chunks of a round
+    /// reach the fastest instances, so a slow instance delays the round as little as possible.
+    /// Without a limit all instances are contacted at once and the order makes no difference.
+    /// Disabled by default, so instances are contacted in the order they were configured.
+    pub fn set_latency_ordering(&mut self, enabled: bool) {
+        self.latency_ordering = enabled;
+    }
+
+    /// Exclude an instance from quorums for `window` after `check_restarts` found it restarted.
     ///
-    /// May return `LockError::TtlTooLarge` if `ttl` is too large.
-    pub async fn acquire_no_guard<'a>(
-        &'a self,
-        resource: &[u8],
-        ttl: Duration,
-    ) -> Result<Lock<'a>, LockError> {
-        loop {
-            match self.lock(resource, ttl).await {
-                Ok(lock) => return Ok(lock),
-                Err(LockError::TtlTooLarge) => return Err(LockError::TtlTooLarge),
-                Err(_) => continue,
+    /// An instance running without persistence forgets its locks when it restarts,
+    /// so another client could be granted a lock that is still held. Redlock's mitigation is
+    /// to keep restarted instances unavailable for at least the longest TTL in use. This does
+    /// it on the client side: pass a window at least as long as the longest TTL. Disabled by
+    /// default, restarts are then only reported.
+    pub fn set_restart_quarantine(&mut self, window: Duration) {
+        self.restart_quarantine = Some(window);
+    }
+
+    pub(crate) fn restart_quarantine(&self) -> Option<Duration> {
+        self.restart_quarantine
+    }
+
+    /// Discard what is known about an instance when `check_restarts` finds that it restarted:
+    /// its shared connection, see `set_connection_registry`, and its latency statistics,
+    /// see `set_latency_ordering`. Disabled by default.
+    ///
+    /// Scripts need no invalidation, they are loaded again when an instance does not know them.
+    pub fn set_reset_on_restart(&mut self, enabled: bool) {
+        self.reset_on_restart = enabled;
+    }
+
+    pub(crate) fn reset_on_restart(&self) -> bool {
+        self.reset_on_restart
+    }
+
+    /// Acquire locks in shadow mode, to validate sizing and configuration before enforcing locks.
+    ///
+    /// In shadow mode, `lock` and the functions built on it run the full acquisition, release
+    /// the lock right away and report the outcome as `Event::ShadowAcquisition`. They succeed
+    /// even if the lock could not be acquired because the resource is held or the instances
+    /// are unavailable, so callers proceed as if locks were not used, and `extend` succeeds
+    /// without contacting the instances. Other errors, like `LockError::QuotaExceeded`,
+    /// `LockError::PermissionDenied` or `LockError::MaintenanceMode`, are returned as usual.
+    /// Disabled by default.
+    pub fn set_shadow_mode(&mut self, enabled: bool) {
+        self.shadow_mode = enabled;
+    }
+
+    /// Enter or leave maintenance mode, e.g. for a controlled maintenance window of the instances.
+    ///
+    /// In maintenance mode, new acquisitions fail fast with `LockError::MaintenanceMode`
+    /// without contacting any instance, while held locks can still be extended and released.
+    /// Unlike most other settings, this can be changed at runtime through a shared reference,
+    /// and applies to the manager and all its clones, like `set_scriptless`.
+    pub fn set_maintenance_mode(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::Release);
+    }
+
+    /// Whether the manager is in maintenance mode, see `set_maintenance_mode`.
+    pub fn is_in_maintenance_mode(&self) -> bool {
+        self.maintenance.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn check_maintenance_mode(&self) -> Result<(), LockError> {
+        if self.is_in_maintenance_mode() {
+            Err(LockError::MaintenanceMode)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Number of contended attempts after which `with_lock_or_optimistic` gives up on the lock
+    /// and takes the optimistic path. Defaults to the retry count.
+    pub fn set_contention_threshold(&mut self, attempts: u32) {
+        self.contention_threshold = Some(attempts.max(1));
+    }
+
+    pub(crate) fn contention_threshold(&self) -> u32 {
+        self.contention_threshold.unwrap_or(self.retry_count)
+    }
+
+    /// Bound the validity of every acquired lock relative to the start of the `lock` call,
+    /// subtracting the time spent on failed attempts, as `AcquireOptions::validity_from_call`
+    /// does for a single call. For callers that treat the TTL as a bound from the moment they
+    /// asked for the lock. Disabled by default, extensions are not affected.
+    pub fn set_validity_from_call(&mut self, enabled: bool) {
+        self.validity_from_call = enabled;
+    }
+
+    /// Renew locks kept alive with `keep_alive` or `LockGuard::into_auto_extended` every
+    /// `interval` instead of once half of their validity has passed. Renewals never wait
+    /// longer than half of the validity, so a longer interval has no effect.
+    pub fn set_renewal_interval(&mut self, interval: Duration) {
+        self.renewal_interval = Some(interval);
+    }
+
+    // When a lock renewed at `start` with the given validity is due again
+    pub(crate) fn renewal_due(&self, start: Instant, validity: Duration) -> Instant {
+        let interval = self
+            .renewal_interval
+            .map_or(validity / 2, |interval| interval.min(validity / 2));
+        start + interval
+    }
+
+    /// Share multiplexed connections with the other managers using `registry`,
+    /// e.g. `ConnectionRegistry::global()`, instead of opening a connection per operation.
+    pub fn set_connection_registry(&mut self, registry: Arc<ConnectionRegistry>) {
+        self.registry = Some(registry);
+        self.own_registry = false;
+    }
+
+    /// Choose whether connections are established lazily, on first use, or eagerly.
+    ///
+    /// With `ConnectStrategy::Eager`, connections are kept open in the connection registry,
+    /// or in a registry of the manager and its clones if none is set, and `warm_up` opens them
+    /// ahead of the first lock call, whose TTL would otherwise absorb the connection setup.
+    /// Defaults to `ConnectStrategy::Lazy`.
+    pub fn set_connect_strategy(&mut self, strategy: ConnectStrategy) {
+        self.connect_strategy = strategy;
+        match strategy {
+            ConnectStrategy::Eager if self.registry.is_none() => {
+                self.registry = Some(Arc::new(ConnectionRegistry::new()));
+                self.own_registry = true;
+            }
+            ConnectStrategy::Lazy if self.own_registry => {
+                self.registry = None;
+                self.own_registry = false;
             }
+            _ => (),
         }
     }
 
-    /// Extend the given lock by given time in milliseconds
-    pub async fn extend<'a>(
-        &'a self,
-        lock: &Lock<'a>,
-        ttl: Duration,
-    ) -> Result<Lock<'a>, LockError> {
-        let ttl = ttl
-            .as_millis()
-            .try_into()
-            .map_err(|_| LockError::TtlTooLarge)?;
+    /// Connect to all instances in parallel, resolving once every attempt has finished.
+    ///
+    /// With `ConnectStrategy::Eager`, the connections stay open for later operations. Otherwise
+    /// this only checks that the instances can be reached. Also records the instances' run ids,
+    /// see `check_restarts`. Returns the number of instances connected, or fails with
+    /// `LockError::Unavailable` if fewer than a quorum could be.
+    pub async fn warm_up(&self) -> Result<u32, LockError> {
+        let connected = self
+            .on_all(|instance| async move { instance.connect().await.map(|_| ()) })
+            .await
+            .iter()
+            .filter(|result| result.is_ok())
+            .count() as u32;
+        if connected < self.acquire_quorum() {
+            return Err(LockError::Unavailable);
+        }
+        self.check_restarts().await;
+        Ok(connected)
+    }
 
-        self.exec_or_retry(&lock.resource, &lock.val, ttl, move |client| {
-            Self::extend_lock_instance(client, &lock.resource, &lock.val, ttl)
-        })
-        .await
+    /// Name connections with `CLIENT SETNAME`, so that they can be told apart in `CLIENT LIST`.
+    ///
+    /// Whitespace, which Redis does not allow in names, is replaced by `-`.
+    pub fn set_client_name(&mut self, name: &str) {
+        let name = name.replace(char::is_whitespace, "-");
+        self.connection_settings.client_name = Some(name);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use anyhow::Result;
-    use once_cell::sync::Lazy;
-    use testcontainers::clients::Cli;
-    use testcontainers::images::redis::Redis;
-    use testcontainers::{Container, RunnableImage};
+    /// Unlock and extend locks with `WATCH`, `GET` and a `MULTI`/`EXEC` transaction instead of
+    /// Lua scripts, for instances whose ACL denies `EVALSHA` but allows transactions.
+    ///
+    /// The transaction is aborted if the lock key changes after it was checked, and is then
+    /// retried. It needs a connection of its own, so shared connections are not used for it.
+    /// `provision` switches this on when an instance denies loading the scripts. Other features
+    /// built on scripts, like protected values, remain unavailable then. Disabled by default.
+    ///
+    /// Like maintenance mode, this can be changed at runtime through a shared reference,
+    /// and applies to the manager and all its clones.
+    pub fn set_scriptless(&self, enabled: bool) {
+        self.connection_settings
+            .scriptless
+            .store(enabled, Ordering::Relaxed);
+    }
 
-    use super::*;
+    /// Fail commands and connection attempts that take longer than `timeout` to complete,
+    /// instead of waiting for the operating system to give up on an unresponsive instance.
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.connection_settings.response_timeout = Some(timeout);
+    }
 
-    type Containers = Vec<Container<'static, Redis>>;
+    pub(crate) fn tracker(&self) -> &Tracker {
+        &self.tracker
+    }
 
-    static DOCKER: Lazy<Cli> = Lazy::new(Cli::docker);
+    pub(crate) fn renewals(&self) -> &Renewals {
+        &self.renewals
+    }
 
-    fn is_normal<T: Sized + Send + Sync + Unpin>() {}
+    /// The background tasks spawned by the manager and its clones.
+    pub fn tasks(&self) -> &TaskSet {
+        &self.tasks
+    }
 
-    fn create_clients() -> (Containers, Vec<String>) {
-        let containers: Containers = (1..=3)
-            .map(|_| {
-                let image = RunnableImage::from(Redis::default()).with_tag("7-alpine");
-                DOCKER.run(image)
-            })
-            .collect();
+    pub(crate) fn tasks_arc(&self) -> &Arc<TaskSet> {
+        &self.tasks
+    }
 
-        let addresses = containers
+    pub(crate) fn instance(&self, i: usize) -> Instance<'_> {
+        Instance {
+            client: &self.servers[i],
+            registry: self.registry.as_deref(),
+            settings: &self.connection_settings,
+        }
+    }
+
+    /// Treat instances whose `maxmemory-policy` may evict lock keys as unusable in `provision`.
+    ///
+    /// Evicted lock keys silently break mutual exclusion.
+    /// By default such instances only raise `Event::EvictionPolicy`.
+    pub fn set_require_noeviction(&mut self, require: bool) {
+        self.require_noeviction = require;
+    }
+
+    pub(crate) fn requires_noeviction(&self) -> bool {
+        self.require_noeviction
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(?event, "rslock event");
+        if let Some(handler) = &self.event_handler {
+            handler.emit(event);
+        }
+    }
+
+    fn check_leaks(&self) {
+        #[cfg(feature = "leak-detection")]
+        for event in self.tracker.leaked() {
+            self.emit(event);
+        }
+    }
+
+    fn check_clock(&self, lock: &Lock<'_>) {
+        if lock.is_suspect() {
+            self.emit(Event::ClockDiscrepancy {
+                resource: lock.resource.clone(),
+                discrepancy: lock.clock_discrepancy(),
+            });
+        }
+    }
+
+    fn check_hold_time(&self, lock: &Lock<'_>) {
+        let limit = self
+            .max_hold_times
             .iter()
-            .map(|node| format!("redis://localhost:{}", node.get_host_port_ipv4(6379)))
+            .filter(|(prefix, _)| lock.resource.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, max)| *max);
+
+        if let Some(limit) = limit {
+            let held = rt::elapsed(lock.held_since);
+            if held > limit {
+                self.emit(Event::HoldTimeExceeded {
+                    resource: lock.resource.clone(),
+                    held,
+                    limit,
+                });
+            }
+        }
+    }
+
+    // Checks the TTL against the configured bounds and converts it to milliseconds
+    pub(crate) fn ttl_millis(&self, ttl: Duration) -> Result<usize, LockError> {
+        if ttl < self.min_ttl || ttl > self.max_ttl {
+            return Err(LockError::TtlOutOfRange);
+        }
+
+        ttl.as_millis()
+            .try_into()
+            .map_err(|_| LockError::TtlTooLarge)
+    }
+
+    // Estimates when enough instances are free again to reach a quorum,
+    // based on the remaining TTLs of the keys held by others
+    async fn retry_after(&self, resource: &[u8]) -> Option<Duration> {
+        let free_in = self
+            .on_all(|instance| transport::pttl_instance(instance, resource))
+            .await
+            .into_iter()
+            .filter_map(|pttl| pttl.ok().flatten());
+        core::retry_after(free_in, self.acquire_quorum())
+    }
+
+    // Runs `op` concurrently against every instance, respecting the in-flight limits
+    // and the round concurrency
+    pub(crate) async fn on_all<'a, T, Fut, R>(&'a self, op: T) -> Vec<R>
+    where
+        T: Fn(Instance<'a>) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let instances: Vec<usize> = (0..self.servers.len()).collect();
+        self.on_instances(&instances, op).await
+    }
+
+    // Like `on_all`, but only against the given instances. The indices must be in range.
+    pub(crate) async fn on_instances<'a, T, Fut, R>(&'a self, instances: &[usize], op: T) -> Vec<R>
+    where
+        T: Fn(Instance<'a>) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let op = &op;
+        let run = |pos: usize| async move {
+            let i = instances[pos];
+            let limit = self
+                .in_flight_limits
+                .as_ref()
+                .and_then(|limits| limits.get(i));
+            let _permit = match limit {
+                Some(semaphore) => semaphore.acquire().await.ok(),
+                None => None,
+            };
+            let start = rt::now();
+            let result = op(self.instance(i)).await;
+            self.tracker.record_latency(i, rt::elapsed(start));
+            (pos, result)
+        };
+
+        let order = if self.latency_ordering {
+            self.tracker.latency_order(instances)
+        } else {
+            (0..instances.len()).collect()
+        };
+        let chunk_size = self.round_concurrency.unwrap_or(order.len()).max(1);
+        let mut results: Vec<Option<R>> = (0..order.len()).map(|_| None).collect();
+        for (n, chunk) in order.chunks(chunk_size).enumerate() {
+            if n > 0 {
+                rt::yield_now().await;
+            }
+            for (pos, result) in join_all(chunk.iter().copied().map(run)).await {
+                results[pos] = Some(result);
+            }
+        }
+        // Results are returned in the order of the instances, whatever order they were contacted in
+        results
+            .into_iter()
+            .map(|result| result.expect("every instance was contacted"))
+            .collect()
+    }
+
+    // A single attempt of `exec_or_retry`.
+    //
+    // If no quorum is reached, the operation is undone on all instances
+    // and the per-instance results are returned.
+    async fn exec_once<'a, T, Fut>(
+        &'a self,
+        resource: &[u8],
+        value: &[u8],
+        ttl: usize,
+        lock: &T,
+    ) -> Result<Result<Lock<'a>, Tally>, LockError>
+    where
+        T: Fn(Instance<'a>) -> Fut,
+        Fut: Future<Output = RedisResult<bool>>,
+    {
+        let start_time = rt::now();
+        let mut results = self.on_all(lock).await;
+        // Instances that restarted recently may have forgotten locks, so they don't count
+        for (i, result) in results.iter_mut().enumerate() {
+            if self.tracker.is_quarantined(i) {
+                *result = Err(transport::quarantined());
+            }
+        }
+        let denied: Vec<String> = results
+            .iter()
+            .filter_map(|result| result.as_ref().err().and_then(transport::denied_command))
             .collect();
+        let tally = Tally::count(results);
 
-        (containers, addresses)
+        let ttl = Duration::from_millis(ttl as u64);
+        let drift = self.clock_drift(ttl);
+        let elapsed = rt::elapsed(start_time);
+        let Some(validity) = core::validity(ttl, elapsed, drift) else {
+            self.on_all(|instance| transport::unlock_instance(instance, resource, value))
+                .await;
+            return Err(LockError::TtlExceeded);
+        };
+        let validity_time = validity.as_millis() as usize;
+
+        let within_budget = self.round_budget.is_none_or(|budget| elapsed <= budget);
+
+        if tally.has_quorum(self.acquire_quorum()) && validity_time > 0 && within_budget {
+            Ok(Ok(Lock {
+                lock_manager: self,
+                resource: resource.to_vec(),
+                val: value.to_vec(),
+                validity_time,
+                acquired_at: rt::now(),
+                acquired_at_wall: SystemTime::now(),
+                held_since: start_time,
+                verified_on: None,
+                fencing_token: None,
+                acquisition: AcquisitionDetails {
+                    ttl,
+                    drift,
+                    quorum: self.acquire_quorum(),
+                    succeeded: tally.succeeded,
+                    instances: self.servers.len() as u32,
+                },
+            }))
+        } else {
+            self.on_all(|instance| transport::unlock_instance(instance, resource, value))
+                .await;
+            // Without permissions on enough instances, retrying cannot reach a quorum
+            if !denied.is_empty() && denied.len() as u32 + self.acquire_quorum() > tally.total() {
+                return Err(LockError::PermissionDenied {
+                    command: denied[0].clone(),
+                });
+            }
+            Ok(Err(tally))
+        }
     }
 
-    // Test that the LockManager is Send + Sync
-    #[test]
-    fn test_is_normal() {
-        is_normal::<LockManager>();
-        is_normal::<LockError>();
-        is_normal::<Lock>();
-        is_normal::<LockGuard>();
+    // A random delay of up to `retry_delay` before the next attempt
+    pub(crate) fn retry_jitter(retry_delay: Duration) -> Result<Duration, LockError> {
+        u64::try_from(retry_delay.as_millis()).map_err(|_| LockError::TtlTooLarge)?;
+        Ok(core::jitter(retry_delay, &mut thread_rng()))
     }
 
-    #[tokio::test]
-    async fn test_lock_get_unique_id() -> Result<()> {
-        let rl = LockManager::new(Vec::<String>::new());
-        assert_eq!(rl.get_unique_lock_id()?.len(), 20);
+    // Can be used for creating or extending a lock.
+    //
+    // When extending, instances that reject the operation no longer hold our value,
+    // so once too many of them do the lock is reported as lost instead of retried.
+    async fn exec_or_retry<'a, T, Fut>(
+        &'a self,
+        resource: &[u8],
+        value: &[u8],
+        ttl: usize,
+        extending: bool,
+        options: &AcquireOptions,
+        lock: T,
+    ) -> Result<Lock<'a>, LockError>
+    where
+        T: Fn(Instance<'a>) -> Fut,
+        Fut: Future<Output = RedisResult<bool>>,
+    {
+        let retry_count = options.retry_count.unwrap_or(self.retry_count);
+        let retry_delay = options.retry_delay.unwrap_or(self.retry_delay);
+        let called_at = rt::now();
+        // Timeouts too long to be represented don't set a deadline
+        let deadline = options
+            .timeout
+            .and_then(|timeout| called_at.checked_add(timeout));
+        let validity_from_call =
+            options.validity_from_call || (self.validity_from_call && !extending);
+        // Overriding the retry policy for a call overrides the warm-up as well
+        let warmup = self.retry_warmup.filter(|_| {
+            !extending && options.retry_count.is_none() && options.retry_delay.is_none()
+        });
+
+        let mut attempts = 0;
+        while attempts < retry_count {
+            match self.exec_once(resource, value, ttl, &lock).await? {
+                Ok(lock) if validity_from_call => {
+                    return self.shift_validity(lock, called_at).await
+                }
+                Ok(lock) => return Ok(lock),
+                Err(tally) => {
+                    if extending && tally.is_lost(self.quorum) {
+                        return Err(LockError::LockLost);
+                    }
+                }
+            }
 
-        Ok(())
+            // Attempts during the warm-up don't count against the retry count
+            let mut delay = match warmup {
+                Some((window, delay)) if rt::elapsed(called_at) < window => {
+                    Self::retry_jitter(delay.min(retry_delay))?
+                }
+                _ => {
+                    attempts += 1;
+                    Self::retry_jitter(retry_delay)?
+                }
+            };
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(rt::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                delay = delay.min(remaining);
+            }
+            rt::sleep(delay).await
+        }
+
+        Err(LockError::Unavailable)
     }
 
-    #[tokio::test]
-    async fn test_lock_get_unique_id_uniqueness() -> Result<()> {
-        let rl = LockManager::new(Vec::<String>::new());
+    // Reduces the validity of a lock by the time that passed between `since` and its attempt
+    async fn shift_validity<'a>(
+        &'a self,
+        mut lock: Lock<'a>,
+        since: Instant,
+    ) -> Result<Lock<'a>, LockError> {
+        let retried = lock.held_since.saturating_duration_since(since).as_millis() as usize;
+        match lock.validity_time.checked_sub(retried) {
+            Some(validity_time) if validity_time > 0 => {
+                lock.validity_time = validity_time;
+                Ok(lock)
+            }
+            _ => {
+                self.on_all(|instance| {
+                    transport::unlock_instance(instance, &lock.resource, &lock.val)
+                })
+                .await;
+                Err(LockError::TtlExceeded)
+            }
+        }
+    }
 
-        let id1 = rl.get_unique_lock_id()?;
-        let id2 = rl.get_unique_lock_id()?;
+    /// Unlock the given lock.
+    ///
+    /// Unlock is best effort. It will simply try to contact all instances
+    /// and remove the key.
+    ///
+    /// Unlocking the same lock repeatedly is safe, the outcome tells whether this call released it.
+    /// Child locks taken with `LockGuard::lock_child` are released first.
+    pub async fn unlock(&self, lock: &Lock<'_>) -> UnlockOutcome {
+        self.check_hold_time(lock);
+        self.check_clock(lock);
+        self.release_children(&lock.val).await;
+        let results = self.unlock_key(&lock.resource, &lock.val).await;
+        self.tracker.released(&lock.val);
+        core::unlock_outcome(&results, self.quorum)
+    }
 
-        assert_eq!(20, id1.len());
-        assert_eq!(20, id2.len());
-        assert_ne!(id1, id2);
+    // Deletes the key of a lock on every instance, removing a read lock from its readers set
+    async fn unlock_key(&self, resource: &[u8], val: &[u8]) -> Vec<Option<UnlockOutcome>> {
+        match rwlock::readers_of(resource, val) {
+            Some(readers) => {
+                self.on_all(|instance| {
+                    transport::read_unlock_instance(instance, resource, readers, val)
+                })
+                .await
+            }
+            None => {
+                self.on_all(|instance| transport::unlock_instance(instance, resource, val))
+                    .await
+            }
+        }
+    }
+
+    /// Unlock all of the given locks.
+    ///
+    /// Locks are released in batches by a single script call per batch and instance,
+    /// instead of one call per lock. Like `unlock`, this is best effort.
+    pub async fn unlock_all(&self, locks: &[Lock<'_>]) {
+        for lock in locks {
+            self.check_hold_time(lock);
+            self.check_clock(lock);
+        }
+        for lock in locks {
+            self.release_children(&lock.val).await;
+        }
+        self.on_all(|instance| transport::unlock_many_instance(instance, locks))
+            .await;
+        for lock in locks {
+            self.tracker.released(&lock.val);
+        }
+    }
+
+    /// Extend all of the given locks to `ttl` in a single round, reporting each lock's outcome.
+    ///
+    /// Locks are extended in batches by a single script call per batch and instance, like
+    /// `unlock_all`. The result has the extended lock or the error for every given lock, in
+    /// order, so callers can abort only the work protected by the locks that failed. Like
+    /// `extend`, a lock whose extension misses the quorum is released, failing with
+    /// `LockError::LockLost` if a quorum no longer holds it and `LockError::Unavailable`
+    /// otherwise, and locks whose validity has already run out fail with
+    /// `LockError::ValidityExpired` without being sent. Nothing is retried.
+    ///
+    /// Fails as a whole only if `ttl` is not accepted, see `extend`.
+    pub async fn extend_all<'a>(
+        &'a self,
+        locks: &[Lock<'a>],
+        ttl: Duration,
+    ) -> Result<Vec<Result<Lock<'a>, LockError>>, LockError> {
+        let ttl_millis = self.ttl_millis(ttl)?;
+        for lock in locks {
+            self.check_hold_time(lock);
+            self.check_clock(lock);
+        }
+        let sent: Vec<usize> = (0..locks.len())
+            .filter(|&i| !locks[i].remaining_validity().is_zero())
+            .collect();
+        let batch: Vec<(&[u8], &[u8], usize)> = sent
+            .iter()
+            .map(|&i| (&locks[i].resource[..], &locks[i].val[..], ttl_millis))
+            .collect();
+
+        let start_time = rt::now();
+        let mut results = self
+            .on_all(|instance| transport::extend_many_instance(instance, &batch))
+            .await;
+        // Instances that restarted recently may have forgotten locks, so they don't count
+        for (i, result) in results.iter_mut().enumerate() {
+            if self.tracker.is_quarantined(i) {
+                *result = Err(transport::quarantined());
+            }
+        }
+        let drift = self.clock_drift(ttl);
+        let elapsed = rt::elapsed(start_time);
+        let validity_time = core::validity(ttl, elapsed, drift)
+            .filter(|_| self.round_budget.is_none_or(|budget| elapsed <= budget))
+            .map_or(0, |validity| validity.as_millis() as usize);
+
+        let mut outcomes: Vec<Result<Lock<'a>, LockError>> = locks
+            .iter()
+            .map(|_| Err(LockError::ValidityExpired))
+            .collect();
+        let mut failed = Vec::new();
+        for (n, &i) in sent.iter().enumerate() {
+            let lock = &locks[i];
+            let tally = Tally::count(results.iter().map(|result| match result {
+                Ok(extended) => Ok(extended.get(n).copied().unwrap_or(false)),
+                Err(e) => Err(e),
+            }));
+            outcomes[i] = if tally.has_quorum(self.acquire_quorum()) && validity_time > 0 {
+                let extended = Lock {
+                    lock_manager: self,
+                    resource: lock.resource.clone(),
+                    val: lock.val.clone(),
+                    validity_time,
+                    acquired_at: rt::now(),
+                    acquired_at_wall: SystemTime::now(),
+                    held_since: lock.held_since,
+                    verified_on: None,
+                    fencing_token: lock.fencing_token,
+                    acquisition: AcquisitionDetails {
+                        ttl,
+                        drift,
+                        quorum: self.acquire_quorum(),
+                        succeeded: tally.succeeded,
+                        instances: self.servers.len() as u32,
+                    },
+                };
+                self.tracker.extended(&extended);
+                Ok(extended)
+            } else {
+                failed.push(lock.clone());
+                Err(if tally.is_lost(self.quorum) {
+                    LockError::LockLost
+                } else {
+                    LockError::Unavailable
+                })
+            };
+        }
+        for outcome in &outcomes {
+            if outcome.is_err() {
+                self.tracker.extend_failed();
+            }
+        }
+
+        if !failed.is_empty() {
+            self.on_all(|instance| transport::unlock_many_instance(instance, &failed))
+                .await;
+        }
+        self.check_leaks();
+        Ok(outcomes)
+    }
+
+    /// Release the given lock, but keep the resource blocked for `cooldown`.
+    ///
+    /// Instead of deleting the key, its value is replaced by a tombstone expiring after `cooldown`,
+    /// so nobody can acquire the resource again during that grace period.
+    /// Like `unlock`, this is best effort, releases child locks first and reports its outcome.
+    ///
+    /// May return `LockError::TtlTooLarge` if `cooldown` is too large.
+    pub async fn release_with_tombstone(
+        &self,
+        lock: &Lock<'_>,
+        cooldown: Duration,
+    ) -> Result<UnlockOutcome, LockError> {
+        let cooldown: usize = cooldown
+            .as_millis()
+            .try_into()
+            .map_err(|_| LockError::TtlTooLarge)?;
+        if cooldown == 0 {
+            return Ok(self.unlock(lock).await);
+        }
+
+        self.check_hold_time(lock);
+        self.check_clock(lock);
+        self.release_children(&lock.val).await;
+        let results = self
+            .on_all(|instance| {
+                transport::tombstone_instance(instance, &lock.resource, &lock.val, cooldown)
+            })
+            .await;
+        self.tracker.released(&lock.val);
+        Ok(core::unlock_outcome(&results, self.quorum))
+    }
+
+    /// Count the instances currently holding the given lock.
+    ///
+    /// This is a read-only check, the lock is neither extended nor released.
+    pub async fn verify(&self, lock: &Lock<'_>) -> u32 {
+        self.on_all(|instance| transport::verify_instance(instance, &lock.resource, &lock.val))
+            .await
+            .into_iter()
+            .filter(|held| *held)
+            .count() as u32
+    }
+
+    /// Take a snapshot of the manager's configuration, instance health, active locks and statistics.
+    ///
+    /// Every instance is sent a `PING` to determine its health.
+    /// Active locks are the ones acquired through this manager or its clones
+    /// that were neither released nor have expired.
+    pub async fn dump_state(&self) -> StateSnapshot {
+        let mut instances = self
+            .on_all(|instance| async move {
+                let start = rt::now();
+                let reachable = match instance.connect().await {
+                    Ok(mut con) => redis::cmd("PING")
+                        .query_async::<_, String>(&mut con)
+                        .await
+                        .is_ok(),
+                    Err(_) => false,
+                };
+                InstanceState {
+                    address: instance.client.get_connection_info().addr.to_string(),
+                    reachable,
+                    latency: reachable.then(|| rt::elapsed(start)),
+                    average_latency: None,
+                    quarantined: false,
+                    run_id: None,
+                }
+            })
+            .await;
+        for (i, instance) in instances.iter_mut().enumerate() {
+            instance.average_latency = self.tracker.average_latency(i);
+            instance.quarantined = self.tracker.is_quarantined(i);
+            instance.run_id = self.tracker.run_id(i);
+        }
+
+        StateSnapshot {
+            config: ConfigSnapshot {
+                quorum: self.quorum,
+                fault_tolerance: self.fault_tolerance(),
+                retry_count: self.retry_count,
+                retry_delay: self.retry_delay,
+                min_ttl: self.min_ttl,
+                max_ttl: self.max_ttl,
+                verify_after_lock: self.verify_after_lock,
+                fencing: self.fencing,
+                max_in_flight: self.max_in_flight,
+                token_pool_size: self.token_pool.as_ref().map(|pool| pool.batch_size),
+                compatibility: self.compatibility,
+                clock_drift_factor: self.clock_drift_factor,
+                clock_drift_padding: self.clock_drift_padding,
+                round_budget: self.round_budget,
+                round_concurrency: self.round_concurrency,
+                latency_ordering: self.latency_ordering,
+                restart_quarantine: self.restart_quarantine,
+                reset_on_restart: self.reset_on_restart,
+                maintenance_mode: self.is_in_maintenance_mode(),
+                shadow_mode: self.shadow_mode,
+                contention_threshold: self.contention_threshold,
+                retry_warmup: self.retry_warmup,
+                quorum_margin: self.quorum_margin,
+                validity_from_call: self.validity_from_call,
+                renewal_interval: self.renewal_interval,
+                shared_connections: self.registry.is_some(),
+                connect_strategy: self.connect_strategy,
+                client_name: self.connection_settings.client_name.clone(),
+                response_timeout: self.connection_settings.response_timeout,
+                scriptless: self.connection_settings.is_scriptless(),
+            },
+            instances,
+            active_locks: self.tracker.active_locks(),
+            stats: self.tracker.stats(),
+        }
+    }
+
+    /// Acquire the lock for the given resource and the requested TTL.
+    ///
+    /// The resource can be given as bytes, a string or any type implementing `LockResource`.
+    ///
+    /// If it succeeds, a `Lock` instance is returned,
+    /// including the value and the validity time
+    ///
+    /// If it fails. `None` is returned.
+    /// A user should retry after a short wait time.
+    /// If the resource is held by another client, `LockError::Contended` is returned
+    /// with a hint when enough instances should be free again, based on the keys' remaining TTLs.
+    ///
+    /// May return `LockError::TtlTooLarge` if `ttl` is too large,
+    /// or `LockError::TtlOutOfRange` if it is outside of the configured bounds.
+    /// Fails fast with `LockError::MaintenanceMode` in maintenance mode, see `set_maintenance_mode`,
+    /// and with `LockError::QuotaExceeded` if a quota is used up, see `set_lock_quota`.
+    ///
+    /// Cancel safe: if the future is dropped before it completes, e.g. by a timeout or `select!`,
+    /// the instances it may already have locked are released in the background, instead of
+    /// staying locked until the TTL expires. The same holds for the `acquire` variants
+    /// and `AcquireAttempt::step`.
+    pub async fn lock<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        self.lock_with_options(resource, ttl, &AcquireOptions::default())
+            .await
+    }
+
+    /// Make exactly one attempt at acquiring the lock, ignoring the manager's retry settings,
+    /// for callers that schedule their own retries or fall back to other work.
+    ///
+    /// Fails like `lock` otherwise: with `LockError::Unavailable` if no quorum was reached,
+    /// or `LockError::Contended` if the resource is held by another client.
+    pub async fn try_lock<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        let options = AcquireOptions::new().retry(1, Duration::ZERO);
+        self.lock_with_options(resource, ttl, &options).await
+    }
+
+    /// Like `lock`, but overriding the manager's acquisition settings for this call.
+    pub async fn lock_with_options<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        options: &AcquireOptions,
+    ) -> Result<Lock<'a>, LockError> {
+        self.check_maintenance_mode()?;
+        let key = resource.key();
+        if self.shadow_mode {
+            return self.lock_shadowed(&key, ttl, options).await;
+        }
+        self.lock_enforced(&key, ttl, options).await
+    }
+
+    async fn lock_enforced<'a>(
+        &'a self,
+        resource: &[u8],
+        ttl: Duration,
+        options: &AcquireOptions,
+    ) -> Result<Lock<'a>, LockError> {
+        self.check_quota(resource)?;
+        let ttl = self.ttl_millis(ttl)?;
+        let val = self.next_lock_id().map_err(LockError::Io)?;
+        if self.fencing {
+            return self.lock_fenced(resource, &val, ttl, options).await;
+        }
+        self.acquire_with(resource, &val, ttl, options, |instance| {
+            transport::lock_instance(instance, resource, val.clone(), ttl)
+        })
+        .await
+    }
+
+    // Acquires the lock like `lock_enforced`, taking a fencing token from the counters
+    // of the instances that grant it, see `set_fencing`
+    async fn lock_fenced<'a>(
+        &'a self,
+        resource: &[u8],
+        val: &[u8],
+        ttl: usize,
+        options: &AcquireOptions,
+    ) -> Result<Lock<'a>, LockError> {
+        let counter = transport::fencing_key(resource);
+        let token = AtomicU64::new(0);
+        let (counter, token) = (&counter, &token);
+
+        let mut lock = self
+            .acquire_with(resource, val, ttl, options, move |instance| async move {
+                let counted =
+                    transport::fenced_lock_instance(instance, resource, counter, val, ttl).await?;
+                token.fetch_max(counted.unwrap_or(0), Ordering::Relaxed);
+                Ok(counted.is_some())
+            })
+            .await?;
+        let token = token.load(Ordering::Relaxed);
+        self.on_all(|instance| transport::raise_fencing_instance(instance, counter, token))
+            .await;
+        lock.fencing_token = Some(token);
+        Ok(lock)
+    }
+
+    // Acquires the lock on `resource` with `val` by running `acquire` on every instance,
+    // with the bookkeeping and cancel safety of `lock`. The lock is released like any other,
+    // so `acquire` must store `val` under `resource`.
+    pub(crate) async fn acquire_with<'a, T, Fut>(
+        &'a self,
+        resource: &[u8],
+        val: &[u8],
+        ttl: usize,
+        options: &AcquireOptions,
+        acquire: T,
+    ) -> Result<Lock<'a>, LockError>
+    where
+        T: Fn(Instance<'a>) -> Fut,
+        Fut: Future<Output = RedisResult<bool>>,
+    {
+        let cancelled = CancelGuard::new(self, resource, val);
+
+        let lock = self
+            .exec_or_retry(resource, val, ttl, false, options, acquire)
+            .await;
+        let e = match lock {
+            Ok(lock) => {
+                let lock = self.acquired(lock).await;
+                cancelled.disarm();
+                return Ok(lock);
+            }
+            // Failed rounds release the instances they reached themselves
+            Err(e) => {
+                cancelled.disarm();
+                e
+            }
+        };
+        self.tracker.acquire_failed();
+        match e {
+            LockError::Unavailable => Err(match self.retry_after(resource).await {
+                Some(retry_after) => LockError::Contended { retry_after },
+                None => LockError::Unavailable,
+            }),
+            e => Err(e),
+        }
+    }
+
+    // Acquires and immediately releases the lock, reporting the outcome as an event,
+    // and hands out a lock as if it had been acquired
+    async fn lock_shadowed<'a>(
+        &'a self,
+        resource: &[u8],
+        ttl: Duration,
+        options: &AcquireOptions,
+    ) -> Result<Lock<'a>, LockError> {
+        let start = rt::now();
+        let result = self.lock_enforced(resource, ttl, options).await;
+        let elapsed = rt::elapsed(start);
+
+        let lock = match result {
+            Ok(lock) => {
+                self.unlock(&lock).await;
+                self.emit(Event::ShadowAcquisition {
+                    resource: resource.to_vec(),
+                    acquired: true,
+                    contended: false,
+                    succeeded: lock.acquisition.succeeded,
+                    elapsed,
+                });
+                lock
+            }
+            // Only contended or unavailable locks are stood in for, other errors are the caller's
+            Err(
+                e @ (LockError::Unavailable | LockError::Contended { .. } | LockError::TtlExceeded),
+            ) => {
+                self.emit(Event::ShadowAcquisition {
+                    resource: resource.to_vec(),
+                    acquired: false,
+                    contended: matches!(e, LockError::Contended { .. }),
+                    succeeded: 0,
+                    elapsed,
+                });
+                let drift = self.clock_drift(ttl);
+                Lock {
+                    lock_manager: self,
+                    resource: resource.to_vec(),
+                    val: self.next_lock_id().map_err(LockError::Io)?,
+                    validity_time: ttl.saturating_sub(drift).as_millis() as usize,
+                    acquired_at: rt::now(),
+                    acquired_at_wall: SystemTime::now(),
+                    held_since: start,
+                    verified_on: None,
+                    fencing_token: None,
+                    acquisition: AcquisitionDetails {
+                        ttl,
+                        drift,
+                        quorum: self.quorum,
+                        succeeded: 0,
+                        instances: self.servers.len() as u32,
+                    },
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(lock)
+    }
+
+    // Bookkeeping for a newly acquired lock
+    async fn acquired<'a>(&'a self, mut lock: Lock<'a>) -> Lock<'a> {
+        self.tracker.acquired(&lock);
+        self.check_leaks();
+
+        if self.verify_after_lock {
+            lock.verified_on = Some(self.verify(&lock).await);
+        }
+
+        lock
+    }
+
+    /// Wait until `when`, then try to acquire the lock for the given resource.
+    ///
+    /// Meant for cron-like coordination, where many processes wake up at the same moment
+    /// and exactly one should win. A few attempts are made in quick succession, so
+    /// simultaneous attempts that all miss the quorum don't leave the resource unlocked.
+    /// The losers fail like `lock` does, with `LockError::Contended` or `LockError::Unavailable`.
+    pub async fn acquire_at<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        when: Instant,
+    ) -> Result<Lock<'a>, LockError> {
+        rt::sleep(when.saturating_duration_since(rt::now())).await;
+        let options = AcquireOptions::new().retry(SCHEDULED_RETRY_COUNT, SCHEDULED_RETRY_DELAY);
+        self.lock_with_options(resource, ttl, &options).await
+    }
+
+    /// Loops until the lock is acquired.
+    ///
+    /// The lock is placed in a guard that will unlock the lock when the guard is dropped.
+    ///
+    /// May return `LockError::TtlTooLarge` if `ttl` is too large.
+    #[cfg(feature = "async-std-comp")]
+    pub async fn acquire<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<LockGuard<'a>, LockError> {
+        let lock = self.acquire_no_guard(resource, ttl).await?;
+        Ok(LockGuard { lock })
+    }
+
+    /// Like `acquire`, but overriding the manager's acquisition settings for this call.
+    ///
+    /// With a `timeout`, gives up once it has passed and returns the last error.
+    #[cfg(feature = "async-std-comp")]
+    pub async fn acquire_with_options<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        options: &AcquireOptions,
+    ) -> Result<LockGuard<'a>, LockError> {
+        let lock = self
+            .acquire_no_guard_with_options(resource, ttl, options)
+            .await?;
+        Ok(LockGuard { lock })
+    }
+
+    /// Loops until the lock is acquired.
+    ///
+    /// Either lock's value must expire after the ttl has elapsed,
+    /// or `LockManager::unlock` must be called to allow other clients to lock the same resource.
+    ///
+    /// May return `LockError::TtlTooLarge` if `ttl` is too large,
+    /// and `LockError::PermissionDenied` if the instances refuse the commands needed.
+    pub async fn acquire_no_guard<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        let key = resource.key();
+        loop {
+            match self.lock(&key[..], ttl).await {
+                Ok(lock) => return Ok(lock),
+                Err(e @ (LockError::TtlTooLarge | LockError::TtlOutOfRange)) => return Err(e),
+                Err(
+                    e @ (LockError::PermissionDenied { .. }
+                    | LockError::MaintenanceMode
+                    | LockError::QuotaExceeded { .. }),
+                ) => return Err(e),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Like `acquire_no_guard`, but overriding the manager's acquisition settings for this call.
+    ///
+    /// With a `timeout`, gives up once it has passed and returns the last error.
+    pub async fn acquire_no_guard_with_options<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        options: &AcquireOptions,
+    ) -> Result<Lock<'a>, LockError> {
+        let deadline = options
+            .timeout
+            .and_then(|timeout| rt::now().checked_add(timeout));
+        let mut options = options.clone();
+        let key = resource.key();
+
+        loop {
+            if let Some(deadline) = deadline {
+                options.timeout = Some(deadline.saturating_duration_since(rt::now()));
+            }
+            match self.lock_with_options(&key[..], ttl, &options).await {
+                Ok(lock) => return Ok(lock),
+                Err(
+                    e @ (LockError::TtlTooLarge
+                    | LockError::TtlOutOfRange
+                    | LockError::PermissionDenied { .. }
+                    | LockError::MaintenanceMode
+                    | LockError::QuotaExceeded { .. }),
+                ) => return Err(e),
+                Err(e) if deadline.is_some_and(|deadline| rt::now() >= deadline) => return Err(e),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Like `acquire_no_guard`, but gives up with `LockError::AcquireTimeout` once `max_wait`
+    /// has passed, see `acquire_until`. A `max_wait` too long to be represented as an `Instant`,
+    /// like `Duration::MAX`, never passes.
+    pub async fn acquire_timeout<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        max_wait: Duration,
+    ) -> Result<LockGuard<'a>, LockError> {
+        match rt::now().checked_add(max_wait) {
+            Some(deadline) => self.acquire_until(resource, ttl, deadline).await,
+            // Too long to be represented, so never given up on
+            None => Ok(LockGuard {
+                lock: self.acquire_no_guard(resource, ttl).await?,
+            }),
+        }
+    }
+
+    /// Like `acquire_no_guard`, but gives up with `LockError::AcquireTimeout` once `deadline`
+    /// has passed. An attempt that is in progress is never interrupted, so the call may return
+    /// up to one attempt later, and at least one attempt is made.
+    ///
+    /// Errors that retrying cannot resolve, like `LockError::TtlOutOfRange`, are returned
+    /// right away.
+    pub async fn acquire_until<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        deadline: Instant,
+    ) -> Result<LockGuard<'a>, LockError> {
+        let options = AcquireOptions::new().timeout(deadline.saturating_duration_since(rt::now()));
+        match self
+            .acquire_no_guard_with_options(resource, ttl, &options)
+            .await
+        {
+            Ok(lock) => Ok(LockGuard { lock }),
+            Err(
+                e @ (LockError::TtlTooLarge
+                | LockError::TtlOutOfRange
+                | LockError::PermissionDenied { .. }
+                | LockError::MaintenanceMode
+                | LockError::QuotaExceeded { .. }),
+            ) => Err(e),
+            Err(_) => Err(LockError::AcquireTimeout),
+        }
+    }
+
+    /// Extend the given lock by given time in milliseconds
+    ///
+    /// Fails with `LockError::LockLost` if a quorum of instances no longer holds the lock's value,
+    /// meaning another client may own the resource and work under the lock must stop.
+    /// `LockError::Unavailable` means the instances could not be reached and extending may be retried.
+    ///
+    /// Fails fast with `LockError::ValidityExpired`, without contacting any instance, if the lock's
+    /// validity has already run out locally: it may have been acquired by another client since.
+    ///
+    /// May return `LockError::TtlOutOfRange` if `ttl` is outside of the configured bounds.
+    pub async fn extend<'a>(
+        &'a self,
+        lock: &Lock<'a>,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        let ttl = self.ttl_millis(ttl)?;
+        if lock.remaining_validity().is_zero() {
+            self.tracker.extend_failed();
+            return Err(LockError::ValidityExpired);
+        }
+        if self.shadow_mode {
+            // Shadow locks are not held, so there is nothing to extend
+            let ttl = Duration::from_millis(ttl as u64);
+            return Ok(Lock {
+                validity_time: ttl.saturating_sub(self.clock_drift(ttl)).as_millis() as usize,
+                acquired_at: rt::now(),
+                acquired_at_wall: SystemTime::now(),
+                ..lock.clone()
+            });
+        }
+        self.check_hold_time(lock);
+        self.check_clock(lock);
+
+        let mut extended = self
+            .exec_or_retry(
+                &lock.resource,
+                &lock.val,
+                ttl,
+                true,
+                &AcquireOptions::default(),
+                move |instance| {
+                    transport::extend_lock_instance(instance, &lock.resource, &lock.val, ttl)
+                },
+            )
+            .await
+            .inspect_err(|_| self.tracker.extend_failed())?;
+        extended.held_since = lock.held_since;
+        extended.fencing_token = lock.fencing_token;
+        self.tracker.extended(&extended);
+        self.check_leaks();
+
+        Ok(extended)
+    }
+
+    /// Extend the given lock, or release it if that fails before the lock's validity runs out.
+    ///
+    /// If a quorum of instances cannot be re-established in time, the lock is released on
+    /// the instances that still hold it, so no half-held lock lingers until its TTL expires,
+    /// and `LockError::LockLost` is returned.
+    ///
+    /// May return `LockError::TtlTooLarge` or `LockError::TtlOutOfRange` without releasing the lock.
+    pub async fn extend_or_release<'a>(
+        &'a self,
+        lock: &Lock<'a>,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        let budget = lock.remaining_validity();
+        match rt::timeout(budget, self.extend(lock, ttl)).await {
+            Ok(Ok(extended)) => Ok(extended),
+            Ok(Err(e @ (LockError::TtlTooLarge | LockError::TtlOutOfRange))) => Err(e),
+            Ok(Err(_)) | Err(_) => {
+                self.unlock(lock).await;
+                Err(LockError::LockLost)
+            }
+        }
+    }
+
+    /// Replace the TTL of the given lock with `new_ttl`, keeping its token.
+    ///
+    /// The TTL is set, not added to, so `new_ttl` may be shorter than the remaining validity:
+    /// a job that learns mid-way that it needs far less time frees the resource sooner if it
+    /// crashes, one that needs far more time keeps its lock without re-acquiring it.
+    /// Everything bound to the token, like renewals, stays valid.
+    ///
+    /// If the lock has been lost or has expired, see `extend`, it is acquired again with its
+    /// value like `lock` would, provided no one else holds the resource by now. The work done
+    /// under the lock may have overlapped with another client's in between, so the returned
+    /// lock's `held_since` is reset and, with fencing enabled, a new fencing token is taken.
+    /// Fails like `lock` then.
+    ///
+    /// A failed shortening may have been applied on some instances already, so the lock must
+    /// then not be relied on for longer than `new_ttl` anymore.
+    pub async fn reacquire_with_ttl<'a>(
+        &'a self,
+        lock: &Lock<'a>,
+        new_ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        match self.extend(lock, new_ttl).await {
+            // Shadow locks are not held, so there is nothing to acquire again
+            Err(LockError::LockLost | LockError::ValidityExpired) if !self.shadow_mode => (),
+            result => return result,
+        }
+        self.check_maintenance_mode()?;
+        let ttl = self.ttl_millis(new_ttl)?;
+        let options = AcquireOptions::default();
+        let (resource, val) = (&lock.resource[..], &lock.val[..]);
+        if self.fencing {
+            return self.lock_fenced(resource, val, ttl, &options).await;
+        }
+        self.acquire_with(resource, val, ttl, &options, |instance| {
+            transport::lock_instance(instance, resource, val.to_vec(), ttl)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use anyhow::Result;
+    use once_cell::sync::Lazy;
+    use testcontainers::clients::Cli;
+    use testcontainers::images::redis::Redis;
+    use testcontainers::{Container, RunnableImage};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    pub(crate) type Containers = Vec<Container<'static, Redis>>;
+
+    static DOCKER: Lazy<Cli> = Lazy::new(Cli::docker);
+
+    fn is_normal<T: Sized + Send + Sync + Unpin>() {}
+
+    fn is_static<T: 'static>() {}
+
+    pub(crate) fn create_clients() -> (Containers, Vec<String>) {
+        let containers: Containers = (1..=3)
+            .map(|_| {
+                let image = RunnableImage::from(Redis).with_tag("7-alpine");
+                DOCKER.run(image)
+            })
+            .collect();
+
+        let addresses = containers
+            .iter()
+            .map(|node| format!("redis://localhost:{}", node.get_host_port_ipv4(6379)))
+            .collect();
+
+        (containers, addresses)
+    }
+
+    // Test that the LockManager is Send + Sync
+    #[test]
+    fn test_is_normal() {
+        is_normal::<LockManager>();
+        is_normal::<LockError>();
+        is_normal::<Lock>();
+        is_normal::<LockGuard>();
+        is_normal::<ReleaseHandle>();
+        is_static::<ReleaseHandle>();
+    }
+
+    #[test]
+    fn test_lock_error_retry_advice() {
+        let retry_after = Duration::from_millis(300);
+        assert_eq!(
+            LockError::Contended { retry_after }.retry_advice(),
+            RetryAdvice::RetryAfter(retry_after)
+        );
+        assert_eq!(LockError::Unavailable.retry_advice(), RetryAdvice::RetryNow);
+        assert_eq!(
+            LockError::MaintenanceMode.retry_advice(),
+            RetryAdvice::RetryNow
+        );
+        assert_eq!(LockError::TtlExceeded.retry_advice(), RetryAdvice::Fatal);
+        assert_eq!(LockError::LockLost.retry_advice(), RetryAdvice::Fatal);
+        assert_eq!(
+            LockError::ValidityExpired.retry_advice(),
+            RetryAdvice::Fatal
+        );
+
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            LockError::Redis(refused.into()).retry_advice(),
+            RetryAdvice::RetryNow
+        );
+        let loading = redis::RedisError::from((redis::ErrorKind::BusyLoadingError, "loading"));
+        assert_eq!(
+            LockError::Redis(loading).retry_advice(),
+            RetryAdvice::RetryNow
+        );
+        let wrong_type = redis::RedisError::from((redis::ErrorKind::TypeError, "type"));
+        assert_eq!(
+            LockError::Redis(wrong_type).retry_advice(),
+            RetryAdvice::Fatal
+        );
+    }
+
+    #[test]
+    fn test_lock_server_count() {
+        assert!(matches!(
+            LockManager::try_new(Vec::<String>::new()),
+            Err(LockError::NoServers)
+        ));
+
+        let tolerance = |n| {
+            let uris = vec!["redis://127.0.0.1:1/"; n];
+            LockManager::new(uris).fault_tolerance()
+        };
+        assert_eq!(tolerance(1), 0);
+        assert_eq!(tolerance(2), 0);
+        assert_eq!(tolerance(3), 1);
+        assert_eq!(tolerance(5), 2);
+    }
+
+    #[test]
+    fn test_lock_quorum_margin() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"; 5]);
+        rl.set_quorum_margin(1);
+        assert_eq!(rl.quorum(), 3);
+        assert_eq!(rl.acquire_quorum(), 4);
+        assert_eq!(rl.fault_tolerance(), 1);
+
+        // Capped at the number of instances
+        rl.set_quorum_margin(5);
+        assert_eq!(rl.acquire_quorum(), 5);
+        assert_eq!(rl.fault_tolerance(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_try_lock_single_attempt() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_retry(100, Duration::from_secs(1));
+
+        let start = rt::now();
+        match rl.try_lock(b"resource", Duration::from_secs(1)).await {
+            Err(LockError::Unavailable) => (),
+            r => panic!("Expected LockError::Unavailable, got {:?}", r),
+        }
+        assert!(rt::elapsed(start) < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_timeout() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_retry(3, Duration::from_millis(10));
+
+        let start = rt::now();
+        match rl
+            .acquire_timeout(
+                b"resource",
+                Duration::from_secs(1),
+                Duration::from_millis(200),
+            )
+            .await
+        {
+            Err(LockError::AcquireTimeout) => (),
+            r => panic!("Expected LockError::AcquireTimeout, got {:?}", r),
+        }
+        let elapsed = rt::elapsed(start);
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_lock_get_unique_id() -> Result<()> {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        assert_eq!(rl.get_unique_lock_id()?.len(), 20);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_get_unique_id_uniqueness() -> Result<()> {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+
+        let id1 = rl.get_unique_lock_id()?;
+        let id2 = rl.get_unique_lock_id()?;
+
+        assert_eq!(20, id1.len());
+        assert_eq!(20, id2.len());
+        assert_ne!(id1, id2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_token_pool() -> Result<()> {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_token_pool(3);
+
+        let ids = (0..7)
+            .map(|_| rl.next_lock_id())
+            .collect::<io::Result<Vec<_>>>()?;
+        assert!(ids.iter().all(|id| id.len() == 20));
+        assert!(ids.iter().enumerate().all(|(i, id)| !ids[..i].contains(id)));
+        assert_eq!(
+            rl.token_pool.as_ref().unwrap().tokens.lock().unwrap().len(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_compatibility_tokens() -> Result<()> {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+
+        rl.set_compatibility(Compatibility::NodeRedlock);
+        let id = rl.next_lock_id()?;
+        assert_eq!(id.len(), 32);
+        assert!(id.iter().all(|b| b"0123456789abcdef".contains(b)));
+
+        rl.set_compatibility(Compatibility::Redsync);
+        let id = rl.next_lock_id()?;
+        assert_eq!(id.len(), 24);
+        assert!(id.ends_with(b"=="));
+
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+
+        // Native tokens starting with the version byte would be parsed as versioned values
+        for token in [[1, 0, 7, 7], [1, 2, 7, 7]] {
+            let id = Compatibility::Native.encode_token(token.to_vec());
+            assert_ne!(id[0], core::VALUE_VERSION_1);
+            assert_eq!(core::value_token(&id), id);
+            assert!(!core::owns(&id, &[1, token[1], 7, 8]));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_valid_instance() {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+
+        assert_eq!(3, rl.servers.len());
+        assert_eq!(2, rl.quorum);
+    }
+
+    #[tokio::test]
+    async fn test_lock_direct_unlock_fails() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let val = rl.get_unique_lock_id()?;
+        assert_eq!(
+            transport::unlock_instance(rl.instance(0), &key, &val).await,
+            Some(UnlockOutcome::AlreadyReleased)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_unlock_outcome() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        assert_eq!(rl.unlock(&lock).await, UnlockOutcome::Released);
+        assert_eq!(rl.unlock(&lock).await, UnlockOutcome::AlreadyReleased);
+
+        let _other = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        assert_eq!(rl.unlock(&lock).await, UnlockOutcome::NotOwned);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_acquisition_details() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_clock_drift(0.01, Duration::from_millis(5));
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(1000)).await?;
+        assert_eq!(
+            lock.acquisition_details(),
+            AcquisitionDetails {
+                ttl: Duration::from_millis(1000),
+                drift: Duration::from_millis(15),
+                quorum: 2,
+                succeeded: 3,
+                instances: 3,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_permission_denied() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        for server in &rl.servers {
+            let mut con = server.get_connection()?;
+            redis::cmd("ACL")
+                .arg("SETUSER")
+                .arg("locker")
+                .arg("on")
+                .arg(">secret")
+                .arg("~*")
+                .arg("+get")
+                .execute(&mut con);
+        }
+        let mut restricted = LockManager::new(
+            addresses
+                .iter()
+                .map(|address| address.replace("redis://", "redis://locker:secret@"))
+                .collect(),
+        );
+        restricted.set_retry(100, Duration::from_millis(100));
+        let key = rl.get_unique_lock_id()?;
+
+        let start = rt::now();
+        match restricted.lock(&key, Duration::from_millis(1000)).await {
+            Err(LockError::PermissionDenied { command }) => assert_eq!(command, "set"),
+            r => panic!("Expected LockError::PermissionDenied, got {:?}", r),
+        }
+        assert!(rt::elapsed(start) < Duration::from_secs(1));
+
+        // Not retried until the deadline either
+        let start = rt::now();
+        let deadline = start + Duration::from_secs(5);
+        match restricted
+            .acquire_until(&key, Duration::from_millis(1000), deadline)
+            .await
+        {
+            Err(LockError::PermissionDenied { command }) => assert_eq!(command, "set"),
+            r => panic!("Expected LockError::PermissionDenied, got {:?}", r),
+        }
+        assert!(rt::elapsed(start) < Duration::from_secs(1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_validity_from_call() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(1000);
+        let options = AcquireOptions::new()
+            .retry(100, Duration::from_millis(20))
+            .validity_from_call(true);
+
+        // Held for 500ms, so the lock is acquired with about half of its TTL left
+        rl.lock(&key, Duration::from_millis(500)).await?;
+        let lock = rl.lock_with_options(&key, ttl, &options).await?;
+        assert!(lock.remaining_validity() < Duration::from_millis(600));
+        rl.unlock(&lock).await;
+
+        // Held for longer than the TTL
+        rl.lock(&key, Duration::from_millis(1500)).await?;
+        match rl.lock_with_options(&key, ttl, &options).await {
+            Err(LockError::TtlExceeded) => (),
+            r => panic!("Expected LockError::TtlExceeded, got {:?}", r),
+        }
+
+        // Enabled for all calls of a manager
+        let mut rl2 = LockManager::new(addresses.clone());
+        rl2.set_retry(100, Duration::from_millis(20));
+        rl2.set_validity_from_call(true);
+        let key = rl.get_unique_lock_id()?;
+        rl.lock(&key, Duration::from_millis(500)).await?;
+        let lock = rl2.lock(&key, ttl).await?;
+        assert!(lock.remaining_validity() < Duration::from_millis(600));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_cancelled() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let rl2 = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        // Cancelled at various points of the round
+        for micros in [1, 100, 500, 1000, 2000] {
+            let lock = rl.lock(&key, Duration::from_millis(10_000));
+            if let Ok(lock) = tokio::time::timeout(Duration::from_micros(micros), lock).await {
+                rl.unlock(&lock?).await;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        rl2.lock(&key, Duration::from_millis(1000)).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_retry_warmup() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let mut rl2 = LockManager::new(addresses.clone());
+        rl2.set_retry(1, Duration::from_secs(1));
+        rl2.set_retry_warmup(Duration::from_millis(1000), Duration::from_millis(20));
+        let key = rl.get_unique_lock_id()?;
+
+        // Acquired with quick retries once the other lock expires, despite a single retry
+        rl.lock(&key, Duration::from_millis(300)).await?;
+        let start = rt::now();
+        rl2.lock(&key, Duration::from_millis(1000)).await?;
+        assert!(rt::elapsed(start) < Duration::from_millis(600));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_required_acl() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let acl = rl.required_acl();
+        assert!(acl.contains(&"+evalsha") && acl.contains(&"+info"));
+        assert!(!acl.contains(&"+multi") && !acl.contains(&"+client|setname"));
+
+        rl.set_scriptless(true);
+        rl.set_client_name("billing worker");
+        let acl = rl.required_acl();
+        assert!(acl.contains(&"+multi") && acl.contains(&"+exec"));
+        assert!(acl.contains(&"+client|setname"));
+    }
+
+    #[tokio::test]
+    async fn test_lock_client_name() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_client_name("billing worker");
+        rl.set_response_timeout(Duration::from_secs(1));
+        rl.set_connection_registry(Arc::new(ConnectionRegistry::new()));
+        let key = rl.get_unique_lock_id()?;
+        rl.lock(&key, Duration::from_millis(1000)).await?;
+
+        let mut con = rl.servers[0].get_connection()?;
+        let clients: String = redis::cmd("CLIENT").arg("LIST").query(&mut con)?;
+        assert!(clients.contains("name=billing-worker"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_versioned_value() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let token = rl.get_unique_lock_id()?;
+        let mut versioned = vec![core::VALUE_VERSION_1, token.len() as u8];
+        versioned.extend_from_slice(&token);
+        versioned.extend_from_slice(b"metadata");
+
+        let mut con = rl.servers[0].get_connection()?;
+        redis::cmd("SET")
+            .arg(&*key)
+            .arg(&*versioned)
+            .execute(&mut con);
+        assert!(transport::verify_instance(rl.instance(0), &key, &token).await);
+        assert_eq!(
+            transport::unlock_instance(rl.instance(0), &key, &token).await,
+            Some(UnlockOutcome::Released)
+        );
+
+        redis::cmd("SET").arg(&*key).arg(&*token).execute(&mut con);
+        assert_eq!(
+            transport::unlock_instance(rl.instance(0), &key, &versioned).await,
+            Some(UnlockOutcome::Released)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_direct_unlock_succeeds() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let val = rl.get_unique_lock_id()?;
+        let mut con = rl.servers[0].get_connection()?;
+        redis::cmd("SET").arg(&*key).arg(&*val).execute(&mut con);
+
+        assert_eq!(
+            transport::unlock_instance(rl.instance(0), &key, &val).await,
+            Some(UnlockOutcome::Released)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_direct_lock_succeeds() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let val = rl.get_unique_lock_id()?;
+        let mut con = rl.servers[0].get_connection()?;
+
+        redis::cmd("DEL").arg(&*key).execute(&mut con);
+        assert!(transport::lock_instance(rl.instance(0), &key, val.clone(), 1000).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_unlock() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let val = rl.get_unique_lock_id()?;
+        let mut con = rl.servers[0].get_connection()?;
+        let _: () = redis::cmd("SET")
+            .arg(&*key)
+            .arg(&*val)
+            .query(&mut con)
+            .unwrap();
+
+        let lock = Lock {
+            lock_manager: &rl,
+            resource: key,
+            val,
+            validity_time: 0,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: rt::now(),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+
+        rl.unlock(&lock).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_lock() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+
+        let key = rl.get_unique_lock_id()?;
+        match rl.lock(&key, Duration::from_millis(1000)).await {
+            Ok(lock) => {
+                assert_eq!(key, lock.resource);
+                assert_eq!(20, lock.val.len());
+                assert!(lock.validity_time > 900);
+                assert!(
+                    lock.validity_time > 900,
+                    "validity time: {}",
+                    lock.validity_time
+                );
+            }
+            Err(e) => panic!("{:?}", e),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_unlock_all() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let mut locks = Vec::new();
+        for _ in 0..3 {
+            let key = rl.get_unique_lock_id()?;
+            locks.push(rl.lock(&key, Duration::from_millis(10_000)).await?);
+        }
+
+        rl.unlock_all(&locks).await;
+        for lock in &locks {
+            assert_eq!(rl.verify(lock).await, 0);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_dump_state() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        let state = rl.dump_state().await;
+        assert_eq!(state.config.quorum, 2);
+        assert!(state.instances.iter().all(|instance| instance.reachable));
+        assert_eq!(state.active_locks.len(), 1);
+        assert_eq!(state.stats.acquired, 1);
+
+        rl.unlock(&lock).await;
+        let state = rl.dump_state().await;
+        assert!(state.active_locks.is_empty());
+        assert_eq!(state.stats.released, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_extend_or_release() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(1000)).await?;
+        let lock = rl
+            .extend_or_release(&lock, Duration::from_millis(1000))
+            .await?;
+
+        // Take the lock away on two of the instances
+        for client in &rl.servers[..2] {
+            let mut con = client.get_connection()?;
+            redis::cmd("SET").arg(&key).arg("other").execute(&mut con);
+        }
+
+        match rl
+            .extend_or_release(&lock, Duration::from_millis(1000))
+            .await
+        {
+            Err(LockError::LockLost) => (),
+            r => panic!("Expected LockError::LockLost, got {:?}", r),
+        }
+        assert_eq!(rl.verify(&lock).await, 0);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_lock_valid_instance() {
+    async fn test_lock_reacquire_with_ttl() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
         let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
 
-        assert_eq!(3, rl.servers.len());
-        assert_eq!(2, rl.quorum);
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        let shortened = rl
+            .reacquire_with_ttl(&lock, Duration::from_millis(1000))
+            .await?;
+        assert_eq!(shortened.val, lock.val);
+        assert!(shortened.validity_time <= 1000);
+        for pttl in rl
+            .on_all(|instance| transport::pttl_instance(instance, &key))
+            .await
+        {
+            assert!(pttl?.is_some_and(|pttl| pttl <= Duration::from_millis(1000)));
+        }
+
+        let lengthened = rl
+            .reacquire_with_ttl(&shortened, Duration::from_millis(20_000))
+            .await?;
+        assert_eq!(lengthened.val, lock.val);
+        assert!(lengthened.validity_time > 10_000);
+        assert_eq!(lengthened.held_since, lock.held_since);
+
+        // A lost lock is acquired again with its value, unless someone else holds it
+        for server in &rl.servers {
+            let mut con = server.get_connection()?;
+            redis::cmd("DEL").arg(&key).execute(&mut con);
+        }
+        let reacquired = rl
+            .reacquire_with_ttl(&lengthened, Duration::from_millis(1000))
+            .await?;
+        assert_eq!(reacquired.val, lock.val);
+        assert_eq!(rl.verify(&reacquired).await, 3);
+
+        for server in &rl.servers {
+            let mut con = server.get_connection()?;
+            redis::cmd("SET").arg(&key).arg("other").execute(&mut con);
+        }
+        assert!(rl
+            .reacquire_with_ttl(&reacquired, Duration::from_millis(1000))
+            .await
+            .is_err());
+
+        Ok(())
     }
 
     #[tokio::test]
-    async fn test_lock_direct_unlock_fails() -> Result<()> {
+    async fn test_lock_release_with_tombstone() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
         let rl = LockManager::new(addresses.clone());
         let key = rl.get_unique_lock_id()?;
 
-        let val = rl.get_unique_lock_id()?;
-        assert!(!LockManager::unlock_instance(&rl.servers[0], &key, &val).await);
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        assert_eq!(
+            rl.release_with_tombstone(&lock, Duration::from_millis(2000))
+                .await?,
+            UnlockOutcome::Released
+        );
+        assert_eq!(rl.verify(&lock).await, 0);
+        assert_eq!(
+            rl.release_with_tombstone(&lock, Duration::from_millis(2000))
+                .await?,
+            UnlockOutcome::NotOwned
+        );
+
+        match rl.lock(&key, Duration::from_millis(1000)).await {
+            Err(LockError::Contended { retry_after }) => {
+                assert!(retry_after <= Duration::from_millis(2000))
+            }
+            r => panic!("Expected tombstone to block the resource, got {:?}", r),
+        }
+
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        rl.lock(&key, Duration::from_millis(1000)).await?;
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_lock_direct_unlock_succeeds() -> Result<()> {
+    async fn test_lock_release_handle_from_task() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
         let rl = LockManager::new(addresses.clone());
         let key = rl.get_unique_lock_id()?;
 
-        let val = rl.get_unique_lock_id()?;
-        let mut con = rl.servers[0].get_connection()?;
-        redis::cmd("SET").arg(&*key).arg(&*val).execute(&mut con);
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        let handle = lock.split_release_handle();
+        tokio::spawn(async move { handle.release().await }).await?;
 
-        assert!(LockManager::unlock_instance(&rl.servers[0], &key, &val).await);
+        assert_eq!(rl.verify(&lock).await, 0);
+        rl.lock(&key, Duration::from_millis(1000)).await?;
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_lock_direct_lock_succeeds() -> Result<()> {
+    async fn test_lock_extension_handle_from_task() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
         let rl = LockManager::new(addresses.clone());
         let key = rl.get_unique_lock_id()?;
 
-        let val = rl.get_unique_lock_id()?;
-        let mut con = rl.servers[0].get_connection()?;
+        let lock = rl.lock(&key, Duration::from_millis(1000)).await?;
+        let handle = lock.extension_handle();
+        let validity = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.extend(Duration::from_millis(10_000)).await }
+        })
+        .await??;
+        assert!(validity > Duration::from_millis(9000));
 
-        redis::cmd("DEL").arg(&*key).execute(&mut con);
-        assert!(LockManager::lock_instance(&rl.servers[0], &*key, val.clone(), 1000).await);
+        rl.unlock(&lock).await;
+        match handle.extend(Duration::from_millis(10_000)).await {
+            Err(LockError::LockLost) => (),
+            r => panic!("Expected LockError::LockLost, got {:?}", r),
+        }
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_lock_unlock() -> Result<()> {
+    async fn test_lock_verify_after_lock() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
-        let rl = LockManager::new(addresses.clone());
+        let mut rl = LockManager::new(addresses.clone());
         let key = rl.get_unique_lock_id()?;
 
-        let val = rl.get_unique_lock_id()?;
-        let mut con = rl.servers[0].get_connection()?;
-        let _: () = redis::cmd("SET")
-            .arg(&*key)
-            .arg(&*val)
-            .query(&mut con)
-            .unwrap();
-
-        let lock = Lock {
-            lock_manager: &rl,
-            resource: key,
-            val,
-            validity_time: 0,
-        };
-
+        let lock = rl.lock(&key, Duration::from_millis(1000)).await?;
+        assert_eq!(lock.verified_on, None);
+        assert_eq!(rl.verify(&lock).await, 3);
         rl.unlock(&lock).await;
+        assert_eq!(rl.verify(&lock).await, 0);
+
+        rl.set_verify_after_lock(true);
+        let lock = rl.lock(&key, Duration::from_millis(1000)).await?;
+        assert_eq!(lock.verified_on, Some(3));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_lock_lock() -> Result<()> {
+    async fn test_lock_fencing() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
-        let rl = LockManager::new(addresses.clone());
-
+        let mut rl = LockManager::new(addresses.clone());
         let key = rl.get_unique_lock_id()?;
-        match rl.lock(&key, Duration::from_millis(1000)).await {
-            Ok(lock) => {
-                assert_eq!(key, lock.resource);
-                assert_eq!(20, lock.val.len());
-                assert!(lock.validity_time > 900);
-                assert!(
-                    lock.validity_time > 900,
-                    "validity time: {}",
-                    lock.validity_time
-                );
-            }
-            Err(e) => panic!("{:?}", e),
-        }
+        let ttl = Duration::from_millis(1000);
+
+        let lock = rl.lock(&key, ttl).await?;
+        assert_eq!(lock.fencing_token, None);
+        rl.unlock(&lock).await;
+
+        rl.set_fencing(true);
+        let lock = rl.lock(&key, ttl).await?;
+        let token = lock.fencing_token.expect("fencing is enabled");
+        let lock = rl.extend(&lock, ttl).await?;
+        assert_eq!(lock.fencing_token, Some(token));
+        rl.unlock(&lock).await;
+
+        // A counter that got ahead on one instance raises the tokens of later holders
+        let instance = &rl.servers[0];
+        redis::cmd("INCRBY")
+            .arg(transport::fencing_key(&key))
+            .arg(10)
+            .execute(&mut instance.get_connection()?);
+        let lock = rl.lock(&key, ttl).await?;
+        let raised = lock.fencing_token.expect("fencing is enabled");
+        assert!(raised > token + 10);
+        rl.unlock(&lock).await;
+        let lock = rl.lock(&key, ttl).await?;
+        assert!(lock.fencing_token > Some(raised));
 
         Ok(())
     }
@@ -543,6 +3205,106 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_lock_round_concurrency() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_round_concurrency(1);
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        assert_eq!(rl.verify(&lock).await, 3);
+        assert_eq!(rl.unlock(&lock).await, UnlockOutcome::Released);
+        assert_eq!(rl.verify(&lock).await, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_latency_ordering() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_latency_ordering(true);
+        rl.set_round_concurrency(1);
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        let state = rl.dump_state().await;
+        assert!(state.config.latency_ordering);
+        assert!(state.instances.iter().all(|i| i.average_latency.is_some()));
+        assert_eq!(rl.verify(&lock).await, 3);
+        assert_eq!(rl.unlock(&lock).await, UnlockOutcome::Released);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_order() {
+        let tracker = Tracker::default();
+        tracker.record_latency(0, Duration::from_millis(30));
+        tracker.record_latency(2, Duration::from_millis(10));
+        assert_eq!(tracker.latency_order(&[0, 1, 2, 3]), [1, 3, 2, 0]);
+
+        for _ in 0..20 {
+            tracker.record_latency(0, Duration::from_millis(1));
+        }
+        assert!(tracker.average_latency(0).unwrap() < Duration::from_millis(10));
+        assert_eq!(tracker.latency_order(&[2, 0, 1]), [2, 1, 0]);
+    }
+
+    #[test]
+    fn test_restart_quarantine() {
+        let tracker = Tracker::default();
+        let quarantine = Some(Duration::from_secs(60));
+        assert!(!tracker.observe_run(1, "a".to_string(), quarantine));
+        assert!(!tracker.observe_run(1, "a".to_string(), quarantine));
+        assert!(!tracker.is_quarantined(1));
+
+        assert!(tracker.observe_run(1, "b".to_string(), quarantine));
+        assert!(tracker.is_quarantined(1));
+        assert!(!tracker.is_quarantined(0));
+
+        assert!(tracker.observe_run(1, "c".to_string(), Some(Duration::ZERO)));
+        assert!(!tracker.is_quarantined(1));
+    }
+
+    #[test]
+    fn test_run_id_pinning() {
+        let tracker = Tracker::default();
+        assert_eq!(tracker.run_id(0), None);
+        tracker.observe_run(0, "a".to_string(), None);
+        tracker.record_latency(0, Duration::from_millis(5));
+        assert_eq!(tracker.run_id(0).as_deref(), Some("a"));
+
+        tracker.forget_latency(0);
+        tracker.forget_latency(3);
+        assert_eq!(tracker.average_latency(0), None);
+    }
+
+    #[tokio::test]
+    async fn test_lock_acquire_at_single_winner() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let managers: Vec<_> = (0..5)
+            .map(|_| LockManager::new(addresses.clone()))
+            .collect();
+        let key = managers[0].get_unique_lock_id()?;
+        let when = rt::now() + Duration::from_millis(100);
+
+        let results = join_all(
+            managers
+                .iter()
+                .map(|rl| rl.acquire_at(&key, Duration::from_millis(10_000), when)),
+        )
+        .await;
+        assert!(rt::now() >= when);
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+
+        Ok(())
+    }
+
     #[cfg(all(not(feature = "tokio-comp"), feature = "async-std-comp"))]
     #[tokio::test]
     async fn test_lock_lock_unlock_raii() -> Result<()> {
@@ -575,9 +3337,33 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(all(not(feature = "tokio-comp"), feature = "async-std-comp"))]
+    #[tokio::test]
+    async fn test_lock_guard_dropped_in_tokio() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut rl = LockManager::new(addresses.clone());
+        let sink = events.clone();
+        rl.set_event_handler(move |event| sink.lock().unwrap().push(event.clone()));
+        let key = rl.get_unique_lock_id()?;
+
+        let guard = rl.acquire(&key, Duration::from_millis(10_000)).await?;
+        drop(guard);
+        assert!(matches!(
+            &events.lock().unwrap()[..],
+            [Event::BlockingDrop { resource }] if *resource == key
+        ));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        rl.lock(&key, Duration::from_millis(1000)).await?;
+
+        Ok(())
+    }
+
     #[cfg(feature = "tokio-comp")]
     #[tokio::test]
-    async fn test_lock_raii_does_not_unlock_with_tokio_enabled() -> Result<()> {
+    async fn test_lock_raii_unlocks_with_tokio_enabled() -> Result<()> {
         let (_containers, addresses) = create_clients();
 
         let rl1 = LockManager::new(addresses.clone());
@@ -585,10 +3371,12 @@ mod tests {
         let key = rl1.get_unique_lock_id()?;
 
         async {
-            let lock_guard = rl1
-                .acquire(&key, Duration::from_millis(10_000))
-                .await
-                .expect("LockManage rl1 should be able to acquire lock");
+            let lock_guard = LockGuard {
+                lock: rl1
+                    .lock(&key, Duration::from_millis(10_000))
+                    .await
+                    .expect("LockManage rl1 should be able to acquire lock"),
+            };
             let lock = &lock_guard.lock;
             assert!(
                 lock.validity_time > 0,
@@ -603,9 +3391,9 @@ mod tests {
         }
         .await;
 
-        if let Ok(_) = rl2.lock(&key, Duration::from_millis(1000)).await {
-            panic!("Lock couldn't be acquired");
-        }
+        // Released in a spawned task
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        rl2.lock(&key, Duration::from_millis(1000)).await?;
 
         Ok(())
     }
@@ -640,7 +3428,7 @@ mod tests {
             match rl2.lock(&key, Duration::from_millis(1000)).await {
                 Ok(_) => panic!("Expected an error when extending the lock but didn't receive one"),
                 Err(e) => match e {
-                    LockError::Unavailable => (),
+                    LockError::Contended { .. } => (),
                     _ => panic!("Unexpected error when extending lock"),
                 },
             }
@@ -671,19 +3459,16 @@ mod tests {
             tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
 
             // Assert rl2 can lock with the key now
-            match rl2.lock(&key, Duration::from_millis(1000)).await {
-                Err(_) => {
-                    panic!("Unexpected error when trying to claim free lock after extend expired")
-                }
-                _ => (),
+            if rl2.lock(&key, Duration::from_millis(1000)).await.is_err() {
+                panic!("Unexpected error when trying to claim free lock after extend expired")
             }
 
-            // Also assert rl1 can't reuse lock1
+            // Also assert rl1 can't reuse lock1, whose validity has run out
             match rl1.extend(&lock1.lock, Duration::from_millis(1000)).await {
                 Ok(_) => panic!("Did not expect OK() when re-extending rl1"),
                 Err(e) => match e {
-                    LockError::Unavailable => (),
-                    _ => panic!("Expected lockError::Unavailable when re-extending rl1"),
+                    LockError::ValidityExpired => (),
+                    _ => panic!("Expected LockError::ValidityExpired when re-extending rl1"),
                 },
             }
         }
@@ -717,6 +3502,25 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_lock_with_options_timeout() {
+        // Nothing to connect to, so every attempt fails
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let options = AcquireOptions::new()
+            .retry(u32::MAX, Duration::from_millis(10))
+            .timeout(Duration::from_millis(100));
+
+        let start = rt::now();
+        match rl
+            .acquire_no_guard_with_options(b"mutex", Duration::from_secs(1), &options)
+            .await
+        {
+            Err(LockError::Unavailable) => (),
+            r => panic!("Expected LockError::Unavailable, got {:?}", r),
+        }
+        assert!(rt::elapsed(start) < Duration::from_secs(1));
+    }
+
     #[tokio::test]
     async fn test_lock_ttl_duration_conversion_error() {
         let (_containers, addresses) = create_clients();
@@ -725,9 +3529,427 @@ mod tests {
 
         // Too big Duration, fails - technical limit is from_millis(u64::MAX)
         let ttl = Duration::from_secs(u64::MAX);
-        match rl.lock(&key, ttl).await {
-            Ok(_) => panic!("Expected LockError::TtlTooLarge"),
-            Err(_) => (), // Test passes
+        if rl.lock(&key, ttl).await.is_ok() {
+            panic!("Expected LockError::TtlTooLarge")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_ttl_out_of_range() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_ttl_bounds(Duration::from_millis(100), Duration::from_secs(60));
+        let key = rl.get_unique_lock_id().unwrap();
+
+        match rl.lock(&key, Duration::from_millis(1)).await {
+            Err(LockError::TtlOutOfRange) => (),
+            r => panic!("Expected LockError::TtlOutOfRange, got {:?}", r),
+        }
+        match rl.lock(&key, Duration::from_secs(7 * 24 * 60 * 60)).await {
+            Err(LockError::TtlOutOfRange) => (),
+            r => panic!("Expected LockError::TtlOutOfRange, got {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_clock_drift_allowance() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_retry(1, Duration::ZERO);
+
+        match rl.lock(b"resource", Duration::from_millis(500)).await {
+            Err(LockError::Unavailable) => (),
+            r => panic!("Expected LockError::Unavailable, got {:?}", r),
+        }
+
+        rl.set_clock_drift(0.0, Duration::from_secs(1));
+        match rl.lock(b"resource", Duration::from_millis(500)).await {
+            Err(LockError::TtlExceeded) => (),
+            r => panic!("Expected LockError::TtlExceeded, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_lock_clock_discrepancy() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let mut checked = rl.clone();
+        checked.set_max_clock_discrepancy(Duration::from_secs(1));
+        let mut lock = Lock {
+            lock_manager: &rl,
+            resource: b"mutex".to_vec(),
+            val: vec![],
+            validity_time: 10_000,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now() - Duration::from_secs(5),
+            held_since: rt::now(),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+        assert!(lock.clock_discrepancy() > Duration::from_secs(4));
+        assert!(!lock.is_suspect());
+        assert!(lock.remaining_validity() > Duration::from_secs(9));
+
+        lock.lock_manager = &checked;
+        assert!(lock.is_suspect());
+        assert!(lock.remaining_validity() <= Duration::from_secs(5));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test(start_paused = true)]
+    async fn test_lock_paused_clock() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_retry(3, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(rl.lock(b"paused", Duration::from_secs(10)).await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let lock = Lock {
+            lock_manager: &rl,
+            resource: b"paused".to_vec(),
+            val: b"val".to_vec(),
+            validity_time: 1000,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: rt::now(),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+        tokio::time::advance(Duration::from_millis(600)).await;
+        assert!(lock.remaining_validity() <= Duration::from_millis(400));
+    }
+
+    #[cfg(feature = "leak-detection")]
+    #[test]
+    fn test_lock_leak_detection() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let sink = events.clone();
+        rl.set_event_handler(move |event| sink.lock().unwrap().push(event.clone()));
+        let lock = |val: &[u8], validity_time| Lock {
+            lock_manager: &rl,
+            resource: b"mutex".to_vec(),
+            val: val.to_vec(),
+            validity_time,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: rt::now(),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+
+        rl.tracker.acquired(&lock(b"leaked", 0));
+        rl.tracker.acquired(&lock(b"released", 0));
+        rl.tracker.released(b"released");
+        rl.tracker.acquired(&lock(b"held", 10_000));
+        rl.check_leaks();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            Event::LockLeaked { resource, .. } => assert_eq!(resource, b"mutex"),
+            e => panic!("Expected Event::LockLeaked, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_lock_display() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let mut lock = Lock {
+            lock_manager: &rl,
+            resource: b"mutex".to_vec(),
+            val: vec![0x9f, 0x86, 0xd0, 0x81, 0x88],
+            validity_time: 0,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: rt::now(),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+
+        assert_eq!(lock.to_string(), "mutex (token 9f86d081, 0ms left)");
+        assert_eq!(
+            format!("{:?}", lock),
+            "Lock { resource: mutex, val: 9f86d08188, validity_time: 0, remaining: 0ns }"
+        );
+
+        lock.resource = vec![0, 1, 255];
+        assert_eq!(lock.to_string(), "0x0001ff (token 9f86d081, 0ms left)");
+    }
+
+    #[tokio::test]
+    async fn test_lock_hold_time_exceeded() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let sink = events.clone();
+        rl.set_event_handler(move |event| sink.lock().unwrap().push(event.clone()));
+        rl.set_max_hold_time(b"", Duration::from_secs(60));
+        rl.set_max_hold_time(b"jobs:", Duration::from_millis(10));
+
+        let lock = |resource: &[u8]| Lock {
+            lock_manager: &rl,
+            resource: resource.to_vec(),
+            val: vec![],
+            validity_time: 0,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: rt::now() - Duration::from_secs(1),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+
+        rl.unlock(&lock(b"mutex")).await;
+        assert!(events.lock().unwrap().is_empty());
+
+        rl.unlock(&lock(b"jobs:cleanup")).await;
+        let events = events.lock().unwrap();
+        match events.as_slice() {
+            [Event::HoldTimeExceeded {
+                resource, limit, ..
+            }] => {
+                assert_eq!(resource, b"jobs:cleanup");
+                assert_eq!(*limit, Duration::from_millis(10));
+            }
+            events => panic!("Unexpected events: {:?}", events),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_extend_validity_expired() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let lock = Lock {
+            lock_manager: &rl,
+            resource: b"mutex".to_vec(),
+            val: vec![1],
+            validity_time: 0,
+            acquired_at: rt::now(),
+            acquired_at_wall: SystemTime::now(),
+            held_since: rt::now(),
+            verified_on: None,
+            fencing_token: None,
+            acquisition: AcquisitionDetails::default(),
+        };
+
+        // Fails before trying to reach the unreachable instance
+        match rl.extend(&lock, Duration::from_millis(1000)).await {
+            Err(LockError::ValidityExpired) => (),
+            r => panic!("Expected LockError::ValidityExpired, got {:?}", r),
+        }
+        assert_eq!(rl.tracker.stats().extend_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lock_maintenance_mode_fails_fast() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let clone = rl.clone();
+        clone.set_maintenance_mode(true);
+        assert!(rl.is_in_maintenance_mode());
+
+        let started = rt::now();
+        match rl.lock(b"mutex", Duration::from_millis(1000)).await {
+            Err(LockError::MaintenanceMode) => (),
+            r => panic!("Expected LockError::MaintenanceMode, got {:?}", r),
+        }
+        assert!(matches!(
+            rl.acquire_no_guard(b"mutex", Duration::from_millis(1000))
+                .await,
+            Err(LockError::MaintenanceMode)
+        ));
+        assert!(rt::elapsed(started) < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_lock_maintenance_mode() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let lock = rl.lock(&key, ttl).await?;
+        rl.set_maintenance_mode(true);
+        assert!(matches!(
+            rl.lock(&rl.get_unique_lock_id()?, ttl).await,
+            Err(LockError::MaintenanceMode)
+        ));
+
+        // Held locks can still be extended and released
+        let lock = rl.extend(&lock, ttl).await?;
+        assert_eq!(rl.unlock(&lock).await, UnlockOutcome::Released);
+
+        rl.set_maintenance_mode(false);
+        rl.lock(&key, ttl).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_unbounded_timeout() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_retry(1, Duration::ZERO);
+        let ttl = Duration::from_millis(1000);
+
+        let options = AcquireOptions::new().timeout(Duration::MAX);
+        assert!(matches!(
+            rl.lock_with_options(b"mutex", ttl, &options).await,
+            Err(LockError::Unavailable)
+        ));
+
+        // Without a deadline, waiting goes on until cancelled
+        let wait = Duration::from_millis(100);
+        let acquire = rl.acquire_timeout(b"mutex", ttl, Duration::MAX);
+        assert!(tokio::time::timeout(wait, acquire).await.is_err());
+        let acquire = rl.acquire_no_guard_with_options(b"mutex", ttl, &options);
+        assert!(tokio::time::timeout(wait, acquire).await.is_err());
+        let free = rl.wait_until_free(b"mutex", Duration::MAX);
+        assert!(tokio::time::timeout(wait, free).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lock_shadow_mode() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        let sink = events.clone();
+        rl.set_event_handler(move |event| sink.lock().unwrap().push(event.clone()));
+        rl.set_retry(1, Duration::from_millis(10));
+        rl.set_shadow_mode(true);
+
+        // The instance is unreachable, but the caller proceeds as if it held the lock
+        let lock = rl
+            .lock(b"mutex", Duration::from_millis(1000))
+            .await
+            .unwrap();
+        assert!(lock.validity_time > 0);
+        let lock = rl.extend(&lock, Duration::from_millis(1000)).await.unwrap();
+        assert_eq!(lock.resource, b"mutex");
+
+        match events.lock().unwrap().as_slice() {
+            [Event::ShadowAcquisition {
+                resource,
+                acquired: false,
+                contended: false,
+                succeeded: 0,
+                ..
+            }] => assert_eq!(resource, b"mutex"),
+            events => panic!("Unexpected events: {:?}", events),
+        }
+
+        assert!(matches!(
+            rl.lock(b"mutex", Duration::MAX).await,
+            Err(LockError::TtlTooLarge | LockError::TtlOutOfRange)
+        ));
+
+        // Errors other than contention and unavailability are not shadowed
+        rl.set_lock_quota(b"jobs:", 0);
+        assert!(matches!(
+            rl.lock(b"jobs:1", Duration::from_millis(1000)).await,
+            Err(LockError::QuotaExceeded { .. })
+        ));
+        rl.set_maintenance_mode(true);
+        assert!(matches!(
+            rl.lock(b"mutex", Duration::from_millis(1000)).await,
+            Err(LockError::MaintenanceMode)
+        ));
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lock_warm_up_unreachable() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_connect_strategy(ConnectStrategy::Eager);
+        assert!(matches!(rl.warm_up().await, Err(LockError::Unavailable)));
+
+        rl.set_connect_strategy(ConnectStrategy::Lazy);
+        assert!(rl.registry.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lock_warm_up() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_connect_strategy(ConnectStrategy::Eager);
+        assert_eq!(rl.warm_up().await?, 3);
+        assert_eq!(rl.registry.as_ref().map(|registry| registry.len()), Some(3));
+
+        let key = rl.get_unique_lock_id()?;
+        rl.lock(&key, Duration::from_millis(1000)).await?;
+        assert_eq!(rl.registry.as_ref().map(|registry| registry.len()), Some(3));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_quota_fails_fast() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_lock_quota(b"jobs:", 0);
+
+        match rl
+            .acquire_no_guard(b"jobs:1", Duration::from_millis(1000))
+            .await
+        {
+            Err(LockError::QuotaExceeded { prefix, limit: 0 }) => assert_eq!(prefix, "jobs:"),
+            r => panic!("Expected LockError::QuotaExceeded, got {:?}", r),
         }
     }
+
+    #[tokio::test]
+    async fn test_lock_quota() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_lock_quota(b"", 3);
+        rl.set_lock_quota(b"jobs:", 2);
+        let clone = rl.clone();
+        let ttl = Duration::from_millis(10_000);
+
+        let first = rl.lock(b"jobs:1", ttl).await?;
+        clone.lock(b"jobs:2", ttl).await?;
+        assert!(matches!(
+            rl.lock(b"jobs:3", ttl).await,
+            Err(LockError::QuotaExceeded { limit: 2, .. })
+        ));
+        rl.lock(b"other", ttl).await?;
+        assert!(matches!(
+            rl.lock(b"more", ttl).await,
+            Err(LockError::QuotaExceeded { limit: 3, .. })
+        ));
+
+        rl.unlock(&first).await;
+        rl.lock(b"jobs:3", ttl).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_max_in_flight() {
+        let mut rl = LockManager::new(vec!["redis://127.0.0.1:6379"]);
+        rl.set_max_in_flight(2);
+
+        let current = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let op = |_: Instance<'_>| async {
+            let n = current.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(n, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            current.fetch_sub(1, Ordering::SeqCst);
+        };
+        join_all((0..5).map(|_| rl.on_all(op))).await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        // A limit of 0 means no limit
+        rl.set_max_in_flight(0);
+        peak.store(0, Ordering::SeqCst);
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            join_all((0..5).map(|_| rl.on_all(op))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(peak.load(Ordering::SeqCst), 5);
+        assert_eq!(rl.max_in_flight, None);
+    }
 }
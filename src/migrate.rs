@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::core;
+use crate::lock::LockManager;
+use crate::rt;
+use crate::transport::{holds_instance, lock_instance, scan_instance, unlock_instance};
+
+/// The result of `migrate_locks`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MigrationReport {
+    /// Resources whose locks now also exist on a quorum of the new instances.
+    pub migrated: Vec<Vec<u8>>,
+    /// Resources whose locks could not be re-created.
+    pub failed: Vec<(Vec<u8>, MigrationFailure)>,
+    /// Number of keys found that are not held as a lock on a quorum of the old instances,
+    /// e.g. fragments of failed acquisitions or keys without expiry. They are not migrated.
+    pub skipped: usize,
+    /// Old instances that could not be scanned. Locks held on them may be missing from the
+    /// report, or be skipped for lacking a quorum.
+    pub unreachable: Vec<usize>,
+}
+
+/// Why a lock could not be re-created by `migrate_locks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MigrationFailure {
+    /// The resource is locked with another value on the new instances.
+    Conflict,
+    /// Fewer than a quorum of the new instances hold the lock after re-creating it.
+    /// It has been removed from the new instances again.
+    NoQuorum,
+    /// The lock expired before it could be re-created.
+    Expired,
+}
+
+// The values of a key on the instances holding it, with their remaining TTLs
+type Values = Vec<(Vec<u8>, Option<Duration>)>;
+
+// A lock held on a quorum of the old instances
+struct Held {
+    resource: Vec<u8>,
+    val: Vec<u8>,
+    remaining: Duration,
+}
+
+/// Re-create the locks held on `old` with keys starting with `prefix` on the instances of `new`.
+///
+/// Supports moving the locking backend to other instances without a global outage:
+/// run the migration, then switch clients over to `new`. Locks keep their values, so their
+/// holders can keep extending and releasing them through `new`, and their remaining TTL,
+/// which is the time until fewer than a quorum of the old instances would still hold them.
+/// Every re-created lock is verified to be held by a quorum of the new instances.
+///
+/// Locks acquired on `old` after the scan are not migrated, and locks migrated but released
+/// on `old` afterwards stay held on `new` until they expire. Running the migration again
+/// picks up new locks, locks that were already migrated are verified again.
+pub async fn migrate_locks(old: &LockManager, new: &LockManager, prefix: &[u8]) -> MigrationReport {
+    let scanned_at = rt::now();
+    let scans = old.on_all(|instance| scan_instance(instance, prefix)).await;
+
+    let mut report = MigrationReport::default();
+    let mut keys: BTreeMap<Vec<u8>, Values> = BTreeMap::new();
+    for (instance, scan) in scans.into_iter().enumerate() {
+        match scan {
+            Ok(found) => {
+                for (key, val, pttl) in found {
+                    keys.entry(key).or_default().push((val, pttl));
+                }
+            }
+            Err(_) => report.unreachable.push(instance),
+        }
+    }
+
+    let quorum = old.quorum() as usize;
+    let mut held = Vec::new();
+    for (resource, entries) in keys {
+        let lock = entries.iter().find_map(|(val, _)| {
+            let mut pttls: Vec<Duration> = entries
+                .iter()
+                .filter(|(v, _)| core::owns(v, val))
+                .filter_map(|(_, pttl)| *pttl)
+                .collect();
+            // The lock is held until fewer than a quorum of instances hold it
+            pttls.sort_by(|a, b| b.cmp(a));
+            let remaining = *pttls.get(quorum - 1)?;
+            Some(Held {
+                resource: resource.clone(),
+                val: val.clone(),
+                remaining,
+            })
+        });
+        match lock {
+            Some(lock) => held.push(lock),
+            None => report.skipped += 1,
+        }
+    }
+
+    for lock in held {
+        match migrate_lock(new, &lock, scanned_at).await {
+            Ok(()) => report.migrated.push(lock.resource),
+            Err(failure) => report.failed.push((lock.resource, failure)),
+        }
+    }
+    report
+}
+
+async fn migrate_lock(
+    new: &LockManager,
+    lock: &Held,
+    scanned_at: Instant,
+) -> Result<(), MigrationFailure> {
+    let remaining = lock.remaining.saturating_sub(rt::elapsed(scanned_at));
+    let ttl = remaining.as_millis() as usize;
+    if ttl == 0 {
+        return Err(MigrationFailure::Expired);
+    }
+
+    let created = new
+        .on_all(|instance| lock_instance(instance, &lock.resource, lock.val.clone(), ttl))
+        .await;
+    // Instances that refused may hold the lock from an earlier migration
+    let holds = new
+        .on_all(|instance| holds_instance(instance, &lock.resource, &lock.val))
+        .await;
+    let holding = holds.iter().filter(|held| matches!(held, Ok(true))).count();
+    if holding >= new.quorum() as usize {
+        return Ok(());
+    }
+
+    new.on_all(|instance| unlock_instance(instance, &lock.resource, &lock.val))
+        .await;
+    let conflicts = created
+        .iter()
+        .zip(&holds)
+        .filter(|(created, held)| matches!(created, Ok(false)) && matches!(held, Ok(false)))
+        .count();
+    if conflicts > 0 {
+        Err(MigrationFailure::Conflict)
+    } else {
+        Err(MigrationFailure::NoQuorum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_migrate_locks() -> Result<()> {
+        let (_old_containers, old_addresses) = create_clients();
+        let (_new_containers, new_addresses) = create_clients();
+
+        let old = LockManager::new(old_addresses);
+        let new = LockManager::new(new_addresses);
+        let other = new.clone();
+        let ttl = Duration::from_millis(10_000);
+
+        let lock = old.lock(b"migrate:a", ttl).await?;
+        old.lock(b"migrate:b", ttl).await?;
+        other.lock(b"migrate:b", ttl).await?;
+
+        let report = migrate_locks(&old, &new, b"migrate:").await;
+        assert_eq!(report.migrated, [b"migrate:a".to_vec()]);
+        assert_eq!(
+            report.failed,
+            [(b"migrate:b".to_vec(), MigrationFailure::Conflict)]
+        );
+        assert!(report.unreachable.is_empty());
+
+        // The holder keeps using its lock through the new manager
+        let lock = crate::lock::Lock {
+            lock_manager: &new,
+            ..lock
+        };
+        assert_eq!(new.verify(&lock).await, 3);
+        assert!(lock.remaining_validity() > Duration::ZERO);
+        new.extend(&lock, ttl).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use crate::core::Tally;
+use crate::event::Event;
+use crate::lock::{Lock, LockManager};
+use crate::rt;
+use crate::transport::holds_instance;
+
+impl LockManager {
+    /// Watch a held lock and return once it is no longer held on a quorum of instances.
+    ///
+    /// Extending a lock only notices its loss when extending. The monitor checks every
+    /// `interval` which instances still hold the lock, so that its loss is noticed early,
+    /// e.g. after instances restarted without persistence and forgot it. Run it alongside
+    /// the work protected by the lock, and stop the work once it returns.
+    ///
+    /// The lock is lost once too many instances answer that they don't hold it for a quorum
+    /// to remain. Unreachable instances are assumed to still hold it, so outages alone don't
+    /// end the monitor. Raises `Event::QuorumLost` and returns the answers of the last check.
+    /// A lock that is released or expires is reported as lost as well.
+    pub async fn monitor_quorum(&self, lock: &Lock<'_>, interval: Duration) -> Tally {
+        loop {
+            let results = self
+                .on_all(|instance| holds_instance(instance, &lock.resource, &lock.val))
+                .await;
+            let tally = Tally::count(results);
+            if tally.is_lost(self.quorum()) {
+                self.emit(Event::QuorumLost {
+                    resource: lock.resource.clone(),
+                    holding: tally.succeeded,
+                });
+                return tally;
+            }
+            rt::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_monitor_quorum() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut rl = LockManager::new(addresses.clone());
+        let sink = events.clone();
+        rl.set_event_handler(move |event| sink.lock().unwrap().push(event.clone()));
+        let key = rl.get_unique_lock_id()?;
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+
+        let monitor = rl.monitor_quorum(&lock, Duration::from_millis(50));
+        let restart = async {
+            rt::sleep(Duration::from_millis(100)).await;
+            for server in &rl.servers[..2] {
+                let mut con = server.get_connection()?;
+                redis::cmd("DEL").arg(&key).execute(&mut con);
+            }
+            anyhow::Ok(())
+        };
+        let (tally, restarted) = futures::join!(monitor, restart);
+        restarted?;
+
+        assert_eq!(tally.succeeded, 1);
+        assert_eq!(tally.rejected, 2);
+        assert!(matches!(
+            &events.lock().unwrap()[..],
+            [Event::QuorumLost { holding: 1, .. }]
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use redis::{ErrorKind, FromRedisValue, RedisError, ToRedisArgs, Value};
+
+use crate::core::UnlockOutcome;
+use crate::lock::{Bytes, Lock, LockError, LockGuard, LockManager};
+use crate::resource::LockResource;
+
+/// A value stored in Redis, guarded by a lock on its resource.
+///
+/// Pairs a resource with the key holding the data, so that the data is only read and
+/// written while holding the lock: `mutex.lock().await?` returns a guard to `get` and `set`
+/// the value through. The value is stored next to the lock on every instance, and reads
+/// and writes check ownership of the lock in the same script call, see `LockManager::locked_get`.
+///
+/// Values are encoded with redis-rs's `ToRedisArgs` and `FromRedisValue`,
+/// and must encode as a single argument, as strings, bytes and numbers do.
+pub struct DistributedMutex<T> {
+    lock_manager: LockManager,
+    resource: Vec<u8>,
+    key: Vec<u8>,
+    ttl: Duration,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> DistributedMutex<T>
+where
+    T: ToRedisArgs + FromRedisValue,
+{
+    /// A mutex locking `resource` for `ttl`, storing its value at `<resource>:data`.
+    pub fn new<R: LockResource + ?Sized>(
+        lock_manager: LockManager,
+        resource: &R,
+        ttl: Duration,
+    ) -> DistributedMutex<T> {
+        let resource = resource.key().into_owned();
+        let key = [&resource[..], b":data"].concat();
+        DistributedMutex {
+            lock_manager,
+            resource,
+            key,
+            ttl,
+            value: PhantomData,
+        }
+    }
+
+    /// The key the value is stored at.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Wait until the lock is acquired, see `LockManager::acquire_no_guard`.
+    ///
+    /// The lock is not extended, so the guard is only valid for the mutex's TTL.
+    pub async fn lock(&self) -> Result<DistributedMutexGuard<'_, T>, LockError> {
+        let lock = self
+            .lock_manager
+            .acquire_no_guard(&self.resource, self.ttl)
+            .await?;
+        Ok(DistributedMutexGuard {
+            guard: LockGuard { lock },
+            mutex: self,
+        })
+    }
+
+    /// Try to acquire the lock like `LockManager::lock`, failing if the resource stays held.
+    pub async fn try_lock(&self) -> Result<DistributedMutexGuard<'_, T>, LockError> {
+        let lock = self.lock_manager.lock(&self.resource, self.ttl).await?;
+        Ok(DistributedMutexGuard {
+            guard: LockGuard { lock },
+            mutex: self,
+        })
+    }
+}
+
+impl<T> fmt::Debug for DistributedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DistributedMutex")
+            .field("resource", &format_args!("{}", Bytes(&self.resource)))
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// Access to the value of a `DistributedMutex` while holding its lock.
+///
+/// Dropping the guard releases the lock like dropping a `LockGuard` does.
+pub struct DistributedMutexGuard<'a, T> {
+    guard: LockGuard<'a>,
+    mutex: &'a DistributedMutex<T>,
+}
+
+impl<'a, T> DistributedMutexGuard<'a, T>
+where
+    T: ToRedisArgs + FromRedisValue,
+{
+    /// The lock held.
+    pub fn lock(&self) -> &Lock<'a> {
+        &self.guard.lock
+    }
+
+    /// Read the value, `None` if it was never set.
+    ///
+    /// Fails with `LockError::LockLost` if the lock has expired.
+    pub async fn get(&self) -> Result<Option<T>, LockError> {
+        let value = self
+            .mutex
+            .lock_manager
+            .locked_get(&self.guard.lock, &self.mutex.key)
+            .await?;
+        value
+            .map(|value| T::from_redis_value(&Value::Data(value)))
+            .transpose()
+            .map_err(LockError::Redis)
+    }
+
+    /// Write the value.
+    ///
+    /// Fails with `LockError::LockLost` if the lock has expired.
+    pub async fn set(&self, value: &T) -> Result<(), LockError> {
+        let mut args = value.to_redis_args();
+        if args.len() != 1 {
+            return Err(LockError::Redis(RedisError::from((
+                ErrorKind::TypeError,
+                "Value does not encode as a single argument",
+            ))));
+        }
+        let value = args.pop().expect("checked above");
+        self.mutex
+            .lock_manager
+            .locked_set(&self.guard.lock, &self.mutex.key, &value)
+            .await
+    }
+
+    /// Release the lock.
+    pub async fn unlock(self) -> UnlockOutcome {
+        let lock = self.guard.into_lock();
+        lock.lock_manager.unlock(&lock).await
+    }
+}
+
+impl<T> fmt::Debug for DistributedMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DistributedMutexGuard")
+            .field("lock", &self.guard.lock)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_distributed_mutex() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let resource = rl.get_unique_lock_id()?;
+        let mutex = DistributedMutex::<i64>::new(rl, &resource, Duration::from_millis(10_000));
+
+        let guard = mutex.lock().await?;
+        assert_eq!(guard.get().await?, None);
+        guard.set(&41).await?;
+        assert_eq!(guard.get().await?, Some(41));
+        assert!(mutex.try_lock().await.is_err());
+        assert_eq!(guard.unlock().await, UnlockOutcome::Released);
+
+        let guard = mutex.try_lock().await?;
+        assert_eq!(guard.get().await?, Some(41));
+        guard.unlock().await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use futures::Future;
+
+use crate::lock::{AcquireOptions, LockError, LockManager};
+use crate::resource::LockResource;
+
+impl LockManager {
+    /// Run `f` while holding the lock for the given resource, or `cas_f` if the resource
+    /// stays contended.
+    ///
+    /// Up to `set_contention_threshold` attempts are made to acquire the lock. If all of them
+    /// fail, `cas_f` runs instead, without the lock. It is expected to perform the same
+    /// read-modify-write optimistically, e.g. with `WATCH`/`MULTI`/`EXEC` on the data being
+    /// modified, retrying on conflicts itself. This suits workloads that are mostly uncontended,
+    /// but whose hot resources would otherwise queue up on the lock.
+    ///
+    /// `cas_f` also runs if the lock instances cannot be reached, as it does not depend on them.
+    /// The lock is not extended while `f` runs and released afterwards, so `f` should
+    /// complete within `ttl`.
+    pub async fn with_lock_or_optimistic<R, F, Fut, C, CFut, T>(
+        &self,
+        resource: &R,
+        ttl: Duration,
+        f: F,
+        cas_f: C,
+    ) -> Result<T, LockError>
+    where
+        R: LockResource + ?Sized,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+        C: FnOnce() -> CFut,
+        CFut: Future<Output = T>,
+    {
+        let options = AcquireOptions::new().retry(self.contention_threshold(), self.retry_delay());
+        match self.lock_with_options(resource, ttl, &options).await {
+            Ok(lock) => {
+                let output = f().await;
+                self.unlock(&lock).await;
+                Ok(output)
+            }
+            Err(LockError::Unavailable | LockError::Contended { .. }) => Ok(cas_f().await),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_with_lock_or_optimistic() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_retry(3, Duration::from_millis(10));
+        rl.set_contention_threshold(1);
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let path = rl
+            .with_lock_or_optimistic(&key, ttl, || async { "lock" }, || async { "cas" })
+            .await?;
+        assert_eq!(path, "lock");
+
+        let lock = rl.lock(&key, ttl).await?;
+        let path = rl
+            .with_lock_or_optimistic(&key, ttl, || async { "lock" }, || async { "cas" })
+            .await?;
+        assert_eq!(path, "cas");
+        rl.unlock(&lock).await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::core::UnlockOutcome;
+use crate::lock::{release_in_tokio, AcquisitionDetails, Lock, LockError, LockGuard, LockManager};
+use crate::resource::LockResource;
+use crate::rt;
+
+/// A lock that owns a handle to its lock manager instead of borrowing it.
+///
+/// Unlike `Lock`, it is `'static`, so it can be stored in structs and moved into spawned
+/// tasks. Create it with `LockManager::lock_owned` or `Lock::into_owned`. The manager
+/// handle is a clone, which shares the connections, statistics and renewals of the manager
+/// it was created from. `as_lock` borrows it as a `Lock` for the other `LockManager` methods.
+#[derive(Clone)]
+pub struct OwnedLock {
+    lock_manager: LockManager,
+    pub resource: Vec<u8>,
+    pub val: Vec<u8>,
+    /// See `Lock::validity_time`.
+    pub validity_time: usize,
+    pub acquired_at: Instant,
+    pub acquired_at_wall: SystemTime,
+    pub held_since: Instant,
+    pub verified_on: Option<u32>,
+    pub fencing_token: Option<u64>,
+    pub acquisition: AcquisitionDetails,
+}
+
+impl OwnedLock {
+    /// The manager the lock was acquired through.
+    pub fn lock_manager(&self) -> &LockManager {
+        &self.lock_manager
+    }
+
+    /// Borrow the lock as a `Lock`.
+    pub fn as_lock(&self) -> Lock<'_> {
+        Lock {
+            lock_manager: &self.lock_manager,
+            resource: self.resource.clone(),
+            val: self.val.clone(),
+            validity_time: self.validity_time,
+            acquired_at: self.acquired_at,
+            acquired_at_wall: self.acquired_at_wall,
+            held_since: self.held_since,
+            verified_on: self.verified_on,
+            fencing_token: self.fencing_token,
+            acquisition: self.acquisition,
+        }
+    }
+
+    /// See `Lock::remaining_validity`.
+    pub fn remaining_validity(&self) -> Duration {
+        self.as_lock().remaining_validity()
+    }
+
+    /// Extend the lock to `ttl` in place, see `LockManager::extend`.
+    pub async fn extend(&mut self, ttl: Duration) -> Result<(), LockError> {
+        *self = self
+            .lock_manager
+            .extend(&self.as_lock(), ttl)
+            .await?
+            .into_owned();
+        Ok(())
+    }
+
+    /// Release the lock, see `LockManager::unlock`.
+    pub async fn unlock(&self) -> UnlockOutcome {
+        self.lock_manager.unlock(&self.as_lock()).await
+    }
+
+    /// Place the lock in a guard that releases it when dropped.
+    pub fn into_guard(self) -> OwnedLockGuard {
+        OwnedLockGuard { lock: self }
+    }
+}
+
+/// A guard for an `OwnedLock`, releasing it when dropped.
+///
+/// Unlike `LockGuard`, it does not borrow the manager, so it can be moved into spawned tasks.
+/// Dropping the guard releases the lock like dropping a `LockGuard`: in a spawned task within
+/// a tokio runtime, synchronously outside of one. `release` releases the lock right away.
+pub struct OwnedLockGuard {
+    pub lock: OwnedLock,
+}
+
+impl OwnedLockGuard {
+    /// Release the lock now, see `LockManager::unlock`.
+    pub async fn release(self) -> UnlockOutcome {
+        let lock = self.into_lock();
+        lock.unlock().await
+    }
+
+    /// Disarm the guard, returning the lock without releasing it.
+    pub fn into_lock(mut self) -> OwnedLock {
+        OwnedLock {
+            resource: std::mem::take(&mut self.lock.resource),
+            val: std::mem::take(&mut self.lock.val),
+            ..self.lock.clone()
+        }
+    }
+}
+
+impl Drop for OwnedLockGuard {
+    fn drop(&mut self) {
+        // Disarmed by `into_lock`
+        if self.lock.val.is_empty() {
+            return;
+        }
+        let lock = self.lock.as_lock();
+        if !release_in_tokio(&[&lock]) {
+            rt::block_on(self.lock.unlock());
+        }
+    }
+}
+
+impl fmt::Debug for OwnedLockGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedLockGuard")
+            .field("lock", &self.lock)
+            .finish()
+    }
+}
+
+impl LockGuard<'_> {
+    /// Turn the guard into an `OwnedLockGuard`, which does not borrow the manager.
+    pub fn into_owned(self) -> OwnedLockGuard {
+        self.into_lock().into_owned().into_guard()
+    }
+}
+
+impl fmt::Display for OwnedLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_lock().fmt(f)
+    }
+}
+
+impl fmt::Debug for OwnedLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_lock(), f)
+    }
+}
+
+impl Lock<'_> {
+    /// Turn the lock into an `OwnedLock`, which does not borrow the manager.
+    pub fn into_owned(self) -> OwnedLock {
+        OwnedLock {
+            lock_manager: self.lock_manager.clone(),
+            resource: self.resource,
+            val: self.val,
+            validity_time: self.validity_time,
+            acquired_at: self.acquired_at,
+            acquired_at_wall: self.acquired_at_wall,
+            held_since: self.held_since,
+            verified_on: self.verified_on,
+            fencing_token: self.fencing_token,
+            acquisition: self.acquisition,
+        }
+    }
+}
+
+impl LockManager {
+    /// Like `lock`, but returning an `OwnedLock`.
+    pub async fn lock_owned<R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<OwnedLock, LockError> {
+        Ok(self.lock(resource, ttl).await?.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+    use crate::lock::AcquireOptions;
+
+    fn is_static_send<T: Send + Sync + 'static>() {}
+
+    #[test]
+    fn test_owned_lock_is_static() {
+        is_static_send::<OwnedLock>();
+        is_static_send::<OwnedLockGuard>();
+    }
+
+    #[tokio::test]
+    async fn test_owned_lock_guard_in_task() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let guard = rl.lock_owned(&key, ttl).await?.into_guard();
+        let lock = guard.lock.clone();
+        tokio::spawn(async move {
+            assert!(guard.lock.remaining_validity() > Duration::ZERO);
+            drop(guard);
+        })
+        .await?;
+
+        // The release is spawned on drop
+        rl.acquire_no_guard_with_options(&key, ttl, &AcquireOptions::new().timeout(ttl))
+            .await?;
+        assert_eq!(lock.unlock().await, UnlockOutcome::NotOwned);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_owned_lock_in_task() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let mut lock = rl.lock_owned(&key, ttl).await?;
+        let lock = tokio::spawn(async move { lock.extend(ttl).await.map(|()| lock) }).await??;
+        assert_eq!(rl.verify(&lock.as_lock()).await, 3);
+
+        let outcome = tokio::spawn(async move { lock.unlock().await }).await?;
+        assert_eq!(outcome, UnlockOutcome::Released);
+        rl.lock(&key, ttl).await?;
+
+        Ok(())
+    }
+}
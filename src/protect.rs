@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use futures::Future;
+
+use crate::lock::{Lock, LockError, LockGuard, LockManager};
+use crate::resource::LockResource;
+
+/// Run `fut` under the lock held by `guard`, releasing the lock however `fut` ends.
+///
+/// Once `fut` completes, with whatever output, the lock is released before its output
+/// is returned. If the returned future is dropped before that, e.g. by a timeout or `select!`,
+/// the guard is dropped with it and releases the lock in the background, see `LockGuard`.
+///
+/// Unlike `LockManager::run_exclusive`, acquiring the lock is left to the caller,
+/// so the lock can be acquired in one place and the work run in another. The lock is not
+/// extended, so `fut` should complete within its validity.
+pub async fn protect<F: Future>(guard: LockGuard<'_>, fut: F) -> F::Output {
+    let output = fut.await;
+    // Disarmed only once released, so that cancelling the release still releases on drop
+    let lock = &guard.lock;
+    lock.lock_manager.unlock(lock).await;
+    guard.into_lock();
+    output
+}
+
+impl LockManager {
+    /// Acquire the lock for the given resource, run the future returned by `f` with it and
+    /// release the lock afterwards, returning the future's output.
+    ///
+    /// The lock is released whether the future completes, panics or is cancelled, see `protect`.
+    /// Fails like `lock` if the lock cannot be acquired, in which case `f` is not called.
+    /// The lock is not extended, so the future should complete within its validity.
+    pub async fn with_lock<'a, R, F, Fut, T>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        f: F,
+    ) -> Result<T, LockError>
+    where
+        R: LockResource + ?Sized,
+        F: FnOnce(Lock<'a>) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let lock = self.lock(resource, ttl).await?;
+        let guard = LockGuard { lock: lock.clone() };
+        Ok(protect(guard, f(lock)).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::AssertUnwindSafe;
+
+    use anyhow::Result;
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_protect() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let rl2 = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let guard = LockGuard {
+            lock: rl.lock(&key, ttl).await?,
+        };
+        let output = protect(guard, async { rl2.lock(&key, ttl).await.is_err() }).await;
+        assert!(output, "resource should be locked while the future runs");
+        let lock = rl2.lock(&key, ttl).await?;
+        rl2.unlock(&lock).await;
+
+        // Cancelled while running
+        let guard = LockGuard {
+            lock: rl.lock(&key, ttl).await?,
+        };
+        let pending = protect(guard, futures::future::pending::<()>());
+        assert!(tokio::time::timeout(Duration::from_millis(50), pending)
+            .await
+            .is_err());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        rl2.lock(&key, ttl).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_lock() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let rl2 = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let resource = rl
+            .with_lock(&key, ttl, |lock| async move { lock.resource })
+            .await?;
+        assert_eq!(resource, key);
+        let lock = rl2.lock(&key, ttl).await?;
+        rl2.unlock(&lock).await;
+
+        // Released when the future panics
+        let panicked = AssertUnwindSafe(rl.with_lock(&key, ttl, |_| async { panic!("failed") }))
+            .catch_unwind()
+            .await;
+        assert!(panicked.is_err());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        rl2.lock(&key, ttl).await?;
+
+        Ok(())
+    }
+}
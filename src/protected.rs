@@ -0,0 +1,128 @@
+use crate::lock::{Lock, LockError, LockManager};
+use crate::transport::{locked_get_instance, locked_incr_instance, locked_set_instance};
+
+impl LockManager {
+    /// Increment the integer stored at `key` by `delta`, on every instance that still holds `lock`.
+    ///
+    /// Ownership is checked and the key updated by a single script call per instance,
+    /// so the lock cannot expire in between. Instances that missed earlier updates may
+    /// diverge, the highest of the new values is returned.
+    ///
+    /// Fails with `LockError::LockLost` if fewer than a quorum of instances hold the lock.
+    /// The instances that do hold it have been updated nonetheless.
+    pub async fn locked_incr(
+        &self,
+        lock: &Lock<'_>,
+        key: &[u8],
+        delta: i64,
+    ) -> Result<i64, LockError> {
+        let values: Vec<i64> = self
+            .on_all(|instance| locked_incr_instance(instance, lock, key, delta))
+            .await
+            .into_iter()
+            .filter_map(|result| result.ok().flatten())
+            .collect();
+
+        if (values.len() as u32) < self.quorum() {
+            return Err(LockError::LockLost);
+        }
+        Ok(values.into_iter().max().expect("quorum is at least one"))
+    }
+
+    /// Set `key` to `value` on every instance that still holds `lock`.
+    ///
+    /// Like `locked_incr`, ownership is checked in the same script call that sets the key.
+    ///
+    /// Fails with `LockError::LockLost` if fewer than a quorum of instances hold the lock.
+    pub async fn locked_set(
+        &self,
+        lock: &Lock<'_>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), LockError> {
+        let set = self
+            .on_all(|instance| locked_set_instance(instance, lock, key, value, None))
+            .await
+            .into_iter()
+            .filter(|result| matches!(result, Ok(true)))
+            .count();
+
+        if (set as u32) < self.quorum() {
+            return Err(LockError::LockLost);
+        }
+        Ok(())
+    }
+
+    /// Read `key` from every instance that still holds `lock`.
+    ///
+    /// Instances that missed earlier updates may diverge, the value read from the most
+    /// instances is returned, `None` standing for a missing key.
+    ///
+    /// Fails with `LockError::LockLost` if fewer than a quorum of instances hold the lock.
+    pub async fn locked_get(
+        &self,
+        lock: &Lock<'_>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, LockError> {
+        let values: Vec<Option<Vec<u8>>> = self
+            .on_all(|instance| locked_get_instance(instance, lock, key))
+            .await
+            .into_iter()
+            .filter_map(|result| result.ok().flatten())
+            .collect();
+
+        if (values.len() as u32) < self.quorum() {
+            return Err(LockError::LockLost);
+        }
+        let count = |value: &Option<Vec<u8>>| values.iter().filter(|v| *v == value).count();
+        Ok(values
+            .iter()
+            .max_by_key(|value| count(value))
+            .cloned()
+            .expect("quorum is at least one"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_locked_incr_set_and_get() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let counter = rl.get_unique_lock_id()?;
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+
+        assert_eq!(rl.locked_incr(&lock, &counter, 2).await?, 2);
+        assert_eq!(rl.locked_incr(&lock, &counter, 3).await?, 5);
+        rl.locked_set(&lock, &counter, b"10").await?;
+        assert_eq!(rl.locked_incr(&lock, &counter, 1).await?, 11);
+        assert_eq!(rl.locked_get(&lock, &counter).await?, Some(b"11".to_vec()));
+        assert_eq!(rl.locked_get(&lock, &key).await?, Some(lock.val.clone()));
+        assert_eq!(rl.locked_get(&lock, b"missing").await?, None);
+
+        rl.unlock(&lock).await;
+        match rl.locked_incr(&lock, &counter, 1).await {
+            Err(LockError::LockLost) => (),
+            r => panic!("Expected LockError::LockLost, got {:?}", r),
+        }
+        match rl.locked_set(&lock, &counter, b"0").await {
+            Err(LockError::LockLost) => (),
+            r => panic!("Expected LockError::LockLost, got {:?}", r),
+        }
+        match rl.locked_get(&lock, &counter).await {
+            Err(LockError::LockLost) => (),
+            r => panic!("Expected LockError::LockLost, got {:?}", r),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use crate::event::Event;
+use crate::lock::{LockError, LockManager};
+use crate::transport::{denied_command, Instance, SCRIPTS};
+
+// `SET` with `NX` and `PX` was introduced in Redis 2.6.12
+const MIN_VERSION: (u32, u32, u32) = (2, 6, 12);
+
+/// Capabilities of the configured Redis instances, returned by `LockManager::provision`.
+#[derive(Debug, Clone)]
+pub struct CapabilityReport {
+    pub instances: Vec<InstanceCapabilities>,
+}
+
+impl CapabilityReport {
+    /// Number of instances that can take part in locking.
+    pub fn usable(&self) -> usize {
+        self.instances.iter().filter(|i| i.is_usable()).count()
+    }
+}
+
+/// Capabilities of a single Redis instance.
+#[derive(Debug, Clone)]
+pub struct InstanceCapabilities {
+    pub address: String,
+    /// Server version as reported by `INFO server`, `None` if it could not be determined.
+    pub version: Option<String>,
+    /// Whether the lock scripts could be loaded, i.e. Lua scripting is available and permitted.
+    /// Instances denying scripts are usable without them, see `LockManager::set_scriptless`.
+    pub scripting: bool,
+    /// Whether the server speaks RESP3 (Redis 6 and later).
+    pub resp3: bool,
+    /// The configured `maxmemory-policy`, `None` if `CONFIG GET` is not permitted.
+    pub maxmemory_policy: Option<String>,
+    /// Why the instance cannot be used, if it can't.
+    pub problem: Option<String>,
+}
+
+impl InstanceCapabilities {
+    pub fn is_usable(&self) -> bool {
+        self.problem.is_none()
+    }
+
+    /// Whether the instance may evict lock keys before they expire.
+    ///
+    /// Lock keys always have a TTL, so besides the `allkeys-*` policies the `volatile-*` ones
+    /// may evict them too when memory runs out. Only `noeviction` is safe.
+    pub fn may_evict_locks(&self) -> bool {
+        self.maxmemory_policy
+            .as_deref()
+            .is_some_and(|policy| policy != "noeviction")
+    }
+}
+
+impl LockManager {
+    /// Check every instance for compatibility and upload the lock scripts.
+    ///
+    /// Verifies that the instances are reachable, recent enough and allow Lua scripting,
+    /// and preloads the scripts so the first lock operations don't have to send them.
+    /// Fails with `LockError::Incompatible` if fewer than a quorum of instances are usable,
+    /// so deployments can fail fast at startup.
+    ///
+    /// Instances whose `maxmemory-policy` may evict lock keys raise `Event::EvictionPolicy`,
+    /// or are considered unusable if `LockManager::set_require_noeviction` is enabled.
+    ///
+    /// If a usable instance denies loading the scripts, the manager and its clones switch
+    /// to unlocking and extending without them, see `LockManager::set_scriptless`.
+    /// Runs `INFO` and `CONFIG GET`, which `LockManager::required_acl` includes. Without
+    /// `CONFIG GET` the eviction policy is unknown and not checked.
+    pub async fn provision(&self) -> Result<CapabilityReport, LockError> {
+        let mut report = CapabilityReport {
+            instances: self.on_all(provision_instance).await,
+        };
+
+        for instance in &mut report.instances {
+            if !instance.may_evict_locks() {
+                continue;
+            }
+            let policy = instance.maxmemory_policy.clone().unwrap_or_default();
+            if self.requires_noeviction() {
+                instance.problem.get_or_insert_with(|| {
+                    format!("maxmemory-policy {} may evict lock keys", policy)
+                });
+            } else {
+                self.emit(Event::EvictionPolicy {
+                    address: instance.address.clone(),
+                    policy,
+                });
+            }
+        }
+
+        if report
+            .instances
+            .iter()
+            .any(|instance| instance.is_usable() && !instance.scripting)
+        {
+            self.set_scriptless(true);
+        }
+
+        if report.usable() < self.quorum() as usize {
+            let problems = report
+                .instances
+                .iter()
+                .filter_map(|i| Some(format!("{}: {}", i.address, i.problem.as_ref()?)))
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(LockError::Incompatible(problems));
+        }
+
+        Ok(report)
+    }
+}
+
+async fn provision_instance(instance: Instance<'_>) -> InstanceCapabilities {
+    let mut capabilities = InstanceCapabilities {
+        address: instance.client.get_connection_info().addr.to_string(),
+        version: None,
+        scripting: false,
+        resp3: false,
+        maxmemory_policy: None,
+        problem: None,
+    };
+
+    let mut con = match instance.connect().await {
+        Ok(con) => con,
+        Err(e) => {
+            capabilities.problem = Some(format!("unreachable ({})", e));
+            return capabilities;
+        }
+    };
+
+    let info: redis::RedisResult<String> =
+        redis::cmd("INFO").arg("server").query_async(&mut con).await;
+    capabilities.version = info.ok().and_then(|info| {
+        info.lines()
+            .find_map(|line| line.strip_prefix("redis_version:"))
+            .map(|version| version.trim().to_string())
+    });
+    let version = capabilities.version.as_deref().and_then(parse_version);
+    capabilities.resp3 = version.is_some_and(|v| v >= (6, 0, 0));
+
+    let policy: redis::RedisResult<Vec<String>> = redis::cmd("CONFIG")
+        .arg("GET")
+        .arg("maxmemory-policy")
+        .query_async(&mut con)
+        .await;
+    capabilities.maxmemory_policy = policy.ok().and_then(|mut pair| pair.pop());
+
+    let mut scripts_error = None;
+    for script in SCRIPTS {
+        let loaded: redis::RedisResult<String> = redis::cmd("SCRIPT")
+            .arg("LOAD")
+            .arg(*script)
+            .query_async(&mut con)
+            .await;
+        if let Err(e) = loaded {
+            scripts_error = Some(e);
+            break;
+        }
+    }
+    capabilities.scripting = scripts_error.is_none();
+
+    capabilities.problem = match (version, scripts_error) {
+        (Some(v), _) if v < MIN_VERSION => Some(format!(
+            "Redis {} is too old, at least {}.{}.{} is required",
+            capabilities.version.as_deref().unwrap_or_default(),
+            MIN_VERSION.0,
+            MIN_VERSION.1,
+            MIN_VERSION.2
+        )),
+        // Locks work without scripts where the ACL denies them
+        (_, Some(e)) if denied_command(&e).is_some() => None,
+        (_, Some(e)) => Some(format!("scripts could not be loaded ({})", e)),
+        _ => None,
+    };
+
+    capabilities
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.split('.').map(|part| part.parse().ok());
+    Some((
+        parts.next()??,
+        parts.next()??,
+        parts.next().flatten().unwrap_or(0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("7.2.4"), Some((7, 2, 4)));
+        assert_eq!(parse_version("2.6"), Some((2, 6, 0)));
+        assert_eq!(parse_version("unknown"), None);
+        assert!(parse_version("2.6.11").unwrap() < MIN_VERSION);
+    }
+
+    #[test]
+    fn test_may_evict_locks() {
+        let mut capabilities = InstanceCapabilities {
+            address: "127.0.0.1:6379".to_string(),
+            version: Some("7.2.4".to_string()),
+            scripting: true,
+            resp3: true,
+            maxmemory_policy: None,
+            problem: None,
+        };
+        assert!(!capabilities.may_evict_locks());
+
+        capabilities.maxmemory_policy = Some("noeviction".to_string());
+        assert!(!capabilities.may_evict_locks());
+
+        capabilities.maxmemory_policy = Some("allkeys-lru".to_string());
+        assert!(capabilities.may_evict_locks());
+
+        capabilities.maxmemory_policy = Some("volatile-ttl".to_string());
+        assert!(capabilities.may_evict_locks());
+    }
+
+    #[tokio::test]
+    async fn test_provision_unreachable() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+
+        match rl.provision().await {
+            Err(LockError::Incompatible(problems)) => {
+                assert!(
+                    problems.starts_with("127.0.0.1:1: unreachable"),
+                    "{}",
+                    problems
+                )
+            }
+            r => panic!("Expected LockError::Incompatible, got {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provision_scriptless() -> anyhow::Result<()> {
+        use std::time::Duration;
+
+        use crate::core::UnlockOutcome;
+        use crate::lock::tests::create_clients;
+
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        for server in &rl.servers {
+            let mut con = server.get_connection()?;
+            redis::cmd("ACL")
+                .arg("SETUSER")
+                .arg("noscripts")
+                .arg("on")
+                .arg(">secret")
+                .arg("~*")
+                .arg("+@all")
+                .arg("-eval")
+                .arg("-evalsha")
+                .arg("-script")
+                .execute(&mut con);
+        }
+        let restricted = LockManager::new(
+            addresses
+                .iter()
+                .map(|address| address.replace("redis://", "redis://noscripts:secret@"))
+                .collect(),
+        );
+
+        let report = restricted.provision().await?;
+        assert!(report
+            .instances
+            .iter()
+            .all(|i| i.is_usable() && !i.scripting));
+        assert!(restricted.dump_state().await.config.scriptless);
+
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+        let lock = restricted.lock(&key, ttl).await?;
+        let lock = restricted.extend(&lock, ttl).await?;
+        assert!(rl.lock(&key, ttl).await.is_err());
+        assert_eq!(restricted.unlock(&lock).await, UnlockOutcome::Released);
+
+        // Another client's lock is left alone
+        let other = rl.lock(&key, ttl).await?;
+        assert_eq!(restricted.unlock(&lock).await, UnlockOutcome::NotOwned);
+        rl.unlock(&other).await;
+
+        Ok(())
+    }
+}
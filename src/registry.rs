@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use redis::aio::MultiplexedConnection;
+use redis::{Client, ConnectionInfo, RedisResult};
+
+use crate::transport::set_name;
+
+/// Multiplexed connections shared by the lock managers using the registry,
+/// keyed by connection info.
+///
+/// Without a registry, a lock manager opens a connection per operation and instance.
+/// Managers using the same registry instead send all commands for an instance over a single
+/// multiplexed connection, which is opened on first use and replaced once it breaks.
+/// See `LockManager::set_connection_registry`.
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    connections: Mutex<HashMap<String, MultiplexedConnection>>,
+}
+
+impl ConnectionRegistry {
+    /// Create an empty registry, for sharing connections among the managers of one subsystem.
+    pub fn new() -> ConnectionRegistry {
+        ConnectionRegistry::default()
+    }
+
+    /// The process-wide registry.
+    pub fn global() -> Arc<ConnectionRegistry> {
+        static GLOBAL: OnceLock<Arc<ConnectionRegistry>> = OnceLock::new();
+        GLOBAL.get_or_init(Arc::default).clone()
+    }
+
+    fn connections(&self) -> MutexGuard<'_, HashMap<String, MultiplexedConnection>> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Number of open connections.
+    pub fn len(&self) -> usize {
+        self.connections().len()
+    }
+
+    /// Whether no connection is open.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Connections are named with `CLIENT SETNAME` when opened, so the name is part of the key
+    pub(crate) async fn connection(
+        &self,
+        client: &Client,
+        name: Option<&str>,
+    ) -> RedisResult<MultiplexedConnection> {
+        let key = key(client.get_connection_info(), name);
+        if let Some(con) = self.connections().get(&key) {
+            return Ok(con.clone());
+        }
+
+        let mut con = client.get_multiplexed_async_connection().await?;
+        if let Some(name) = name {
+            set_name(&mut con, name).await?;
+        }
+        // Another task may have connected in the meantime, keep the first connection
+        Ok(self.connections().entry(key).or_insert(con).clone())
+    }
+
+    // Forgets a broken connection, so that the next operation reconnects
+    pub(crate) fn evict(&self, client: &Client, name: Option<&str>) {
+        let key = key(client.get_connection_info(), name);
+        self.connections().remove(&key);
+    }
+}
+
+impl fmt::Debug for ConnectionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionRegistry")
+            .field("connections", &self.len())
+            .finish()
+    }
+}
+
+// Connections may only be shared if they authenticate as the same user, select the same database
+// and have the same name
+fn key(info: &ConnectionInfo, name: Option<&str>) -> String {
+    format!(
+        "{}/{}/{:?}/{:?}/{:?}",
+        info.addr, info.redis.db, info.redis.username, info.redis.password, name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+    use crate::lock::LockManager;
+
+    #[tokio::test]
+    async fn test_registry_shares_connections() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let registry = Arc::new(ConnectionRegistry::new());
+        let mut rl = LockManager::new(addresses.clone());
+        let mut rl2 = LockManager::new(addresses.clone());
+        rl.set_connection_registry(registry.clone());
+        rl2.set_connection_registry(registry.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        assert!(rl2.lock(&key, Duration::from_millis(10_000)).await.is_err());
+        rl.unlock(&lock).await;
+        rl2.lock(&key, Duration::from_millis(10_000)).await?;
+
+        assert_eq!(registry.len(), 3);
+
+        Ok(())
+    }
+}
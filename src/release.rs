@@ -0,0 +1,75 @@
+use tokio::sync::mpsc;
+
+use crate::lock::{Lock, ReleaseHandle};
+use crate::rt;
+
+/// Releases locks in a background task, for synchronous contexts such as `Drop` impls.
+///
+/// Enqueuing a release never blocks, so unlike dropping a `LockGuard` it is safe inside
+/// destructors running on an async runtime. The service is cheap to clone and keeps running
+/// until all of its clones are dropped and every enqueued release has been performed.
+/// Like `LockManager::unlock`, releasing is best effort.
+#[derive(Debug, Clone)]
+pub struct ReleaseService {
+    sender: mpsc::UnboundedSender<ReleaseHandle>,
+}
+
+impl ReleaseService {
+    /// Start the background task.
+    ///
+    /// If the `tokio-comp` flag is enabled, this must be called from within a tokio runtime.
+    pub fn spawn() -> ReleaseService {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<ReleaseHandle>();
+        rt::spawn(async move {
+            while let Some(handle) = receiver.recv().await {
+                // Releases run concurrently, so an unreachable instance doesn't hold up the queue
+                rt::spawn(async move {
+                    handle.release().await;
+                });
+            }
+        });
+
+        ReleaseService { sender }
+    }
+
+    /// Enqueue the release of the lock the handle belongs to.
+    pub fn release(&self, handle: ReleaseHandle) {
+        // The receiver lives as long as any sender
+        let _ = self.sender.send(handle);
+    }
+
+    /// Enqueue the release of the given lock.
+    pub fn release_lock(&self, lock: &Lock<'_>) {
+        self.release(lock.split_release_handle())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+    use crate::lock::LockManager;
+
+    #[tokio::test]
+    async fn test_release_service() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let service = ReleaseService::spawn();
+        let key = rl.get_unique_lock_id()?;
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+
+        service.release_lock(&lock);
+        for _ in 0..50 {
+            if rl.verify(&lock).await == 0 {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Lock was not released by the service");
+    }
+}
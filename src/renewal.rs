@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use tokio::sync::Notify;
+
+use crate::core::{self, Tally};
+use crate::event::Event;
+use crate::lock::{Bytes, Lock, LockError, LockGuard, LockManager};
+use crate::{rt, transport};
+
+// The locks kept alive by a manager and its clones, renewed by a single task
+#[derive(Default)]
+pub(crate) struct Renewals {
+    entries: Mutex<Entries>,
+    wake: Notify,
+}
+
+#[derive(Default)]
+struct Entries {
+    next_id: u64,
+    locks: HashMap<u64, Entry>,
+    // Whether the renewal task is running
+    running: bool,
+}
+
+struct Entry {
+    resource: Vec<u8>,
+    val: Vec<u8>,
+    ttl: usize,
+    due: Instant,
+    state: Arc<State>,
+}
+
+// The state of a renewed lock, shared with its `Renewal`
+struct State {
+    expires_at: Mutex<Instant>,
+    lost: AtomicBool,
+    on_lost: Notify,
+}
+
+impl Renewals {
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Marks the renewed locks as lost when the renewal task is aborted,
+// see `TaskSet::abort_all`, so that the next lock kept alive starts a new task
+struct OnAbort<'a> {
+    renewals: &'a Renewals,
+    armed: bool,
+}
+
+impl Drop for OnAbort<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let mut entries = self.renewals.entries();
+        entries.running = false;
+        for (_, entry) in entries.locks.drain() {
+            entry.state.lost.store(true, Ordering::Release);
+            entry.state.on_lost.notify_waiters();
+        }
+    }
+}
+
+impl fmt::Debug for Renewals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Renewals")
+            .field("locks", &self.entries().locks.len())
+            .finish()
+    }
+}
+
+/// A lock kept alive by the manager's renewal scheduler, created with `LockManager::keep_alive`.
+///
+/// Dropping the handle stops renewing the lock, which is then held until it expires
+/// unless released.
+pub struct Renewal {
+    lock_manager: LockManager,
+    id: u64,
+    resource: Vec<u8>,
+    state: Arc<State>,
+}
+
+impl Renewal {
+    /// The resource of the renewed lock.
+    pub fn resource(&self) -> &[u8] {
+        &self.resource
+    }
+
+    /// Time left until the lock expires, as of its last renewal. Zero once it is lost.
+    pub fn remaining_validity(&self) -> Duration {
+        if self.is_lost() {
+            return Duration::ZERO;
+        }
+        let expires_at = *self
+            .state
+            .expires_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        expires_at.saturating_duration_since(rt::now())
+    }
+
+    /// Whether renewing failed and the lock is not held anymore.
+    pub fn is_lost(&self) -> bool {
+        self.state.lost.load(Ordering::Acquire)
+    }
+
+    /// Wait until the lock is lost, to stop the work it protects.
+    pub async fn lost(&self) {
+        loop {
+            let notified = self.state.on_lost.notified();
+            if self.is_lost() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for Renewal {
+    fn drop(&mut self) {
+        self.lock_manager
+            .renewals()
+            .entries()
+            .locks
+            .remove(&self.id);
+    }
+}
+
+impl fmt::Debug for Renewal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Renewal")
+            .field("resource", &format_args!("{}", Bytes(&self.resource)))
+            .field("lost", &self.is_lost())
+            .finish()
+    }
+}
+
+/// A guard whose lock is extended in the background for as long as it is alive,
+/// created with `LockGuard::into_auto_extended`.
+///
+/// Dropping it stops the renewals and releases the lock like dropping a `LockGuard`.
+pub struct AutoExtendedGuard<'a> {
+    // Declared first so renewals stop before the guard releases the lock
+    renewal: Renewal,
+    guard: LockGuard<'a>,
+}
+
+impl<'a> AutoExtendedGuard<'a> {
+    /// The lock as acquired. Its validity is not updated by renewals,
+    /// see `remaining_validity` instead.
+    pub fn lock(&self) -> &Lock<'a> {
+        &self.guard.lock
+    }
+
+    /// The handle of the background renewals.
+    pub fn renewal(&self) -> &Renewal {
+        &self.renewal
+    }
+
+    /// Time left until the lock expires, as of its last renewal. Zero once it is lost.
+    pub fn remaining_validity(&self) -> Duration {
+        self.renewal.remaining_validity()
+    }
+
+    /// Whether renewing failed and the lock is not held anymore.
+    pub fn is_lost(&self) -> bool {
+        self.renewal.is_lost()
+    }
+
+    /// Wait until the lock is lost, e.g. to `select` on it alongside the protected work.
+    pub async fn lost(&self) {
+        self.renewal.lost().await
+    }
+
+    /// Stop renewing the lock and return its guard, which still holds it until it expires.
+    pub fn into_guard(self) -> LockGuard<'a> {
+        self.guard
+    }
+
+    /// Stop renewing and release the lock.
+    pub async fn release(self) {
+        drop(self.renewal);
+        let lock = self.guard.into_lock();
+        lock.lock_manager.unlock(&lock).await;
+    }
+}
+
+impl fmt::Debug for AutoExtendedGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoExtendedGuard")
+            .field("lock", &self.guard.lock)
+            .field("lost", &self.is_lost())
+            .finish()
+    }
+}
+
+impl<'a> LockGuard<'a> {
+    /// Keep extending the lock to `ttl` in the background while the returned guard is alive,
+    /// like a watchdog. See `LockManager::keep_alive` for how and when locks are renewed,
+    /// and `AutoExtendedGuard::lost` to learn that renewing failed.
+    pub fn into_auto_extended(self, ttl: Duration) -> Result<AutoExtendedGuard<'a>, LockError> {
+        let renewal = self.lock.lock_manager.keep_alive(&self.lock, ttl)?;
+        Ok(AutoExtendedGuard {
+            renewal,
+            guard: self,
+        })
+    }
+}
+
+impl LockManager {
+    /// Keep extending the lock to `ttl` in the background until the returned handle is dropped.
+    ///
+    /// All locks kept alive by a manager and its clones are renewed by a single task instead
+    /// of a timer per lock. A lock is due once half of its validity has passed, or earlier with
+    /// `set_renewal_interval`, and the locks due at the same time are extended together,
+    /// with one script call per instance. Failed renewals are retried after the retry delay
+    /// until the lock expires. Once a quorum cannot hold the lock anymore, or it expired,
+    /// the lock is lost, see `Renewal::lost`, and `Event::RenewalFailed` is raised.
+    ///
+    /// Requires a running runtime to spawn the renewal task on, which belongs to `tasks`.
+    /// Aborting it loses all renewed locks. May return
+    /// `LockError::TtlTooLarge` or `LockError::TtlOutOfRange` like `extend`.
+    pub fn keep_alive(&self, lock: &Lock<'_>, ttl: Duration) -> Result<Renewal, LockError> {
+        let ttl = self.ttl_millis(ttl)?;
+        let now = rt::now();
+        let remaining = lock.remaining_validity();
+        let state = Arc::new(State {
+            expires_at: Mutex::new(now + remaining),
+            lost: AtomicBool::new(false),
+            on_lost: Notify::new(),
+        });
+
+        let renewals = self.renewals();
+        let (id, spawn) = {
+            let mut entries = renewals.entries();
+            let id = entries.next_id;
+            entries.next_id += 1;
+            entries.locks.insert(
+                id,
+                Entry {
+                    resource: lock.resource.clone(),
+                    val: lock.val.clone(),
+                    ttl,
+                    due: self.renewal_due(now, remaining),
+                    state: state.clone(),
+                },
+            );
+            (id, !std::mem::replace(&mut entries.running, true))
+        };
+        if spawn {
+            let lock_manager = self.clone();
+            self.tasks_arc()
+                .spawn(async move { lock_manager.run_renewals().await });
+        } else {
+            renewals.wake.notify_one();
+        }
+
+        Ok(Renewal {
+            lock_manager: self.clone(),
+            id,
+            resource: lock.resource.clone(),
+            state,
+        })
+    }
+
+    // The renewal task, running until no lock is left to renew
+    async fn run_renewals(&self) {
+        let renewals = self.renewals();
+        let mut aborted = OnAbort {
+            renewals,
+            armed: true,
+        };
+        loop {
+            let now = rt::now();
+            let (due, next) = {
+                let mut entries = renewals.entries();
+                if entries.locks.is_empty() {
+                    entries.running = false;
+                    aborted.armed = false;
+                    return;
+                }
+                let due: Vec<u64> = entries
+                    .locks
+                    .iter()
+                    .filter(|(_, entry)| entry.due <= now)
+                    .map(|(id, _)| *id)
+                    .collect();
+                let next = entries.locks.values().map(|entry| entry.due).min();
+                (due, next.unwrap_or(now))
+            };
+
+            if due.is_empty() {
+                let sleep = rt::sleep(next.saturating_duration_since(now));
+                let woken = renewals.wake.notified();
+                futures::pin_mut!(sleep, woken);
+                future::select(sleep, woken).await;
+            } else {
+                self.renew(&due).await;
+            }
+        }
+    }
+
+    // Extends the given locks in one round
+    async fn renew(&self, ids: &[u64]) {
+        let renewals = self.renewals();
+        let due: Vec<(u64, Vec<u8>, Vec<u8>, usize)> = {
+            let entries = renewals.entries();
+            ids.iter()
+                .filter_map(|id| {
+                    let entry = entries.locks.get(id)?;
+                    Some((*id, entry.resource.clone(), entry.val.clone(), entry.ttl))
+                })
+                .collect()
+        };
+        let locks: Vec<(&[u8], &[u8], usize)> = due
+            .iter()
+            .map(|(_, resource, val, ttl)| (&resource[..], &val[..], *ttl))
+            .collect();
+
+        let start = rt::now();
+        let results = self
+            .on_all(|instance| transport::extend_many_instance(instance, &locks))
+            .await;
+        let elapsed = rt::elapsed(start);
+
+        let now = rt::now();
+        let mut entries = renewals.entries();
+        for (n, (id, _, _, ttl)) in due.iter().enumerate() {
+            // The renewal may have been stopped in the meantime
+            let Some(entry) = entries.locks.get_mut(id) else {
+                continue;
+            };
+            let tally = Tally::count(results.iter().map(|result| match result {
+                Ok(extended) => Ok(extended.get(n).copied().unwrap_or(false)),
+                Err(e) => Err(e),
+            }));
+            let ttl = Duration::from_millis(*ttl as u64);
+            let validity = core::validity(ttl, elapsed, self.clock_drift(ttl))
+                .filter(|_| tally.has_quorum(self.acquire_quorum()));
+
+            let mut expires_at = entry
+                .state
+                .expires_at
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            match validity {
+                Some(validity) => {
+                    *expires_at = start + validity;
+                    entry.due = self.renewal_due(start, validity);
+                }
+                None if tally.is_lost(self.quorum()) || *expires_at <= now => {
+                    drop(expires_at);
+                    let entry = entries.locks.remove(id).expect("entry was just found");
+                    entry.state.lost.store(true, Ordering::Release);
+                    entry.state.on_lost.notify_waiters();
+                    self.emit(Event::RenewalFailed {
+                        resource: entry.resource,
+                    });
+                }
+                None => entry.due = (now + self.retry_delay()).min(*expires_at),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_keep_alive() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let rl2 = LockManager::new(addresses.clone());
+        let ttl = Duration::from_millis(500);
+        let key = rl.get_unique_lock_id()?;
+        let other = rl.get_unique_lock_id()?;
+
+        let lock = rl.lock(&key, ttl).await?;
+        let other_lock = rl.lock(&other, ttl).await?;
+        let renewal = rl.keep_alive(&lock, ttl)?;
+        let other_renewal = rl.keep_alive(&other_lock, ttl)?;
+
+        rt::sleep(Duration::from_millis(1500)).await;
+        assert!(!renewal.is_lost());
+        assert!(renewal.remaining_validity() > Duration::ZERO);
+        assert!(rl2.lock(&key, ttl).await.is_err());
+
+        // Renewals stop when their handle is dropped
+        drop(other_renewal);
+        rt::sleep(Duration::from_millis(1000)).await;
+        rl2.lock(&other, ttl).await?;
+
+        for server in &rl.servers[..2] {
+            let mut con = server.get_connection()?;
+            redis::cmd("DEL").arg(&key).execute(&mut con);
+        }
+        tokio::time::timeout(Duration::from_secs(2), renewal.lost()).await?;
+        assert_eq!(renewal.remaining_validity(), Duration::ZERO);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_into_auto_extended() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_renewal_interval(Duration::from_millis(100));
+        let rl2 = LockManager::new(addresses.clone());
+        let ttl = Duration::from_millis(500);
+        let key = rl.get_unique_lock_id()?;
+
+        let guard = LockGuard {
+            lock: rl.lock(&key, ttl).await?,
+        }
+        .into_auto_extended(ttl)?;
+        rt::sleep(Duration::from_millis(1000)).await;
+        assert!(!guard.is_lost());
+        assert!(guard.remaining_validity() > Duration::from_millis(200));
+        assert!(rl2.lock(&key, ttl).await.is_err());
+
+        guard.release().await;
+        rl2.lock(&key, ttl).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use std::borrow::Cow;
+
+/// A type that names a lockable resource, so it can be passed to `LockManager::lock` and
+/// `acquire` directly.
+///
+/// Implementing it for domain types, e.g. an `OrderId` returning `order:<id>`, keeps the key
+/// format in one place instead of at every call site. Implemented for byte slices, arrays and
+/// vectors, and for strings, which are locked by their UTF-8 bytes.
+pub trait LockResource {
+    /// The key the resource is locked under.
+    fn key(&self) -> Cow<'_, [u8]>;
+}
+
+impl LockResource for [u8] {
+    fn key(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<const N: usize> LockResource for [u8; N] {
+    fn key(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl LockResource for Vec<u8> {
+    fn key(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl LockResource for str {
+    fn key(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl LockResource for String {
+    fn key(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::{LockError, LockManager};
+    use std::time::Duration;
+
+    struct OrderId(u64);
+
+    impl LockResource for OrderId {
+        fn key(&self) -> Cow<'_, [u8]> {
+            Cow::Owned(format!("order:{}", self.0).into_bytes())
+        }
+    }
+
+    #[test]
+    fn test_lock_resource_keys() {
+        assert_eq!(OrderId(7).key(), &b"order:7"[..]);
+        assert_eq!("mutex".key(), &b"mutex"[..]);
+        assert_eq!(b"mutex".key(), "mutex".to_string().key());
+        assert_eq!(vec![0u8, 255].key(), &[0, 255][..]);
+    }
+
+    #[tokio::test]
+    async fn test_lock_domain_type() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+        rl.set_maintenance_mode(true);
+        assert!(matches!(
+            rl.lock(&OrderId(7), Duration::from_millis(1000)).await,
+            Err(LockError::MaintenanceMode)
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::event::Event;
+use crate::lock::LockManager;
+use crate::transport::run_id_instance;
+
+impl LockManager {
+    /// Ask every instance for its run id and return the instances that restarted since
+    /// the previous check.
+    ///
+    /// Restarted instances raise `Event::InstanceRestarted` and, if enabled with
+    /// `set_restart_quarantine`, are excluded from quorums for the configured window.
+    /// The first check only records the run ids, `warm_up` makes it on first contact.
+    /// Run ids are shared by the clones of a manager. With `set_reset_on_restart`, the
+    /// shared connection and the latency statistics of a restarted instance are discarded. Call this periodically, e.g. from a background task, at an interval
+    /// well below the quarantine window: a restart is only noticed by the next check.
+    ///
+    /// Instances that cannot be reached are skipped.
+    pub async fn check_restarts(&self) -> Vec<usize> {
+        let run_ids = self.on_all(run_id_instance).await;
+        let quarantine = self.restart_quarantine();
+
+        let mut restarted = Vec::new();
+        for (i, run_id) in run_ids.into_iter().enumerate() {
+            let Ok(run_id) = run_id else {
+                continue;
+            };
+            if self.tracker().observe_run(i, run_id.clone(), quarantine) {
+                if self.reset_on_restart() {
+                    self.instance(i).evict();
+                    self.tracker().forget_latency(i);
+                }
+                self.emit(Event::InstanceRestarted {
+                    address: self.servers[i].get_connection_info().addr.to_string(),
+                    run_id,
+                    quarantined_for: quarantine,
+                });
+                restarted.push(i);
+            }
+        }
+        restarted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+    use crate::lock::LockError;
+
+    #[tokio::test]
+    async fn test_check_restarts() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_restart_quarantine(Duration::from_secs(60));
+        rl.set_retry(1, Duration::from_millis(10));
+        assert!(rl.check_restarts().await.is_empty());
+        assert!(rl.check_restarts().await.is_empty());
+
+        // Pretend that two instances restarted
+        rl.tracker().observe_run(0, "old".to_string(), None);
+        rl.tracker().observe_run(1, "old".to_string(), None);
+        assert_eq!(rl.check_restarts().await, [0, 1]);
+        assert_ne!(rl.tracker().run_id(0).as_deref(), Some("old"));
+
+        let key = rl.get_unique_lock_id()?;
+        assert!(matches!(
+            rl.lock(&key, Duration::from_millis(10_000)).await,
+            Err(LockError::Unavailable)
+        ));
+        let state = rl.dump_state().await;
+        assert!(state.instances[0].quarantined && !state.instances[2].quarantined);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Timers and tasks of the selected async runtime.
+//!
+//! With `tokio-comp` enabled tokio is used, which requires a tokio runtime.
+//! Otherwise async-std is used, which works on any executor.
+//!
+//! With `test-util` enabled, time is measured and slept through tokio's clock,
+//! so that it can be paused and advanced with `tokio::time::pause` in tests.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// The future passed to `timeout` did not complete in time.
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+#[cfg(any(feature = "tokio-comp", feature = "test-util"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+#[cfg(any(feature = "tokio-comp", feature = "test-util"))]
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| Elapsed)
+}
+
+#[cfg(not(any(feature = "tokio-comp", feature = "test-util")))]
+pub(crate) async fn sleep(duration: Duration) {
+    async_std::task::sleep(duration).await
+}
+
+#[cfg(not(any(feature = "tokio-comp", feature = "test-util")))]
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    async_std::future::timeout(duration, future)
+        .await
+        .map_err(|_| Elapsed)
+}
+
+#[cfg(feature = "test-util")]
+pub(crate) fn now() -> Instant {
+    tokio::time::Instant::now().into_std()
+}
+
+#[cfg(not(feature = "test-util"))]
+pub(crate) fn now() -> Instant {
+    Instant::now()
+}
+
+pub(crate) fn elapsed(since: Instant) -> Duration {
+    now().saturating_duration_since(since)
+}
+
+#[cfg(feature = "tokio-comp")]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+#[cfg(not(feature = "tokio-comp"))]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    async_std::task::spawn(future);
+}
+
+// Runs a future to completion outside of any runtime, e.g. to release locks on drop.
+// tokio's connections need a reactor, so a temporary runtime is created for them,
+// and the future is dropped unpolled if that fails.
+#[cfg(feature = "tokio-comp")]
+pub(crate) fn block_on<F: Future>(future: F) {
+    if let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        runtime.block_on(future);
+    }
+}
+
+#[cfg(not(feature = "tokio-comp"))]
+pub(crate) fn block_on<F: Future>(future: F) {
+    futures::executor::block_on(future);
+}
+
+#[cfg(feature = "tokio-comp")]
+pub(crate) async fn yield_now() {
+    tokio::task::yield_now().await
+}
+
+#[cfg(not(feature = "tokio-comp"))]
+pub(crate) async fn yield_now() {
+    async_std::task::yield_now().await
+}
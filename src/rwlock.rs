@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use crate::lock::{AcquireOptions, Lock, LockError, LockManager};
+use crate::resource::LockResource;
+use crate::transport;
+
+// The set tracking the readers of `resource`
+fn readers_key(resource: &[u8]) -> Vec<u8> {
+    [resource, b":readers"].concat()
+}
+
+// The readers set the key of a read lock with `val` belongs to, `None` for other locks
+pub(crate) fn readers_of<'a>(key: &'a [u8], val: &[u8]) -> Option<&'a [u8]> {
+    let readers = key.strip_suffix(val)?.strip_suffix(b":")?;
+    readers.ends_with(b":readers").then_some(readers)
+}
+
+impl LockManager {
+    /// Acquire a shared read lock on the given resource.
+    ///
+    /// Any number of readers may hold the resource at once, while `lock_write` excludes them.
+    /// Each instance tracks its readers in a set stored next to the resource, and a read lock
+    /// is acquired on a quorum of instances like any other lock.
+    ///
+    /// The returned lock holds a key of its own, named after the resource and the lock's value,
+    /// so it is extended, released and kept alive like a lock returned by `lock`, including
+    /// the cancel safety. Plain locks taken on the resource with `lock` exclude readers too,
+    /// but are not excluded by them. Releasing it with `unlock` also removes it from the set,
+    /// readers that expire are removed by the next read or write lock on the resource.
+    ///
+    /// Read and write locks are always enforced and carry no fencing token: shadow mode,
+    /// see `set_shadow_mode`, and fencing, see `set_fencing`, don't apply to them.
+    ///
+    /// Fails like `lock`, with `LockError::Unavailable` if a writer holds the resource
+    /// or waits for it with `lock_write_with_grace`.
+    pub async fn lock_read<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        self.check_maintenance_mode()?;
+        let resource = resource.key();
+        self.check_quota(&resource)?;
+        let ttl = self.ttl_millis(ttl)?;
+        let val = self.next_lock_id().map_err(LockError::Io)?;
+        let readers = readers_key(&resource);
+        let reader = transport::reader_key(&readers, &val);
+
+        self.acquire_with(&reader, &val, ttl, &AcquireOptions::default(), |instance| {
+            transport::read_lock_instance(instance, &resource, &readers, &val, ttl)
+        })
+        .await
+    }
+
+    /// Acquire an exclusive write lock on the given resource, once no other writer
+    /// and no reader holds it, see `lock_read`.
+    ///
+    /// The returned lock is held on the resource itself, so it also excludes plain locks
+    /// and is extended, released and kept alive like a lock returned by `lock`.
+    /// Readers don't wait for writers retrying here, so a resource that always has a reader
+    /// starves them, see `lock_write_with_grace`. Like read locks, write locks are neither
+    /// shadowed nor fenced.
+    ///
+    /// Fails like `lock`.
+    pub async fn lock_write<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        self.check_maintenance_mode()?;
+        let resource = resource.key();
+        self.check_quota(&resource)?;
+        let ttl = self.ttl_millis(ttl)?;
+        let val = self.next_lock_id().map_err(LockError::Io)?;
+        let readers = readers_key(&resource);
+
+        self.acquire_with(
+            &resource,
+            &val,
+            ttl,
+            &AcquireOptions::default(),
+            |instance| transport::write_lock_instance(instance, &resource, &readers, &val, ttl),
+        )
+        .await
+    }
+
+    /// Like `lock_write`, but keeps new readers out while waiting up to `grace` for the
+    /// current ones to leave, so that writers are not starved by overlapping readers.
+    ///
+    /// The writer announces itself on every instance with a key that `lock_read` refuses
+    /// to share the resource with, and retries with the manager's retry delay until it holds
+    /// the resource or `grace` has passed. The announcement is withdrawn either way, and
+    /// expires after `grace` if the call is cancelled.
+    ///
+    /// Fails like `lock_write`, but with `LockError::AcquireTimeout` if the resource is not
+    /// acquired within `grace`, and with `LockError::TtlTooLarge` if `grace` is too large.
+    pub async fn lock_write_with_grace<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        grace: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        self.check_maintenance_mode()?;
+        let resource = resource.key();
+        self.check_quota(&resource)?;
+        let ttl = self.ttl_millis(ttl)?;
+        let grace_millis: usize = grace
+            .as_millis()
+            .try_into()
+            .map_err(|_| LockError::TtlTooLarge)?;
+        let val = self.next_lock_id().map_err(LockError::Io)?;
+        let readers = readers_key(&resource);
+        let writer = transport::writer_key(&resource);
+
+        self.on_all(|instance| {
+            transport::lock_instance(instance, &writer, val.clone(), grace_millis.max(1))
+        })
+        .await;
+        let options = AcquireOptions {
+            retry_count: Some(u32::MAX),
+            ..AcquireOptions::new().timeout(grace)
+        };
+        let lock = self
+            .acquire_with(&resource, &val, ttl, &options, |instance| {
+                transport::write_lock_instance(instance, &resource, &readers, &val, ttl)
+            })
+            .await;
+        self.on_all(|instance| transport::unlock_instance(instance, &writer, &val))
+            .await;
+
+        match lock {
+            Err(LockError::Unavailable) => Err(LockError::AcquireTimeout),
+            lock => lock,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_lock_read_write() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_retry(2, Duration::from_millis(10));
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        // Readers share the resource and exclude writers
+        let reader = rl.lock_read(&key, ttl).await?;
+        let reader2 = rl.lock_read(&key, ttl).await?;
+        assert!(rl.lock_write(&key, ttl).await.is_err());
+        rl.unlock(&reader).await;
+        assert!(rl.lock_write(&key, ttl).await.is_err());
+        let reader2 = rl.extend(&reader2, ttl).await?;
+        rl.unlock(&reader2).await;
+        // Released readers leave the set, which is then deleted
+        for server in &rl.servers {
+            let mut con = server.get_connection()?;
+            let exists: bool = redis::cmd("EXISTS")
+                .arg(readers_key(&key))
+                .query(&mut con)?;
+            assert!(!exists);
+        }
+
+        // Writers exclude readers and other writers
+        let writer = rl.lock_write(&key, ttl).await?;
+        assert!(rl.lock_read(&key, ttl).await.is_err());
+        assert!(rl.lock_write(&key, ttl).await.is_err());
+        assert!(rl.lock(&key, ttl).await.is_err());
+        rl.unlock(&writer).await;
+
+        // Expired readers don't hold the resource
+        rl.lock_read(&key, Duration::from_millis(100)).await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let writer = rl.lock_write(&key, ttl).await?;
+        rl.unlock(&writer).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_write_with_grace() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let mut rl = LockManager::new(addresses.clone());
+        rl.set_retry(2, Duration::from_millis(10));
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        // Readers that don't leave within the grace period time the writer out
+        let reader = rl.lock_read(&key, ttl).await?;
+        match rl
+            .lock_write_with_grace(&key, ttl, Duration::from_millis(100))
+            .await
+        {
+            Err(LockError::AcquireTimeout) => (),
+            r => panic!("Expected LockError::AcquireTimeout, got {:?}", r),
+        }
+        // The writer withdrew, so readers are let in again
+        let reader2 = rl.lock_read(&key, ttl).await?;
+        rl.unlock(&reader2).await;
+
+        // New readers are kept out while the writer waits for the current ones
+        let (writer, blocked) = tokio::join!(
+            rl.lock_write_with_grace(&key, ttl, Duration::from_millis(2000)),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let blocked = rl.lock_read(&key, ttl).await.is_err();
+                rl.unlock(&reader).await;
+                blocked
+            }
+        );
+        assert!(blocked);
+        let writer = writer?;
+        assert!(rl.lock_read(&key, ttl).await.is_err());
+        rl.unlock(&writer).await;
+        rl.unlock(&rl.lock_read(&key, ttl).await?).await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_readers_of() {
+        let readers = readers_key(b"mutex");
+        let reader = transport::reader_key(&readers, b"tok");
+        assert_eq!(readers_of(&reader, b"tok"), Some(&readers[..]));
+        assert_eq!(readers_of(&reader, b"other"), None);
+        assert_eq!(readers_of(b"mutex:tok", b"tok"), None);
+    }
+}
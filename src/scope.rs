@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::lock::{Lock, LockError, LockManager};
+
+/// Tracks every lock acquired through it and releases all of them together.
+///
+/// Locks are released in reverse order of acquisition, with one batched call per server,
+/// when the scope is closed with `LockScope::close`.
+///
+/// Dropping an unclosed scope releases its locks like dropping a `LockGuard`: in a spawned task
+/// within a tokio runtime, synchronously outside of one.
+#[derive(Debug)]
+pub struct LockScope<'a> {
+    lock_manager: &'a LockManager,
+    locks: Vec<Lock<'a>>,
+}
+
+impl<'a> LockScope<'a> {
+    /// Acquire a lock that will be released together with the scope.
+    ///
+    /// See `LockManager::lock`.
+    pub async fn lock(&mut self, resource: &[u8], ttl: Duration) -> Result<&Lock<'a>, LockError> {
+        let lock = self.lock_manager.lock(resource, ttl).await?;
+        self.locks.push(lock);
+        Ok(self.locks.last().expect("lock was just added"))
+    }
+
+    /// Locks currently held by the scope, in order of acquisition.
+    pub fn locks(&self) -> &[Lock<'a>] {
+        &self.locks
+    }
+
+    /// Release all locks held by the scope.
+    pub async fn close(mut self) {
+        self.release().await
+    }
+
+    async fn release(&mut self) {
+        let mut locks = std::mem::take(&mut self.locks);
+        if !locks.is_empty() {
+            locks.reverse();
+            self.lock_manager.unlock_all(&locks).await;
+        }
+    }
+}
+
+/// Like for `LockGuard`, a scope dropped within a tokio runtime releases its locks
+/// in a spawned task.
+impl Drop for LockScope<'_> {
+    fn drop(&mut self) {
+        let locks: Vec<&Lock<'_>> = self.locks.iter().rev().collect();
+        if !locks.is_empty() && crate::lock::release_in_tokio(&locks) {
+            self.locks.clear();
+            return;
+        }
+        crate::rt::block_on(self.release());
+    }
+}
+
+impl LockManager {
+    /// Create a scope releasing all locks acquired through it at once.
+    pub fn scope(&self) -> LockScope<'_> {
+        LockScope {
+            lock_manager: self,
+            locks: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_scope_close_releases_all() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let mut scope = rl.scope();
+        for _ in 0..3 {
+            let key = rl.get_unique_lock_id()?;
+            scope.lock(&key, Duration::from_millis(10_000)).await?;
+        }
+        let locks = scope.locks().to_vec();
+        assert_eq!(locks.len(), 3);
+
+        scope.close().await;
+        for lock in &locks {
+            assert_eq!(rl.verify(lock).await, 0);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use crate::core::UnlockOutcome;
+use crate::lock::{AcquireOptions, Lock, LockError, LockManager};
+use crate::resource::LockResource;
+use crate::rt;
+
+/// A lock whose total occupancy is bounded by a time budget.
+///
+/// The budget starts counting when the session is requested and covers the acquisition
+/// as well as every extension. Extensions are shortened so the lock never outlives the budget,
+/// and once it is spent `extend` refuses with `LockError::BudgetExhausted`.
+/// A budget too long to be represented as an `Instant` never runs out.
+#[derive(Debug)]
+pub struct LockSession<'a> {
+    lock: Lock<'a>,
+    deadline: Option<Instant>,
+}
+
+impl<'a> LockSession<'a> {
+    /// The currently held lock.
+    pub fn lock(&self) -> &Lock<'a> {
+        &self.lock
+    }
+
+    /// Point in time at which the budget is spent, `None` if it never is.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Budget left for holding the lock.
+    pub fn remaining_budget(&self) -> Duration {
+        remaining(self.deadline)
+    }
+
+    /// Whether the budget is spent and the lock must not be used anymore.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining_budget().is_zero()
+    }
+
+    /// Resolves once the budget is spent, so the holder can stop using the lock in time.
+    ///
+    /// Never resolves if the budget has no deadline.
+    pub async fn exhausted(&self) {
+        match self.deadline {
+            Some(deadline) => rt::sleep(deadline.saturating_duration_since(rt::now())).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Extend the lock by `ttl`, or less if the remaining budget is smaller.
+    ///
+    /// Fails with `LockError::BudgetExhausted` once the budget is spent.
+    pub async fn extend(&mut self, ttl: Duration) -> Result<(), LockError> {
+        let remaining = self.remaining_budget();
+        if remaining.is_zero() {
+            return Err(LockError::BudgetExhausted);
+        }
+
+        self.lock = self
+            .lock
+            .lock_manager
+            .extend(&self.lock, ttl.min(remaining))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Release the lock and end the session.
+    pub async fn release(self) -> UnlockOutcome {
+        self.lock.lock_manager.unlock(&self.lock).await
+    }
+}
+
+impl LockManager {
+    /// Acquire the lock for the given resource within a total time `budget`.
+    ///
+    /// Acquisition is retried until it succeeds or the budget is spent, in which case
+    /// `LockError::BudgetExhausted` is returned. Each attempt's retries stop once the budget
+    /// is spent, and the lock's TTL is capped to the remaining budget.
+    pub async fn lock_session<'a, R: LockResource + ?Sized>(
+        &'a self,
+        resource: &R,
+        ttl: Duration,
+        budget: Duration,
+    ) -> Result<LockSession<'a>, LockError> {
+        // Budgets too long to be represented don't set a deadline
+        let deadline = rt::now().checked_add(budget);
+        let resource = resource.key();
+
+        loop {
+            let remaining = remaining(deadline);
+            if remaining.is_zero() {
+                return Err(LockError::BudgetExhausted);
+            }
+
+            let options = AcquireOptions::new().timeout(remaining);
+            match self
+                .lock_with_options(&resource[..], ttl.min(remaining), &options)
+                .await
+            {
+                Ok(lock) => return Ok(LockSession { lock, deadline }),
+                Err(LockError::Unavailable | LockError::Contended { .. }) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn remaining(deadline: Option<Instant>) -> Duration {
+    deadline.map_or(Duration::MAX, |deadline| {
+        deadline.saturating_duration_since(rt::now())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_zero_budget() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+
+        match rl
+            .lock_session(b"resource", Duration::from_secs(1), Duration::ZERO)
+            .await
+        {
+            Err(LockError::BudgetExhausted) => (),
+            r => panic!("Expected LockError::BudgetExhausted, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use crate::core::UnlockOutcome;
+use crate::lock::{Lock, LockError, LockManager};
+use crate::resource::LockResource;
+
+/// Routes resources to one of several independent Redlock groups.
+///
+/// Each resource is mapped to a group by consistent hashing of its key, so every lock only
+/// touches the instances of its own group and throughput scales with the number of groups.
+/// The mapping is stable across processes; when a group is appended only about `1/n`
+/// of the resources move to it.
+///
+/// All clients sharing locks must configure the same groups in the same order.
+#[derive(Debug, Clone)]
+pub struct ShardedLockManager {
+    groups: Vec<LockManager>,
+}
+
+impl ShardedLockManager {
+    /// Create a sharded manager over the given groups.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `groups` is empty.
+    pub fn new(groups: Vec<LockManager>) -> ShardedLockManager {
+        assert!(!groups.is_empty(), "at least one lock group is required");
+        ShardedLockManager { groups }
+    }
+
+    /// All configured groups, in routing order.
+    pub fn groups(&self) -> &[LockManager] {
+        &self.groups
+    }
+
+    /// The group responsible for the given resource.
+    pub fn manager_for<R: LockResource + ?Sized>(&self, resource: &R) -> &LockManager {
+        &self.groups[shard_index(&resource.key(), self.groups.len())]
+    }
+
+    /// Acquire the lock on the group responsible for the resource.
+    ///
+    /// See `LockManager::lock`.
+    pub async fn lock<R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+        ttl: Duration,
+    ) -> Result<Lock<'_>, LockError> {
+        let resource = resource.key();
+        self.manager_for(&resource[..])
+            .lock(&resource[..], ttl)
+            .await
+    }
+
+    /// Extend the given lock on the group it was acquired from.
+    ///
+    /// See `LockManager::extend`.
+    pub async fn extend<'a>(
+        &'a self,
+        lock: &Lock<'a>,
+        ttl: Duration,
+    ) -> Result<Lock<'a>, LockError> {
+        lock.lock_manager.extend(lock, ttl).await
+    }
+
+    /// Unlock the given lock on the group it was acquired from.
+    pub async fn unlock(&self, lock: &Lock<'_>) -> UnlockOutcome {
+        lock.lock_manager.unlock(lock).await
+    }
+}
+
+// Jump consistent hash (Lamping & Veach) over a 64-bit FNV-1a hash of the resource.
+// Both are fixed algorithms, so all processes agree on the routing.
+fn shard_index(resource: &[u8], shards: usize) -> usize {
+    let mut key = resource.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    let (mut b, mut j) = (-1i64, 0i64);
+    while j < shards as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    b as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_index_is_stable() {
+        assert_eq!(shard_index(b"mutex", 1), 0);
+        assert_eq!(shard_index(b"mutex", 5), shard_index(b"mutex", 5));
+        assert!((0..100).all(|i| shard_index(format!("key{}", i).as_bytes(), 3) < 3));
+    }
+
+    #[test]
+    fn test_shard_index_moves_few_keys() {
+        let keys: Vec<_> = (0..1000).map(|i| format!("resource:{}", i)).collect();
+
+        let moved = keys
+            .iter()
+            .filter(|key| shard_index(key.as_bytes(), 4) != shard_index(key.as_bytes(), 5))
+            .count();
+        // About a fifth of the keys should move to the new shard, and only there
+        assert!((100..300).contains(&moved), "moved: {}", moved);
+        assert!(keys.iter().all(|key| {
+            let (before, after) = (
+                shard_index(key.as_bytes(), 4),
+                shard_index(key.as_bytes(), 5),
+            );
+            before == after || after == 4
+        }));
+    }
+
+    #[test]
+    fn test_manager_for() {
+        let sharded = ShardedLockManager::new(vec![
+            LockManager::new(vec!["redis://127.0.0.1:6380/"]),
+            LockManager::new(vec!["redis://127.0.0.1:6381/"]),
+        ]);
+
+        let index = shard_index(b"mutex", 2);
+        assert!(std::ptr::eq(
+            sharded.manager_for(b"mutex"),
+            &sharded.groups()[index]
+        ));
+    }
+}
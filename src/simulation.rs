@@ -0,0 +1,542 @@
+//! Deterministic simulation of the Redlock algorithm.
+//!
+//! Models clients competing for a single resource on a set of simulated instances, driven by
+//! a virtual clock and a seeded random number generator. The clients use the functions of
+//! `rslock::core`, just like `LockManager` does, so scenarios with latencies, crashes,
+//! partitions and skewed clocks can be checked for mutual exclusion without any Redis.
+//!
+//! Only available with the `simulation` feature.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::core::{self, Tally};
+
+/// A period in which a client considered itself the holder of the lock, in virtual time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hold {
+    pub client: usize,
+    pub from: Duration,
+    pub until: Duration,
+}
+
+impl Hold {
+    fn overlaps(&self, other: &Hold) -> bool {
+        self.from < other.until && other.from < self.until
+    }
+}
+
+/// The result of `Simulation::run`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// All holds, in order of acquisition.
+    pub holds: Vec<Hold>,
+    /// Pairs of holds by different clients that overlap.
+    pub violations: Vec<(Hold, Hold)>,
+}
+
+impl Report {
+    /// Whether mutual exclusion held throughout the simulation.
+    pub fn is_safe(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Outage {
+    instance: usize,
+    from: Duration,
+    until: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Partition {
+    client: usize,
+    instance: usize,
+    from: Duration,
+    until: Duration,
+}
+
+/// A scenario of clients repeatedly acquiring, holding and releasing one lock.
+///
+/// Each client tries to acquire the lock, holds it for the configured time or until its
+/// validity runs out, whichever comes first, releases it and tries again after a random delay.
+/// Failed attempts are undone and retried like `LockManager::lock` does.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    seed: u64,
+    instances: usize,
+    clients: usize,
+    ttl: Duration,
+    hold: Duration,
+    latency: (Duration, Duration),
+    timeout: Duration,
+    retry_delay: Duration,
+    drift_factor: f32,
+    drift_padding: Duration,
+    duration: Duration,
+    clock_rates: Vec<f64>,
+    outages: Vec<Outage>,
+    partitions: Vec<Partition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lock,
+    Unlock,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Start {
+        client: usize,
+    },
+    Arrive {
+        client: usize,
+        attempt: u64,
+        instance: usize,
+        op: Op,
+        sent: Duration,
+    },
+    Respond {
+        client: usize,
+        attempt: u64,
+        result: Result<bool, ()>,
+    },
+    Release {
+        client: usize,
+        attempt: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Key {
+    token: u64,
+    set_at: Duration,
+    expires_at: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Attempt {
+    id: u64,
+    started: Duration,
+    results: Vec<Result<bool, ()>>,
+}
+
+struct Run<'a> {
+    sim: &'a Simulation,
+    rng: StdRng,
+    now: Duration,
+    queue: BinaryHeap<Reverse<(Duration, u64)>>,
+    events: HashMap<u64, Event>,
+    next_event: u64,
+    keys: Vec<Option<Key>>,
+    attempts: Vec<Attempt>,
+    next_attempt: u64,
+    holds: Vec<Hold>,
+}
+
+impl Simulation {
+    /// A scenario with `instances` instances and `clients` clients, all randomness derived from `seed`.
+    ///
+    /// Defaults to a TTL of 1s, holds of 200ms, latencies between 1ms and 5ms, a timeout of 50ms,
+    /// a retry delay of up to 200ms, the default drift allowance and a duration of 60s.
+    pub fn new(seed: u64, instances: usize, clients: usize) -> Simulation {
+        Simulation {
+            seed,
+            instances,
+            clients,
+            ttl: Duration::from_secs(1),
+            hold: Duration::from_millis(200),
+            latency: (Duration::from_millis(1), Duration::from_millis(5)),
+            timeout: Duration::from_millis(50),
+            retry_delay: Duration::from_millis(200),
+            drift_factor: 0.01,
+            drift_padding: Duration::from_millis(2),
+            duration: Duration::from_secs(60),
+            clock_rates: vec![1.0; instances],
+            outages: Vec::new(),
+            partitions: Vec::new(),
+        }
+    }
+
+    /// TTL of the lock.
+    pub fn ttl(mut self, ttl: Duration) -> Simulation {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long clients use the lock before releasing it.
+    pub fn hold(mut self, hold: Duration) -> Simulation {
+        self.hold = hold;
+        self
+    }
+
+    /// Range of one-way latencies between clients and instances.
+    /// The round trip must stay below the timeout.
+    pub fn latency(mut self, min: Duration, max: Duration) -> Simulation {
+        self.latency = (min, max);
+        self
+    }
+
+    /// Time after which a request to an unreachable instance fails.
+    pub fn timeout(mut self, timeout: Duration) -> Simulation {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Upper bound of the random delay between attempts.
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Simulation {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Clock drift allowance of the clients, see `LockManager::set_clock_drift`.
+    pub fn clock_drift(mut self, factor: f32, padding: Duration) -> Simulation {
+        self.drift_factor = factor;
+        self.drift_padding = padding;
+        self
+    }
+
+    /// Virtual time after which no new attempts are started.
+    pub fn duration(mut self, duration: Duration) -> Simulation {
+        self.duration = duration;
+        self
+    }
+
+    /// Let the clock of an instance run at `rate` times the speed of the clients' clocks,
+    /// so that its keys expire after `ttl / rate`.
+    pub fn clock_rate(mut self, instance: usize, rate: f64) -> Simulation {
+        self.clock_rates[instance] = rate;
+        self
+    }
+
+    /// Crash an instance at `at`, restarting it empty after `down_for`.
+    pub fn crash(mut self, instance: usize, at: Duration, down_for: Duration) -> Simulation {
+        self.outages.push(Outage {
+            instance,
+            from: at,
+            until: at + down_for,
+        });
+        self
+    }
+
+    /// Drop all messages between a client and an instance from `from` until `until`.
+    pub fn partition(
+        mut self,
+        client: usize,
+        instance: usize,
+        from: Duration,
+        until: Duration,
+    ) -> Simulation {
+        self.partitions.push(Partition {
+            client,
+            instance,
+            from,
+            until,
+        });
+        self
+    }
+
+    /// Run the scenario to completion.
+    pub fn run(&self) -> Report {
+        let mut run = Run {
+            sim: self,
+            rng: StdRng::seed_from_u64(self.seed),
+            now: Duration::ZERO,
+            queue: BinaryHeap::new(),
+            events: HashMap::new(),
+            next_event: 0,
+            keys: vec![None; self.instances],
+            attempts: (0..self.clients).map(|_| Attempt::default()).collect(),
+            next_attempt: 1,
+            holds: Vec::new(),
+        };
+        for client in 0..self.clients {
+            let at = core::jitter(self.retry_delay, &mut run.rng);
+            run.schedule(at, Event::Start { client });
+        }
+        while let Some(Reverse((at, id))) = run.queue.pop() {
+            run.now = at;
+            let event = run.events.remove(&id).expect("scheduled event exists");
+            run.handle(event);
+        }
+
+        let mut holds = run.holds;
+        holds.sort_by_key(|hold| hold.from);
+        let mut violations = Vec::new();
+        for (i, a) in holds.iter().enumerate() {
+            for b in &holds[i + 1..] {
+                if b.from >= a.until {
+                    break;
+                }
+                if a.client != b.client && a.overlaps(b) {
+                    violations.push((*a, *b));
+                }
+            }
+        }
+
+        Report { holds, violations }
+    }
+}
+
+impl Run<'_> {
+    fn schedule(&mut self, at: Duration, event: Event) {
+        let id = self.next_event;
+        self.next_event += 1;
+        self.events.insert(id, event);
+        self.queue.push(Reverse((at, id)));
+    }
+
+    fn latency(&mut self) -> Duration {
+        let (min, max) = self.sim.latency;
+        if max <= min {
+            return min;
+        }
+        min + Duration::from_micros(self.rng.gen_range(0..(max - min).as_micros().max(1) as u64))
+    }
+
+    fn partitioned(&self, client: usize, instance: usize) -> bool {
+        self.sim.partitions.iter().any(|p| {
+            p.client == client && p.instance == instance && p.from <= self.now && self.now < p.until
+        })
+    }
+
+    fn down(&self, instance: usize) -> bool {
+        self.sim
+            .outages
+            .iter()
+            .any(|o| o.instance == instance && o.from <= self.now && self.now < o.until)
+    }
+
+    fn send(&mut self, client: usize, attempt: u64, op: Op) {
+        for instance in 0..self.sim.instances {
+            let sent = self.now;
+            if self.partitioned(client, instance) {
+                if op == Op::Lock {
+                    let at = sent + self.sim.timeout;
+                    self.schedule(at, respond(client, attempt, Err(())));
+                }
+                continue;
+            }
+            let at = sent + self.latency();
+            self.schedule(
+                at,
+                Event::Arrive {
+                    client,
+                    attempt,
+                    instance,
+                    op,
+                    sent,
+                },
+            );
+        }
+    }
+
+    // Applies an operation to an instance, returning whether it succeeded
+    fn apply(&mut self, instance: usize, token: u64, op: Op) -> bool {
+        let now = self.now;
+        let crashed = |key: &Key| {
+            self.sim
+                .outages
+                .iter()
+                .any(|o| o.instance == instance && key.set_at < o.from && o.from <= now)
+        };
+        if let Some(key) = self.keys[instance] {
+            if key.expires_at <= now || crashed(&key) {
+                self.keys[instance] = None;
+            }
+        }
+
+        match op {
+            Op::Lock if self.keys[instance].is_none() => {
+                let ttl = self.sim.ttl.div_f64(self.sim.clock_rates[instance]);
+                self.keys[instance] = Some(Key {
+                    token,
+                    set_at: now,
+                    expires_at: now + ttl,
+                });
+                true
+            }
+            Op::Lock => false,
+            Op::Unlock => match self.keys[instance] {
+                Some(key) if key.token == token => {
+                    self.keys[instance] = None;
+                    true
+                }
+                _ => false,
+            },
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start { client } => {
+                if self.now >= self.sim.duration {
+                    return;
+                }
+                let id = self.next_attempt;
+                self.next_attempt += 1;
+                self.attempts[client] = Attempt {
+                    id,
+                    started: self.now,
+                    results: Vec::new(),
+                };
+                self.send(client, id, Op::Lock);
+            }
+            Event::Arrive {
+                client,
+                attempt,
+                instance,
+                op,
+                sent,
+            } => {
+                if self.down(instance) {
+                    if op == Op::Lock {
+                        let at = (sent + self.sim.timeout).max(self.now);
+                        self.schedule(at, respond(client, attempt, Err(())));
+                    }
+                    return;
+                }
+                let ok = self.apply(instance, attempt, op);
+                if op == Op::Unlock {
+                    return;
+                }
+                if self.partitioned(client, instance) {
+                    let at = (sent + self.sim.timeout).max(self.now);
+                    self.schedule(at, respond(client, attempt, Err(())));
+                } else {
+                    let at = self.now + self.latency();
+                    self.schedule(at, respond(client, attempt, Ok(ok)));
+                }
+            }
+            Event::Respond {
+                client,
+                attempt,
+                result,
+            } => {
+                let current = &mut self.attempts[client];
+                if current.id != attempt {
+                    return;
+                }
+                current.results.push(result);
+                if current.results.len() < self.sim.instances {
+                    return;
+                }
+                self.evaluate(client);
+            }
+            Event::Release { client, attempt } => {
+                self.send(client, attempt, Op::Unlock);
+                let at = self.now + core::jitter(self.sim.retry_delay, &mut self.rng);
+                self.schedule(at, Event::Start { client });
+            }
+        }
+    }
+
+    // All instances answered the client's attempt
+    fn evaluate(&mut self, client: usize) {
+        let attempt = std::mem::take(&mut self.attempts[client]);
+        let tally = Tally::count(attempt.results.iter().copied());
+        let quorum = core::quorum(self.sim.instances);
+        let elapsed = self.now - attempt.started;
+        let drift = core::clock_drift(self.sim.ttl, self.sim.drift_factor, self.sim.drift_padding);
+        let validity = core::validity(self.sim.ttl, elapsed, drift);
+
+        match validity {
+            Some(validity) if tally.has_quorum(quorum) => {
+                let until = self.now + validity.min(self.sim.hold);
+                self.holds.push(Hold {
+                    client,
+                    from: self.now,
+                    until,
+                });
+                self.schedule(
+                    until,
+                    Event::Release {
+                        client,
+                        attempt: attempt.id,
+                    },
+                );
+            }
+            _ => {
+                self.send(client, attempt.id, Op::Unlock);
+                let at = self.now + core::jitter(self.sim.retry_delay, &mut self.rng);
+                self.schedule(at, Event::Start { client });
+            }
+        }
+    }
+}
+
+fn respond(client: usize, attempt: u64, result: Result<bool, ()>) -> Event {
+    Event::Respond {
+        client,
+        attempt,
+        result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_simulation_mutual_exclusion() {
+        for seed in 0..200 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let instances = rng.gen_range(1..=7);
+            let clients = rng.gen_range(2..=5);
+            let mut sim = Simulation::new(seed, instances, clients)
+                .ttl(rng.gen_range(100..1000) * MS)
+                .hold(rng.gen_range(10..1000) * MS)
+                .latency(MS, rng.gen_range(1..20) * MS)
+                .duration(Duration::from_secs(10));
+            for instance in 0..instances {
+                sim = sim.clock_rate(instance, rng.gen_range(0.995..1.005));
+            }
+            for _ in 0..rng.gen_range(0..5) {
+                let from = rng.gen_range(0..10_000) * MS;
+                sim = sim.partition(
+                    rng.gen_range(0..clients),
+                    rng.gen_range(0..instances),
+                    from,
+                    from + rng.gen_range(0..2000) * MS,
+                );
+            }
+
+            let report = sim.run();
+            assert!(!report.holds.is_empty(), "seed {}", seed);
+            assert!(report.is_safe(), "seed {}: {:?}", seed, report.violations);
+        }
+    }
+
+    #[test]
+    fn test_simulation_detects_fast_clocks() {
+        let mut sim = Simulation::new(0, 3, 3).hold(Duration::from_secs(1));
+        for instance in 0..3 {
+            sim = sim.clock_rate(instance, 2.0);
+        }
+
+        assert!(!sim.run().is_safe());
+    }
+
+    #[test]
+    fn test_simulation_detects_restart_without_persistence() {
+        // Instance 1 forgets the lock of client 0, which only held instances 0 and 1
+        let sim = Simulation::new(0, 3, 2)
+            .retry_delay(Duration::ZERO)
+            .hold(Duration::from_millis(900))
+            .duration(Duration::from_millis(500))
+            .partition(0, 2, Duration::ZERO, Duration::from_secs(10))
+            .partition(1, 0, Duration::ZERO, Duration::from_secs(10));
+        assert!(sim.run().is_safe());
+
+        let sim = sim.crash(1, Duration::from_millis(100), Duration::from_millis(10));
+        assert!(!sim.run().is_safe());
+    }
+}
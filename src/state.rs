@@ -0,0 +1,360 @@
+#[cfg(feature = "leak-detection")]
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "leak-detection")]
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "leak-detection")]
+use crate::event::Event;
+use crate::lock::{Compatibility, ConnectStrategy, Lock};
+use crate::rt;
+
+/// Snapshot of a lock manager's state, produced by `LockManager::dump_state`.
+///
+/// Meant for logs and debug endpoints during incidents.
+/// With the `serde` feature enabled, all snapshot types implement `Serialize`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StateSnapshot {
+    pub config: ConfigSnapshot,
+    pub instances: Vec<InstanceState>,
+    pub active_locks: Vec<ActiveLockState>,
+    pub stats: StatsSnapshot,
+}
+
+/// The manager's effective configuration.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConfigSnapshot {
+    pub quorum: u32,
+    pub fault_tolerance: u32,
+    pub retry_count: u32,
+    pub retry_delay: Duration,
+    pub min_ttl: Duration,
+    pub max_ttl: Duration,
+    pub verify_after_lock: bool,
+    pub fencing: bool,
+    pub max_in_flight: Option<usize>,
+    pub token_pool_size: Option<usize>,
+    pub compatibility: Compatibility,
+    pub clock_drift_factor: f32,
+    pub clock_drift_padding: Duration,
+    pub round_budget: Option<Duration>,
+    pub round_concurrency: Option<usize>,
+    pub latency_ordering: bool,
+    pub restart_quarantine: Option<Duration>,
+    pub reset_on_restart: bool,
+    pub maintenance_mode: bool,
+    pub shadow_mode: bool,
+    pub contention_threshold: Option<u32>,
+    pub retry_warmup: Option<(Duration, Duration)>,
+    pub quorum_margin: u32,
+    pub validity_from_call: bool,
+    pub renewal_interval: Option<Duration>,
+    pub shared_connections: bool,
+    pub connect_strategy: ConnectStrategy,
+    pub client_name: Option<String>,
+    pub response_timeout: Option<Duration>,
+    pub scriptless: bool,
+}
+
+/// Health of one Redis instance, as observed by a `PING`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct InstanceState {
+    pub address: String,
+    pub reachable: bool,
+    pub latency: Option<Duration>,
+    /// Moving average of the instance's response times in past operations,
+    /// see `LockManager::set_latency_ordering`.
+    pub average_latency: Option<Duration>,
+    /// Whether the instance is excluded from quorums after a restart,
+    /// see `LockManager::set_restart_quarantine`.
+    pub quarantined: bool,
+    /// The run id the instance reported to `LockManager::check_restarts` last.
+    pub run_id: Option<String>,
+}
+
+/// A lock acquired through the manager that was neither released nor has expired.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ActiveLockState {
+    /// The resource, as UTF-8 if printable, hex otherwise.
+    pub resource: String,
+    pub remaining_validity: Duration,
+    pub held_for: Duration,
+}
+
+/// Counters since the manager was created.
+///
+/// With the `metrics` feature enabled, the same counters are also recorded through the
+/// `metrics` crate as `rslock_acquired_total`, `rslock_acquire_failures_total`,
+/// `rslock_extended_total`, `rslock_extend_failures_total` and `rslock_released_total`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatsSnapshot {
+    pub acquired: u64,
+    pub acquire_failures: u64,
+    pub extended: u64,
+    pub extend_failures: u64,
+    pub released: u64,
+}
+
+struct ActiveLock {
+    resource: Vec<u8>,
+    expires_at: Instant,
+    held_since: Instant,
+    #[cfg(feature = "leak-detection")]
+    created: Arc<Backtrace>,
+}
+
+// Keeps track of the locks and operations of a manager and all its clones
+#[derive(Default)]
+pub(crate) struct Tracker {
+    active: Mutex<HashMap<Vec<u8>, ActiveLock>>,
+    acquired: AtomicU64,
+    acquire_failures: AtomicU64,
+    extended: AtomicU64,
+    extend_failures: AtomicU64,
+    released: AtomicU64,
+    latencies: Mutex<Vec<Option<Duration>>>,
+    runs: Mutex<Vec<Run>>,
+    // The child locks of held locks, by the value of their parent, see `LockGuard::lock_child`
+    children: Mutex<HashMap<Vec<u8>, Vec<Child>>>,
+}
+
+// A child lock, released along with its parent
+pub(crate) struct Child {
+    pub(crate) resource: Vec<u8>,
+    pub(crate) val: Vec<u8>,
+}
+
+// The run of an instance, as last reported by `INFO`
+#[derive(Debug, Clone, Default)]
+struct Run {
+    run_id: Option<String>,
+    quarantined_until: Option<Instant>,
+}
+
+impl Tracker {
+    fn active(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, ActiveLock>> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn acquired(&self, lock: &Lock<'_>) {
+        self.acquired.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("rslock_acquired_total").increment(1);
+        self.track(lock);
+    }
+
+    pub(crate) fn extended(&self, lock: &Lock<'_>) {
+        self.extended.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("rslock_extended_total").increment(1);
+        self.track(lock);
+    }
+
+    pub(crate) fn acquire_failed(&self) {
+        self.acquire_failures.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("rslock_acquire_failures_total").increment(1);
+    }
+
+    pub(crate) fn extend_failed(&self) {
+        self.extend_failures.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("rslock_extend_failures_total").increment(1);
+    }
+
+    pub(crate) fn released(&self, val: &[u8]) {
+        self.released.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("rslock_released_total").increment(1);
+        self.active().remove(val);
+        // Released on its own before its parent
+        let mut children = self.children();
+        children.retain(|_, siblings| {
+            siblings.retain(|child| child.val != val);
+            !siblings.is_empty()
+        });
+    }
+
+    fn children(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, Vec<Child>>> {
+        self.children.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn add_child(&self, parent: &[u8], child: Child) {
+        self.children()
+            .entry(parent.to_vec())
+            .or_default()
+            .push(child);
+    }
+
+    // Removes and returns the children of a lock, their children and so on,
+    // parents before their children
+    pub(crate) fn take_descendants(&self, val: &[u8]) -> Vec<Child> {
+        let mut children = self.children();
+        let mut descendants = children.remove(val).unwrap_or_default();
+        let mut i = 0;
+        while i < descendants.len() {
+            let grandchildren = children.remove(&descendants[i].val).unwrap_or_default();
+            descendants.extend(grandchildren);
+            i += 1;
+        }
+        descendants
+    }
+
+    fn track(&self, lock: &Lock<'_>) {
+        let expires_at = rt::now() + lock.remaining_validity();
+        let mut active = self.active();
+        // Forget about locks that expired without being released,
+        // unless they are reported by `leaked`
+        #[cfg(not(feature = "leak-detection"))]
+        active.retain(|_, lock| lock.expires_at > rt::now());
+        #[cfg(feature = "leak-detection")]
+        let created = active.get(&lock.val).map_or_else(
+            || Arc::new(Backtrace::force_capture()),
+            |active| active.created.clone(),
+        );
+        active.insert(
+            lock.val.clone(),
+            ActiveLock {
+                resource: lock.resource.clone(),
+                expires_at,
+                held_since: lock.held_since,
+                #[cfg(feature = "leak-detection")]
+                created,
+            },
+        );
+    }
+
+    // Forgets about locks that expired without being released and reports them
+    #[cfg(feature = "leak-detection")]
+    pub(crate) fn leaked(&self) -> Vec<Event> {
+        let now = rt::now();
+        let mut leaked = Vec::new();
+        self.active().retain(|_, lock| {
+            if lock.expires_at > now {
+                return true;
+            }
+            leaked.push(Event::LockLeaked {
+                resource: lock.resource.clone(),
+                backtrace: lock.created.to_string(),
+            });
+            false
+        });
+        leaked
+    }
+
+    pub(crate) fn active_locks(&self) -> Vec<ActiveLockState> {
+        let now = rt::now();
+        self.active()
+            .values()
+            .filter(|lock| lock.expires_at > now)
+            .map(|lock| ActiveLockState {
+                resource: crate::lock::Bytes(&lock.resource).to_string(),
+                remaining_validity: lock.expires_at - now,
+                held_for: now - lock.held_since,
+            })
+            .collect()
+    }
+
+    // Folds the duration of an operation on an instance into its moving average
+    pub(crate) fn record_latency(&self, instance: usize, sample: Duration) {
+        let mut latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        if latencies.len() <= instance {
+            latencies.resize(instance + 1, None);
+        }
+        let average = &mut latencies[instance];
+        *average = Some(match *average {
+            Some(average) => (average * 7 + sample) / 8,
+            None => sample,
+        });
+    }
+
+    pub(crate) fn average_latency(&self, instance: usize) -> Option<Duration> {
+        let latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        latencies.get(instance).copied().flatten()
+    }
+
+    // The positions of `instances` ordered by latency, fastest first. Instances without
+    // measurements come first, so that they get measured.
+    pub(crate) fn latency_order(&self, instances: &[usize]) -> Vec<usize> {
+        let latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        let mut order: Vec<usize> = (0..instances.len()).collect();
+        order.sort_by_key(|&pos| latencies.get(instances[pos]).copied().flatten());
+        order
+    }
+
+    // Records the run id reported by an instance and returns whether it changed, i.e. whether
+    // the instance restarted since the last report. A restarted instance is quarantined for
+    // `quarantine`.
+    pub(crate) fn observe_run(
+        &self,
+        instance: usize,
+        run_id: String,
+        quarantine: Option<Duration>,
+    ) -> bool {
+        let mut runs = self.runs.lock().unwrap_or_else(|e| e.into_inner());
+        if runs.len() <= instance {
+            runs.resize(instance + 1, Run::default());
+        }
+        let run = &mut runs[instance];
+        let restarted = run.run_id.as_ref().is_some_and(|known| *known != run_id);
+        if restarted {
+            run.quarantined_until = quarantine.map(|quarantine| rt::now() + quarantine);
+        }
+        run.run_id = Some(run_id);
+        restarted
+    }
+
+    // Number of unexpired locks held with resources starting with `prefix`
+    pub(crate) fn held(&self, prefix: &[u8]) -> usize {
+        let now = rt::now();
+        self.active()
+            .values()
+            .filter(|lock| lock.expires_at > now && lock.resource.starts_with(prefix))
+            .count()
+    }
+
+    pub(crate) fn run_id(&self, instance: usize) -> Option<String> {
+        let runs = self.runs.lock().unwrap_or_else(|e| e.into_inner());
+        runs.get(instance).and_then(|run| run.run_id.clone())
+    }
+
+    pub(crate) fn forget_latency(&self, instance: usize) {
+        let mut latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(average) = latencies.get_mut(instance) {
+            *average = None;
+        }
+    }
+
+    pub(crate) fn is_quarantined(&self, instance: usize) -> bool {
+        let runs = self.runs.lock().unwrap_or_else(|e| e.into_inner());
+        runs.get(instance)
+            .and_then(|run| run.quarantined_until)
+            .is_some_and(|until| until > rt::now())
+    }
+
+    pub(crate) fn stats(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            acquired: self.acquired.load(Ordering::Relaxed),
+            acquire_failures: self.acquire_failures.load(Ordering::Relaxed),
+            extended: self.extended.load(Ordering::Relaxed),
+            extend_failures: self.extend_failures.load(Ordering::Relaxed),
+            released: self.released.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl std::fmt::Debug for Tracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracker")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
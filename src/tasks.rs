@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use futures::future::{AbortHandle, Abortable};
+use futures::Future;
+use tokio::sync::Notify;
+
+use crate::rt;
+
+/// The background tasks spawned by a lock manager and its clones, e.g. the renewals of
+/// `LockManager::keep_alive`, returned by `LockManager::tasks`.
+///
+/// Lets applications make sure that no task outlives them, by aborting the tasks with
+/// `abort_all` and waiting for them to stop with `join`. Aborted tasks stop at their next
+/// suspension point, and the features they back report it, e.g. renewed locks are lost.
+/// Releases of dropped guards are not tracked, as aborting them would leave locks held
+/// until they expire.
+#[derive(Default)]
+pub struct TaskSet {
+    tasks: Mutex<Tasks>,
+    idle: Notify,
+}
+
+#[derive(Default)]
+struct Tasks {
+    next_id: u64,
+    running: HashMap<u64, AbortHandle>,
+}
+
+impl TaskSet {
+    fn tasks(&self) -> MutexGuard<'_, Tasks> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Number of tasks that are still running.
+    pub fn len(&self) -> usize {
+        self.tasks().running.len()
+    }
+
+    /// Whether all tasks have finished.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Abort all running tasks. Tasks spawned afterwards are not affected.
+    pub fn abort_all(&self) {
+        for handle in self.tasks().running.values() {
+            handle.abort();
+        }
+    }
+
+    /// Wait until all tasks, including those spawned while waiting, have finished.
+    pub async fn join(&self) {
+        loop {
+            let notified = self.idle.notified();
+            if self.is_empty() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // Runs `future` on the runtime as a task of the set
+    pub(crate) fn spawn<F>(self: &Arc<Self>, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (handle, registration) = AbortHandle::new_pair();
+        let id = {
+            let mut tasks = self.tasks();
+            let id = tasks.next_id;
+            tasks.next_id += 1;
+            tasks.running.insert(id, handle);
+            id
+        };
+
+        let tasks = self.clone();
+        rt::spawn(async move {
+            let _ = Abortable::new(future, registration).await;
+            let mut running = tasks.tasks();
+            running.running.remove(&id);
+            if running.running.is_empty() {
+                tasks.idle.notify_waiters();
+            }
+        });
+    }
+}
+
+impl fmt::Debug for TaskSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskSet")
+            .field("running", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_task_set_abort_all() {
+        let tasks = Arc::new(TaskSet::default());
+        tasks.spawn(futures::future::pending());
+        tasks.spawn(rt::sleep(Duration::from_millis(10)));
+        assert_eq!(tasks.len(), 2);
+
+        rt::sleep(Duration::from_millis(100)).await;
+        assert_eq!(tasks.len(), 1);
+
+        tasks.abort_all();
+        rt::timeout(Duration::from_secs(1), tasks.join())
+            .await
+            .expect("aborted tasks should stop");
+        assert!(tasks.is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! Support for integration tests against real Redis instances.
+//!
+//! `exercise` runs a short lock, extend, keep-alive and unlock cycle through the public API.
+//! It sleeps, times out and spawns through whichever runtime the crate was built for, so
+//! running it under every feature combination an application ships with, e.g.
+//! `cargo test --no-default-features --features tokio-comp`, catches combinations where a
+//! runtime dependency is missing. The crate's own tests use it as well.
+//!
+//! Only available with the `test-support` feature.
+
+use std::time::Duration;
+
+use crate::core::UnlockOutcome;
+use crate::lock::{LockError, LockManager};
+use crate::rt;
+
+/// A step of `exercise` that did not behave as expected.
+#[derive(Debug, thiserror::Error)]
+#[error("{step}: {reason}")]
+pub struct CheckFailure {
+    /// The step that failed, e.g. `"extend"`.
+    pub step: &'static str,
+    /// What went wrong.
+    pub reason: String,
+}
+
+pub(crate) fn fail(step: &'static str, reason: impl ToString) -> CheckFailure {
+    CheckFailure {
+        step,
+        reason: reason.to_string(),
+    }
+}
+
+/// Run a lock, extend, keep-alive and unlock cycle against the instances at `addresses`,
+/// failing with the first step that misbehaves.
+///
+/// Uses a fresh random resource, so it can run concurrently with other tests on the same
+/// instances. Takes about a second, most of it waiting for a renewal.
+pub async fn exercise(addresses: &[String]) -> Result<(), CheckFailure> {
+    let ttl = Duration::from_millis(500);
+    let rl = LockManager::try_new(addresses.to_vec()).map_err(|e| fail("connect", e))?;
+    let mut rl2 = rl.clone();
+    rl2.set_retry(2, Duration::from_millis(10));
+    let resource = rl.get_unique_lock_id().map_err(|e| fail("connect", e))?;
+
+    let lock = rl.lock(&resource, ttl).await.map_err(|e| fail("lock", e))?;
+    match rl2.lock(&resource, ttl).await {
+        Err(LockError::Contended { .. } | LockError::Unavailable) => (),
+        Ok(_) => return Err(fail("exclusion", "locked a held resource")),
+        Err(e) => return Err(fail("exclusion", e)),
+    }
+
+    rt::sleep(Duration::from_millis(10)).await;
+    if lock.remaining_validity() >= Duration::from_millis(lock.validity_time as u64) {
+        return Err(fail("clock", "remaining validity did not decrease"));
+    }
+    let lock = rl.extend(&lock, ttl).await.map_err(|e| fail("extend", e))?;
+
+    // Renewals run in a spawned task
+    let renewal = rl
+        .keep_alive(&lock, ttl)
+        .map_err(|e| fail("keep-alive", e))?;
+    rt::sleep(ttl + ttl / 2).await;
+    if renewal.is_lost() || rl.verify(&lock).await < rl.quorum() {
+        return Err(fail("keep-alive", "lock expired while kept alive"));
+    }
+    drop(renewal);
+
+    match rl.unlock(&lock).await {
+        UnlockOutcome::Released => (),
+        outcome => return Err(fail("unlock", format!("{:?}", outcome))),
+    }
+    let relocked = rl2
+        .lock(&resource, ttl)
+        .await
+        .map_err(|e| fail("relock", e))?;
+    rl2.unlock(&relocked).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_exercise() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        exercise(&addresses).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use crate::lock::{Lock, LockError, LockGuard, LockManager};
+use crate::renewal::AutoExtendedGuard;
+use crate::resource::LockResource;
+
+/// A `LockGuard` whose TTL is part of its type, acquired with `LockManager::lock_timed`.
+///
+/// Code handling a `TimedGuard<'_, 30_000>` knows the lock's TTL class without passing
+/// durations around, and derives its extension schedule from the associated constants.
+/// A TTL of zero, or too large for Redis, fails the build.
+///
+/// ```rust,no_run
+/// # async fn run(rl: &rslock::LockManager) -> Result<(), rslock::LockError> {
+/// type JobGuard<'a> = rslock::TimedGuard<'a, 30_000>;
+///
+/// let mut guard: JobGuard = rl.lock_timed(b"job").await?;
+/// loop {
+///     // Work for at most `JobGuard::EXTEND_INTERVAL` between extensions
+///     guard.extend().await?;
+/// #   break;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```rust,compile_fail
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), rslock::LockError> {
+/// # let rl = rslock::LockManager::new(vec!["redis://127.0.0.1:6379/"]);
+/// let guard: rslock::TimedGuard<'_, 0> = rl.lock_timed(b"job").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TimedGuard<'a, const TTL_MS: u64> {
+    guard: LockGuard<'a>,
+}
+
+impl<'a, const TTL_MS: u64> TimedGuard<'a, TTL_MS> {
+    /// The TTL the lock is acquired and extended with.
+    pub const TTL: Duration = Duration::from_millis(TTL_MS);
+
+    /// How often to extend the lock: a third of the TTL, leaving time for two more attempts
+    /// before it expires.
+    pub const EXTEND_INTERVAL: Duration = Duration::from_millis(TTL_MS / 3);
+
+    // Evaluated when the guard is created, failing the build for invalid TTLs
+    const VALID: () = assert!(
+        TTL_MS > 0 && TTL_MS <= i64::MAX as u64,
+        "the TTL must be positive and fit into a Redis PX argument"
+    );
+
+    /// The held lock, updated by `extend`.
+    pub fn lock(&self) -> &Lock<'a> {
+        &self.guard.lock
+    }
+
+    /// Extend the lock by `TTL`, see `LockManager::extend`.
+    pub async fn extend(&mut self) -> Result<(), LockError> {
+        let lock = &self.guard.lock;
+        self.guard.lock = lock.lock_manager.extend(lock, Self::TTL).await?;
+        Ok(())
+    }
+
+    /// Keep extending the lock by `TTL` in the background, see `LockGuard::into_auto_extended`.
+    pub fn into_auto_extended(self) -> Result<AutoExtendedGuard<'a>, LockError> {
+        self.guard.into_auto_extended(Self::TTL)
+    }
+
+    /// Forget the TTL class, returning the plain guard.
+    pub fn into_guard(self) -> LockGuard<'a> {
+        self.guard
+    }
+}
+
+impl LockManager {
+    /// Acquire the lock for the given resource with the TTL given by the guard's type,
+    /// see `TimedGuard` and `LockManager::lock`.
+    pub async fn lock_timed<const TTL_MS: u64, R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+    ) -> Result<TimedGuard<'_, TTL_MS>, LockError> {
+        let () = TimedGuard::<TTL_MS>::VALID;
+        let lock = self.lock(resource, TimedGuard::<TTL_MS>::TTL).await?;
+        Ok(TimedGuard {
+            guard: LockGuard { lock },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[test]
+    fn test_timed_guard_constants() {
+        assert_eq!(TimedGuard::<30_000>::TTL, Duration::from_secs(30));
+        assert_eq!(
+            TimedGuard::<30_000>::EXTEND_INTERVAL,
+            Duration::from_secs(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lock_timed() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+
+        let mut guard = rl.lock_timed::<1000, _>(&key).await?;
+        assert!(guard.lock().remaining_validity() <= Duration::from_millis(1000));
+        guard.extend().await?;
+        assert!(rl.lock(&key, Duration::from_millis(1000)).await.is_err());
+
+        let lock = guard.into_guard().into_lock();
+        rl.unlock(&lock).await;
+
+        Ok(())
+    }
+}
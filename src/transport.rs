@@ -0,0 +1,965 @@
+//! Redis specifics: the scripts and the commands run against a single instance.
+
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::aio::{self, MultiplexedConnection};
+use redis::Value::Okay;
+use redis::{
+    Client, Cmd, ErrorKind, Pipeline, RedisError, RedisFuture, RedisResult, ScriptInvocation, Value,
+};
+
+use crate::core::{owns, UnlockOutcome};
+use crate::lock::Lock;
+use crate::registry::ConnectionRegistry;
+use crate::rt;
+
+// Prepends the Lua counterpart of `core::value_token` and `core::owns` to a script
+macro_rules! versioned_script {
+    ($script:literal) => {
+        concat!(
+            r#"
+local function token(v)
+  if string.byte(v, 1) == 1 then
+    local n = string.byte(v, 2)
+    if n and n > 0 and #v >= 2 + n then
+      return string.sub(v, 3, 2 + n)
+    end
+  end
+  return v
+end
+local function owns(v, ours)
+  return v == ours or (v and token(v) == token(ours))
+end
+"#,
+            $script
+        )
+    };
+}
+pub(crate) const UNLOCK_SCRIPT: &str = versioned_script!(
+    r#"
+local val = redis.call("GET", KEYS[1])
+if owns(val, ARGV[1]) then
+  return redis.call("DEL", KEYS[1])
+elseif val then
+  return -1
+else
+  return 0
+end
+"#
+);
+pub(crate) const EXTEND_SCRIPT: &str = versioned_script!(
+    r#"
+if not owns(redis.call("get", KEYS[1]), ARGV[1]) then
+  return 0
+else
+  if redis.call("set", KEYS[1], ARGV[1], "PX", ARGV[2]) ~= nil then
+    return 1
+  else
+    return 0
+  end
+end
+"#
+);
+const UNLOCK_MANY_SCRIPT: &str = versioned_script!(
+    r#"
+local released = 0
+for i, key in ipairs(KEYS) do
+  if owns(redis.call("GET", key), ARGV[i]) then
+    released = released + redis.call("DEL", key)
+  end
+end
+return released
+"#
+);
+// Maximum number of locks released by a single UNLOCK_MANY_SCRIPT call
+const UNLOCK_MANY_BATCH_SIZE: usize = 100;
+const EXTEND_MANY_SCRIPT: &str = versioned_script!(
+    r#"
+local extended = {}
+for i, key in ipairs(KEYS) do
+  local val = ARGV[2 * i - 1]
+  if owns(redis.call("GET", key), val) and redis.call("SET", key, val, "PX", ARGV[2 * i]) then
+    extended[i] = 1
+  else
+    extended[i] = 0
+  end
+end
+return extended
+"#
+);
+// Maximum number of locks extended by a single EXTEND_MANY_SCRIPT call
+const EXTEND_MANY_BATCH_SIZE: usize = 100;
+const TOMBSTONE_SCRIPT: &str = versioned_script!(
+    r#"
+local val = redis.call("GET", KEYS[1])
+if owns(val, ARGV[1]) then
+  return redis.call("SET", KEYS[1], ARGV[2], "PX", ARGV[3]) and 1 or 0
+elseif val then
+  return -1
+else
+  return 0
+end
+"#
+);
+const TOMBSTONE_VALUE: &[u8] = b"rslock:tombstone";
+const LOCKED_INCR_SCRIPT: &str = versioned_script!(
+    r#"
+if owns(redis.call("GET", KEYS[1]), ARGV[1]) then
+  return redis.call("INCRBY", KEYS[2], ARGV[2])
+else
+  return false
+end
+"#
+);
+const LOCKED_SET_SCRIPT: &str = versioned_script!(
+    r#"
+if owns(redis.call("GET", KEYS[1]), ARGV[1]) then
+  if ARGV[3] then
+    redis.call("SET", KEYS[2], ARGV[2], "PX", ARGV[3])
+  else
+    redis.call("SET", KEYS[2], ARGV[2])
+  end
+  return 1
+else
+  return 0
+end
+"#
+);
+const LOCKED_GET_SCRIPT: &str = versioned_script!(
+    r#"
+if owns(redis.call("GET", KEYS[1]), ARGV[1]) then
+  return {1, redis.call("GET", KEYS[2])}
+else
+  return false
+end
+"#
+);
+const FENCED_LOCK_SCRIPT: &str = r#"
+if redis.call("SET", KEYS[1], ARGV[1], "NX", "PX", ARGV[2]) then
+  return redis.call("INCR", KEYS[2])
+else
+  return false
+end
+"#;
+const RAISE_FENCING_SCRIPT: &str = r#"
+if tonumber(redis.call("GET", KEYS[1]) or "0") < tonumber(ARGV[1]) then
+  redis.call("SET", KEYS[1], ARGV[1])
+end
+return 1
+"#;
+// Readers of a resource are tracked in a set next to it, KEYS[2], each holding a key of its own.
+// The members read beforehand are passed from ARGV[3] on, their keys from KEYS[first] on,
+// and members whose key has expired are dropped before counting.
+macro_rules! readers_script {
+    ($script:literal) => {
+        concat!(
+            r#"
+local function prune_readers(readers, first)
+  for i = first, #KEYS do
+    if redis.call("EXISTS", KEYS[i]) == 0 then
+      redis.call("SREM", readers, ARGV[3 + i - first])
+    end
+  end
+end
+"#,
+            $script
+        )
+    };
+}
+// Writers waiting for the readers to leave announce themselves in KEYS[4], see `writer_key`
+const READ_LOCK_SCRIPT: &str = readers_script!(
+    r#"
+prune_readers(KEYS[2], 5)
+if redis.call("EXISTS", KEYS[1]) == 1 or redis.call("EXISTS", KEYS[4]) == 1 then
+  return 0
+end
+redis.call("SADD", KEYS[2], ARGV[1])
+redis.call("SET", KEYS[3], ARGV[1], "PX", ARGV[2])
+return 1
+"#
+);
+const WRITE_LOCK_SCRIPT: &str = readers_script!(
+    r#"
+prune_readers(KEYS[2], 3)
+if redis.call("SCARD", KEYS[2]) > 0 then
+  return 0
+end
+return redis.call("SET", KEYS[1], ARGV[1], "NX", "PX", ARGV[2]) and 1 or 0
+"#
+);
+// Like `UNLOCK_SCRIPT` for the key of a read lock, KEYS[1], also leaving the readers set, KEYS[2]
+const READ_UNLOCK_SCRIPT: &str = versioned_script!(
+    r#"
+local val = redis.call("GET", KEYS[1])
+if val and not owns(val, ARGV[1]) then
+  return -1
+end
+redis.call("SREM", KEYS[2], ARGV[1])
+if val then
+  return redis.call("DEL", KEYS[1])
+end
+return 0
+"#
+);
+const EXPIRE_PERSISTENT_SCRIPT: &str = r#"
+if redis.call("PTTL", KEYS[1]) == -1 then
+  return redis.call("PEXPIRE", KEYS[1], ARGV[1])
+else
+  return 0
+end
+"#;
+// Number of keys requested per SCAN call
+const SCAN_COUNT: usize = 100;
+// All scripts used by the manager, for preloading
+pub(crate) const SCRIPTS: &[&str] = &[
+    UNLOCK_SCRIPT,
+    EXTEND_SCRIPT,
+    UNLOCK_MANY_SCRIPT,
+    EXTEND_MANY_SCRIPT,
+    TOMBSTONE_SCRIPT,
+    LOCKED_INCR_SCRIPT,
+    LOCKED_SET_SCRIPT,
+    LOCKED_GET_SCRIPT,
+    FENCED_LOCK_SCRIPT,
+    RAISE_FENCING_SCRIPT,
+    READ_LOCK_SCRIPT,
+    WRITE_LOCK_SCRIPT,
+    READ_UNLOCK_SCRIPT,
+    EXPIRE_PERSISTENT_SCRIPT,
+];
+
+// Commands run by the manager, directly or from scripts, as ACL rules
+pub(crate) const REQUIRED_ACL: &[&str] = &[
+    "+set",
+    "+get",
+    "+pttl",
+    "+del",
+    "+incr",
+    "+incrby",
+    "+exists",
+    "+sadd",
+    "+srem",
+    "+scard",
+    "+smembers",
+    "+pexpire",
+    "+evalsha",
+    "+script|load",
+    "+ping",
+    "+info",
+    "+config|get",
+    "+scan",
+];
+
+// Commands unlocking and extending without scripts, see `LockManager::set_scriptless`
+pub(crate) const SCRIPTLESS_ACL: &[&str] = &["+watch", "+unwatch", "+multi", "+exec"];
+
+// Command naming connections, see `LockManager::set_client_name`
+pub(crate) const CLIENT_NAME_ACL: &str = "+client|setname";
+
+// The result standing in for an instance excluded from quorums after a restart
+pub(crate) fn quarantined() -> RedisError {
+    RedisError::from((
+        ErrorKind::TryAgain,
+        "Instance is quarantined after a restart",
+    ))
+}
+
+// The command an instance refused for lack of permissions, `AUTH` if it rejected the credentials.
+// Falls back to the error detail if it names no command, e.g. for denied keys.
+pub(crate) fn denied_command(e: &RedisError) -> Option<String> {
+    if e.kind() == ErrorKind::AuthenticationFailed || e.code() == Some("WRONGPASS") {
+        return Some("AUTH".to_string());
+    }
+    if e.code() != Some("NOPERM") {
+        return None;
+    }
+    let detail = e.detail().unwrap_or_default();
+    Some(detail.split('\'').nth(1).unwrap_or(detail).to_string())
+}
+
+// Settings applied to every connection
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionSettings {
+    pub(crate) client_name: Option<String>,
+    pub(crate) response_timeout: Option<Duration>,
+    // Whether to unlock and extend with transactions instead of scripts,
+    // shared by clones as `provision` may switch it on
+    pub(crate) scriptless: Arc<AtomicBool>,
+}
+
+impl ConnectionSettings {
+    pub(crate) fn is_scriptless(&self) -> bool {
+        self.scriptless.load(Ordering::Relaxed)
+    }
+}
+
+// Attempts at a transaction before giving up on a key that keeps changing
+const WATCH_ATTEMPTS: usize = 3;
+
+// An instance as seen by a single operation: its client and where connections come from
+#[derive(Clone, Copy)]
+pub(crate) struct Instance<'a> {
+    pub(crate) client: &'a Client,
+    pub(crate) registry: Option<&'a ConnectionRegistry>,
+    pub(crate) settings: &'a ConnectionSettings,
+}
+
+impl<'a> Instance<'a> {
+    pub(crate) async fn connect(self) -> RedisResult<Connection<'a>> {
+        let name = self.settings.client_name.as_deref();
+        let con = match self.registry {
+            Some(registry) => {
+                Inner::Shared(self.timed(registry.connection(self.client, name)).await?)
+            }
+            None => return self.connect_dedicated().await,
+        };
+        Ok(Connection {
+            con,
+            instance: self,
+        })
+    }
+
+    // A connection that is never shared, as needed for `WATCH` and `MULTI`
+    async fn connect_dedicated(self) -> RedisResult<Connection<'a>> {
+        let mut con = self.timed(self.client.get_async_connection()).await?;
+        if let Some(name) = self.settings.client_name.as_deref() {
+            self.timed(set_name(&mut con, name)).await?;
+        }
+        Ok(Connection {
+            con: Inner::Own(con),
+            instance: self,
+        })
+    }
+
+    async fn timed<T>(self, fut: impl Future<Output = RedisResult<T>>) -> RedisResult<T> {
+        match self.settings.response_timeout {
+            Some(timeout) => rt::timeout(timeout, fut).await.unwrap_or_else(|_| {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "Redis response timed out").into())
+            }),
+            None => fut.await,
+        }
+    }
+
+    // Forgets the shared connection to the instance, if any, so that the next operation reconnects
+    pub(crate) fn evict(self) {
+        if let Some(registry) = self.registry {
+            registry.evict(self.client, self.settings.client_name.as_deref());
+        }
+    }
+
+    // Evicts a shared connection from the registry once it fails
+    fn check<T>(self, result: &RedisResult<T>) {
+        if let (Some(registry), Err(e)) = (self.registry, result) {
+            if e.is_io_error() || e.is_connection_dropped() {
+                registry.evict(self.client, self.settings.client_name.as_deref());
+            }
+        }
+    }
+}
+
+pub(crate) async fn set_name(con: &mut impl aio::ConnectionLike, name: &str) -> RedisResult<()> {
+    redis::cmd("CLIENT")
+        .arg("SETNAME")
+        .arg(name)
+        .query_async(con)
+        .await
+}
+
+// A connection of the operation's own, or one shared through a `ConnectionRegistry`
+pub(crate) struct Connection<'a> {
+    con: Inner,
+    instance: Instance<'a>,
+}
+
+enum Inner {
+    Own(aio::Connection),
+    Shared(MultiplexedConnection),
+}
+
+impl aio::ConnectionLike for Connection<'_> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let result = match &mut self.con {
+                Inner::Own(con) => self.instance.timed(con.req_packed_command(cmd)).await,
+                Inner::Shared(con) => self.instance.timed(con.req_packed_command(cmd)).await,
+            };
+            self.instance.check(&result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let result = match &mut self.con {
+                Inner::Own(con) => {
+                    let fut = con.req_packed_commands(cmd, offset, count);
+                    self.instance.timed(fut).await
+                }
+                Inner::Shared(con) => {
+                    let fut = con.req_packed_commands(cmd, offset, count);
+                    self.instance.timed(fut).await
+                }
+            };
+            self.instance.check(&result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        match &self.con {
+            Inner::Own(con) => con.get_db(),
+            Inner::Shared(con) => con.get_db(),
+        }
+    }
+}
+
+// Returns `Ok(false)` if the key is already held by someone else
+pub(crate) async fn lock_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    val: Vec<u8>,
+    ttl: usize,
+) -> RedisResult<bool> {
+    let mut con = instance.connect().await?;
+    let result: Value = redis::cmd("SET")
+        .arg(resource)
+        .arg(val)
+        .arg("NX")
+        .arg("PX")
+        .arg(ttl)
+        .query_async(&mut con)
+        .await?;
+
+    Ok(result == Okay)
+}
+
+// The counter the fencing tokens of `resource` are taken from
+pub(crate) fn fencing_key(resource: &[u8]) -> Vec<u8> {
+    [resource, b":fencing"].concat()
+}
+
+// The key a writer waiting for the readers of `resource` to leave holds, keeping new readers out
+pub(crate) fn writer_key(resource: &[u8]) -> Vec<u8> {
+    [resource, b":writer"].concat()
+}
+
+// The key held by the reader `val` of the readers set `readers`
+pub(crate) fn reader_key(readers: &[u8], val: &[u8]) -> Vec<u8> {
+    [readers, b":", val].concat()
+}
+
+// Reads the members of the readers set, to be passed to `prune_readers` with `pass_readers`
+async fn readers_instance(
+    con: &mut impl aio::ConnectionLike,
+    readers: &[u8],
+) -> RedisResult<Vec<Vec<u8>>> {
+    redis::cmd("SMEMBERS").arg(readers).query_async(con).await
+}
+
+fn pass_readers(invocation: &mut ScriptInvocation<'_>, readers: &[u8], members: &[Vec<u8>]) {
+    for member in members {
+        invocation.key(reader_key(readers, member)).arg(member);
+    }
+}
+
+// Returns the incremented fencing counter, `Ok(None)` if the key is already held by someone else
+pub(crate) async fn fenced_lock_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    counter: &[u8],
+    val: &[u8],
+    ttl: usize,
+) -> RedisResult<Option<u64>> {
+    let mut con = instance.connect().await?;
+    let script = redis::Script::new(FENCED_LOCK_SCRIPT);
+    script
+        .key(resource)
+        .key(counter)
+        .arg(val)
+        .arg(ttl)
+        .invoke_async(&mut con)
+        .await
+}
+
+// Raises the fencing counter to `token` unless it is higher already
+pub(crate) async fn raise_fencing_instance(
+    instance: Instance<'_>,
+    counter: &[u8],
+    token: u64,
+) -> RedisResult<()> {
+    let mut con = instance.connect().await?;
+    let script = redis::Script::new(RAISE_FENCING_SCRIPT);
+    let _: i32 = script
+        .key(counter)
+        .arg(token)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(())
+}
+
+// Returns `Ok(false)` if the resource is held or awaited by a writer
+pub(crate) async fn read_lock_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    readers: &[u8],
+    val: &[u8],
+    ttl: usize,
+) -> RedisResult<bool> {
+    let mut con = instance.connect().await?;
+    let members = readers_instance(&mut con, readers).await?;
+    let script = redis::Script::new(READ_LOCK_SCRIPT);
+    let mut invocation = script.key(resource);
+    invocation
+        .key(readers)
+        .key(reader_key(readers, val))
+        .key(writer_key(resource))
+        .arg(val)
+        .arg(ttl);
+    pass_readers(&mut invocation, readers, &members);
+    let result: i32 = invocation.invoke_async(&mut con).await?;
+
+    Ok(result == 1)
+}
+
+// Returns `Ok(false)` if the resource is held by a writer or readers
+pub(crate) async fn write_lock_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    readers: &[u8],
+    val: &[u8],
+    ttl: usize,
+) -> RedisResult<bool> {
+    let mut con = instance.connect().await?;
+    let members = readers_instance(&mut con, readers).await?;
+    let script = redis::Script::new(WRITE_LOCK_SCRIPT);
+    let mut invocation = script.key(resource);
+    invocation.key(readers).arg(val).arg(ttl);
+    pass_readers(&mut invocation, readers, &members);
+    let result: i32 = invocation.invoke_async(&mut con).await?;
+
+    Ok(result == 1)
+}
+
+// Returns `Ok(false)` if the key does not hold our value anymore
+pub(crate) async fn extend_lock_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    val: &[u8],
+    ttl: usize,
+) -> RedisResult<bool> {
+    if instance.settings.is_scriptless() {
+        let mut con = instance.connect_dedicated().await?;
+        return extend_watched(&mut con, resource, val, ttl).await;
+    }
+    let mut con = instance.connect().await?;
+    let script = redis::Script::new(EXTEND_SCRIPT);
+    let result: i32 = script
+        .key(resource)
+        .arg(val)
+        .arg(ttl)
+        .invoke_async(&mut con)
+        .await?;
+
+    Ok(result == 1)
+}
+
+// Returns `Ok(None)` if the key exists without an expiry
+pub(crate) async fn pttl_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+) -> RedisResult<Option<Duration>> {
+    let mut con = instance.connect().await?;
+    let pttl: i64 = redis::cmd("PTTL")
+        .arg(resource)
+        .query_async(&mut con)
+        .await?;
+    Ok(match pttl {
+        -2 => Some(Duration::ZERO),
+        -1 => None,
+        ms => Some(Duration::from_millis(ms as u64)),
+    })
+}
+
+pub(crate) async fn verify_instance(instance: Instance<'_>, resource: &[u8], val: &[u8]) -> bool {
+    holds_instance(instance, resource, val)
+        .await
+        .unwrap_or(false)
+}
+
+// The id of the instance's current run, which changes when it restarts
+pub(crate) async fn run_id_instance(instance: Instance<'_>) -> RedisResult<String> {
+    let mut con = instance.connect().await?;
+    let info: String = redis::cmd("INFO")
+        .arg("server")
+        .query_async(&mut con)
+        .await?;
+    info.lines()
+        .find_map(|line| line.strip_prefix("run_id:"))
+        .map(|run_id| run_id.trim().to_string())
+        .ok_or_else(|| RedisError::from((ErrorKind::TypeError, "INFO reported no run_id")))
+}
+
+// Returns `Ok(false)` if the key does not hold our value
+pub(crate) async fn holds_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    val: &[u8],
+) -> RedisResult<bool> {
+    let mut con = instance.connect().await?;
+    let stored: Option<Vec<u8>> = redis::cmd("GET")
+        .arg(resource)
+        .query_async(&mut con)
+        .await?;
+    Ok(stored.is_some_and(|stored| owns(&stored, val)))
+}
+
+// Returns `None` if the instance could not be reached
+pub(crate) async fn unlock_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    val: &[u8],
+) -> Option<UnlockOutcome> {
+    let result = if instance.settings.is_scriptless() {
+        let mut con = instance.connect_dedicated().await.ok()?;
+        unlock_watched(&mut con, resource, val).await
+    } else {
+        let mut con = instance.connect().await.ok()?;
+        let script = redis::Script::new(UNLOCK_SCRIPT);
+        script.key(resource).arg(val).invoke_async(&mut con).await
+    };
+    match result.ok()? {
+        1 => Some(UnlockOutcome::Released),
+        -1 => Some(UnlockOutcome::NotOwned),
+        _ => Some(UnlockOutcome::AlreadyReleased),
+    }
+}
+
+// Like `unlock_instance` for the key of a read lock, see `read_lock_instance`
+pub(crate) async fn read_unlock_instance(
+    instance: Instance<'_>,
+    reader: &[u8],
+    readers: &[u8],
+    val: &[u8],
+) -> Option<UnlockOutcome> {
+    let mut con = instance.connect().await.ok()?;
+    let script = redis::Script::new(READ_UNLOCK_SCRIPT);
+    let mut invocation = script.key(reader);
+    invocation.key(readers).arg(val);
+    let result: i32 = invocation.invoke_async(&mut con).await.ok()?;
+    match result {
+        1 => Some(UnlockOutcome::Released),
+        -1 => Some(UnlockOutcome::NotOwned),
+        _ => Some(UnlockOutcome::AlreadyReleased),
+    }
+}
+
+// Returns the number of released locks
+pub(crate) async fn unlock_many_instance(instance: Instance<'_>, locks: &[Lock<'_>]) -> usize {
+    if instance.settings.is_scriptless() {
+        let Ok(mut con) = instance.connect_dedicated().await else {
+            return 0;
+        };
+        let mut released = 0;
+        for lock in locks {
+            if let Ok(1) = unlock_watched(&mut con, &lock.resource, &lock.val).await {
+                released += 1;
+            }
+        }
+        return released;
+    }
+    let mut con = match instance.connect().await {
+        Err(_) => return 0,
+        Ok(val) => val,
+    };
+    let script = redis::Script::new(UNLOCK_MANY_SCRIPT);
+    let mut released = 0;
+    for batch in locks.chunks(UNLOCK_MANY_BATCH_SIZE) {
+        let mut invocation = script.prepare_invoke();
+        for lock in batch {
+            invocation.key(&lock.resource).arg(&lock.val);
+        }
+        let result: RedisResult<usize> = invocation.invoke_async(&mut con).await;
+        released += result.unwrap_or(0);
+    }
+    released
+}
+
+// Returns for every lock, given as resource, value and TTL, whether it was extended
+pub(crate) async fn extend_many_instance(
+    instance: Instance<'_>,
+    locks: &[(&[u8], &[u8], usize)],
+) -> RedisResult<Vec<bool>> {
+    if instance.settings.is_scriptless() {
+        let mut con = instance.connect_dedicated().await?;
+        let mut extended = Vec::with_capacity(locks.len());
+        for (resource, val, ttl) in locks {
+            extended.push(extend_watched(&mut con, resource, val, *ttl).await?);
+        }
+        return Ok(extended);
+    }
+    let mut con = instance.connect().await?;
+    let script = redis::Script::new(EXTEND_MANY_SCRIPT);
+    let mut extended = Vec::with_capacity(locks.len());
+    for batch in locks.chunks(EXTEND_MANY_BATCH_SIZE) {
+        let mut invocation = script.prepare_invoke();
+        for (resource, val, ttl) in batch {
+            invocation.key(*resource).arg(*val).arg(*ttl);
+        }
+        let results: Vec<i32> = invocation.invoke_async(&mut con).await?;
+        extended.extend(results.into_iter().map(|result| result == 1));
+    }
+    Ok(extended)
+}
+
+// The unlock script as a transaction: `GET` under `WATCH`, then `DEL` in `MULTI`/`EXEC`,
+// which Redis aborts if the key changed in between, e.g. because it expired and was
+// locked again. Then the key is checked again. Returns what the script returns.
+async fn unlock_watched(con: &mut Connection<'_>, resource: &[u8], val: &[u8]) -> RedisResult<i32> {
+    for _ in 0..WATCH_ATTEMPTS {
+        match watch_owned(con, resource, val).await? {
+            Some(true) => (),
+            Some(false) => return Ok(-1),
+            None => return Ok(0),
+        }
+        let deleted: Option<(i32,)> = redis::pipe()
+            .atomic()
+            .del(resource)
+            .query_async(con)
+            .await?;
+        if let Some((deleted,)) = deleted {
+            return Ok(deleted);
+        }
+    }
+    Err(watch_conflict())
+}
+
+// The extend script as a transaction, see `unlock_watched`
+async fn extend_watched(
+    con: &mut Connection<'_>,
+    resource: &[u8],
+    val: &[u8],
+    ttl: usize,
+) -> RedisResult<bool> {
+    for _ in 0..WATCH_ATTEMPTS {
+        if watch_owned(con, resource, val).await? != Some(true) {
+            return Ok(false);
+        }
+        let set: Option<(Value,)> = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(resource)
+            .arg(val)
+            .arg("PX")
+            .arg(ttl)
+            .query_async(con)
+            .await?;
+        if let Some((set,)) = set {
+            return Ok(set == Okay);
+        }
+    }
+    Err(watch_conflict())
+}
+
+// Watches the key and tells whether it holds our value, `None` if it doesn't exist.
+// Unwatches it again unless it holds our value.
+async fn watch_owned(
+    con: &mut Connection<'_>,
+    resource: &[u8],
+    val: &[u8],
+) -> RedisResult<Option<bool>> {
+    redis::cmd("WATCH")
+        .arg(resource)
+        .query_async::<_, ()>(con)
+        .await?;
+    let stored: Option<Vec<u8>> = redis::cmd("GET").arg(resource).query_async(con).await?;
+    let owned = stored.map(|stored| owns(&stored, val));
+    if owned != Some(true) {
+        redis::cmd("UNWATCH").query_async::<_, ()>(con).await?;
+    }
+    Ok(owned)
+}
+
+fn watch_conflict() -> RedisError {
+    RedisError::from((
+        ErrorKind::TryAgain,
+        "Lock key kept changing during the transaction",
+    ))
+}
+
+pub(crate) async fn tombstone_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+    val: &[u8],
+    cooldown: usize,
+) -> Option<UnlockOutcome> {
+    let mut con = instance.connect().await.ok()?;
+    let script = redis::Script::new(TOMBSTONE_SCRIPT);
+    let result: i32 = script
+        .key(resource)
+        .arg(val)
+        .arg(TOMBSTONE_VALUE)
+        .arg(cooldown)
+        .invoke_async(&mut con)
+        .await
+        .ok()?;
+    match result {
+        1 => Some(UnlockOutcome::Released),
+        -1 => Some(UnlockOutcome::NotOwned),
+        _ => Some(UnlockOutcome::AlreadyReleased),
+    }
+}
+
+// Returns `None` if the instance does not hold the lock
+pub(crate) async fn locked_incr_instance(
+    instance: Instance<'_>,
+    lock: &Lock<'_>,
+    key: &[u8],
+    delta: i64,
+) -> RedisResult<Option<i64>> {
+    let mut con = instance.connect().await?;
+    redis::Script::new(LOCKED_INCR_SCRIPT)
+        .key(&lock.resource)
+        .key(key)
+        .arg(&lock.val)
+        .arg(delta)
+        .invoke_async(&mut con)
+        .await
+}
+
+// Returns `false` if the instance does not hold the lock
+// Sets `key` to expire after `ttl` milliseconds if given
+pub(crate) async fn locked_set_instance(
+    instance: Instance<'_>,
+    lock: &Lock<'_>,
+    key: &[u8],
+    value: &[u8],
+    ttl: Option<usize>,
+) -> RedisResult<bool> {
+    let mut con = instance.connect().await?;
+    let script = redis::Script::new(LOCKED_SET_SCRIPT);
+    let mut invocation = script.key(&lock.resource);
+    invocation.key(key).arg(&lock.val).arg(value);
+    if let Some(ttl) = ttl {
+        invocation.arg(ttl);
+    }
+    invocation.invoke_async(&mut con).await
+}
+
+pub(crate) async fn get_instance(
+    instance: Instance<'_>,
+    key: &[u8],
+) -> RedisResult<Option<Vec<u8>>> {
+    let mut con = instance.connect().await?;
+    redis::cmd("GET").arg(key).query_async(&mut con).await
+}
+
+// Returns `None` if the instance does not hold the lock, `Some(None)` if `key` does not exist
+pub(crate) async fn locked_get_instance(
+    instance: Instance<'_>,
+    lock: &Lock<'_>,
+    key: &[u8],
+) -> RedisResult<Option<Option<Vec<u8>>>> {
+    let mut con = instance.connect().await?;
+    let result: Value = redis::Script::new(LOCKED_GET_SCRIPT)
+        .key(&lock.resource)
+        .key(key)
+        .arg(&lock.val)
+        .invoke_async(&mut con)
+        .await?;
+    match result {
+        Value::Bulk(values) if values.len() == 2 => Ok(Some(redis::from_redis_value(&values[1])?)),
+        _ => Ok(None),
+    }
+}
+
+// Returns the value held by the instance and its remaining TTL, `None` if there is no expiry
+pub(crate) async fn holder_instance(
+    instance: Instance<'_>,
+    resource: &[u8],
+) -> RedisResult<Option<(Vec<u8>, Option<Duration>)>> {
+    let mut con = instance.connect().await?;
+    let (val, pttl): (Option<Vec<u8>>, i64) = redis::pipe()
+        .cmd("GET")
+        .arg(resource)
+        .cmd("PTTL")
+        .arg(resource)
+        .query_async(&mut con)
+        .await?;
+    Ok(val.map(|val| (val, u64::try_from(pttl).ok().map(Duration::from_millis))))
+}
+
+// Returns the string keys starting with `prefix`, with their values and remaining TTLs,
+// `None` if there is no expiry
+pub(crate) async fn scan_instance(
+    instance: Instance<'_>,
+    prefix: &[u8],
+) -> RedisResult<Vec<(Vec<u8>, Vec<u8>, Option<Duration>)>> {
+    let mut pattern: Vec<u8> = Vec::with_capacity(prefix.len() + 1);
+    for b in prefix {
+        if matches!(b, b'*' | b'?' | b'[' | b']' | b'\\') {
+            pattern.push(b'\\');
+        }
+        pattern.push(*b);
+    }
+    pattern.push(b'*');
+
+    let mut con = instance.connect().await?;
+    let mut cursor = 0u64;
+    let mut found = Vec::new();
+    loop {
+        let (next, keys): (u64, Vec<Vec<u8>>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(&pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .arg("TYPE")
+            .arg("string")
+            .query_async(&mut con)
+            .await?;
+        if !keys.is_empty() {
+            let mut pipe = redis::pipe();
+            for key in &keys {
+                pipe.cmd("GET").arg(key).cmd("PTTL").arg(key);
+            }
+            let states: Vec<Value> = pipe.query_async(&mut con).await?;
+            for (key, state) in keys.into_iter().zip(states.chunks(2)) {
+                let val: Option<Vec<u8>> = redis::from_redis_value(&state[0])?;
+                let pttl: i64 = redis::from_redis_value(&state[1])?;
+                // Keys deleted since the scan have no value
+                if let Some(val) = val {
+                    let pttl = u64::try_from(pttl).ok().map(Duration::from_millis);
+                    found.push((key, val, pttl));
+                }
+            }
+        }
+        if next == 0 {
+            return Ok(found);
+        }
+        cursor = next;
+    }
+}
+
+// Returns `Ok(false)` if the key does not exist or already expires
+pub(crate) async fn expire_persistent_instance(
+    instance: Instance<'_>,
+    key: &[u8],
+    ttl: usize,
+) -> RedisResult<bool> {
+    let mut con = instance.connect().await?;
+    let result: i32 = redis::Script::new(EXPIRE_PERSISTENT_SCRIPT)
+        .key(key)
+        .arg(ttl)
+        .invoke_async(&mut con)
+        .await?;
+    Ok(result == 1)
+}
@@ -0,0 +1,375 @@
+//! Test vectors for the lock scripts and the lock value format.
+//!
+//! Implementations in other languages that share instances with this crate must agree with it
+//! on which values own a lock, see `core::owns`. `SCRIPT_VECTORS` describe how the unlock and
+//! extend scripts treat a stored value, and `VALUE_VECTORS` how lock values decode. With the
+//! `serde` feature enabled they implement `Serialize`, so they can be exported as JSON and
+//! replayed by the other implementation's test suite. `run_vectors` replays them against
+//! a Redis instance with this crate's scripts.
+//!
+//! Only available with the `test-support` feature.
+
+use redis::AsyncCommands;
+
+use crate::core::{self, LockValue};
+use crate::testing::{fail, CheckFailure};
+use crate::transport::{EXTEND_SCRIPT, UNLOCK_SCRIPT};
+
+/// A lock script exercised by a `ScriptVector`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ScriptOperation {
+    /// Called with `KEYS[1]` the resource and `ARGV[1]` the lock value. Replies `1` if the key
+    /// was owned and deleted, `-1` if it is held with another value and `0` if it is absent.
+    Unlock,
+    /// Called with `KEYS[1]` the resource, `ARGV[1]` the lock value and `ARGV[2]` the TTL in
+    /// milliseconds. Replies `1` if the key was owned and set to the value with the TTL,
+    /// `0` otherwise.
+    Extend,
+}
+
+impl ScriptOperation {
+    /// The Lua source of the script, as sent to Redis.
+    pub fn script(&self) -> &'static str {
+        match self {
+            ScriptOperation::Unlock => UNLOCK_SCRIPT,
+            ScriptOperation::Extend => EXTEND_SCRIPT,
+        }
+    }
+}
+
+/// A call of a lock script and its expected effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScriptVector {
+    pub name: &'static str,
+    pub operation: ScriptOperation,
+    /// The value stored under the resource before the call, `None` if it is absent.
+    pub stored: Option<&'static [u8]>,
+    /// The lock value passed as `ARGV[1]`.
+    pub value: &'static [u8],
+    /// The TTL passed to `Extend` as `ARGV[2]`, in milliseconds.
+    pub ttl_ms: Option<u64>,
+    /// The script's reply.
+    pub reply: i64,
+    /// The value stored under the resource after the call, `None` if it is absent.
+    pub stored_after: Option<&'static [u8]>,
+}
+
+/// A lock value and how it decodes, see `core::decode_value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ValueVector {
+    pub name: &'static str,
+    pub value: &'static [u8],
+    /// The token identifying the lock, `None` if the value must be rejected.
+    pub token: Option<&'static [u8]>,
+    /// The metadata of a versioned value, `None` for plain and rejected values.
+    /// Encoding the token with the metadata gives back the value.
+    pub metadata: Option<&'static [u8]>,
+}
+
+const TTL_MS: Option<u64> = Some(10_000);
+
+pub const SCRIPT_VECTORS: &[ScriptVector] = &[
+    ScriptVector {
+        name: "unlock owned plain value",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"tok"),
+        value: b"tok",
+        ttl_ms: None,
+        reply: 1,
+        stored_after: None,
+    },
+    ScriptVector {
+        name: "unlock absent key",
+        operation: ScriptOperation::Unlock,
+        stored: None,
+        value: b"tok",
+        ttl_ms: None,
+        reply: 0,
+        stored_after: None,
+    },
+    ScriptVector {
+        name: "unlock key held with another value",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"other"),
+        value: b"tok",
+        ttl_ms: None,
+        reply: -1,
+        stored_after: Some(b"other"),
+    },
+    ScriptVector {
+        name: "unlock versioned value with its plain token",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"\x01\x03tokmeta"),
+        value: b"tok",
+        ttl_ms: None,
+        reply: 1,
+        stored_after: None,
+    },
+    ScriptVector {
+        name: "unlock plain value with a versioned value of the same token",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"tok"),
+        value: b"\x01\x03tokmeta",
+        ttl_ms: None,
+        reply: 1,
+        stored_after: None,
+    },
+    ScriptVector {
+        name: "unlock versioned value with other metadata",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"\x01\x03tokold"),
+        value: b"\x01\x03toknew",
+        ttl_ms: None,
+        reply: 1,
+        stored_after: None,
+    },
+    ScriptVector {
+        name: "unlock versioned value with another token",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"\x01\x03othmeta"),
+        value: b"\x01\x03tokmeta",
+        ttl_ms: None,
+        reply: -1,
+        stored_after: Some(b"\x01\x03othmeta"),
+    },
+    ScriptVector {
+        name: "unlock versioned value whose token is a prefix",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"\x01\x02tokmeta"),
+        value: b"\x01\x03tokmeta",
+        ttl_ms: None,
+        reply: -1,
+        stored_after: Some(b"\x01\x02tokmeta"),
+    },
+    ScriptVector {
+        name: "unlock truncated versioned value as plain",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"\x01\x09tok"),
+        value: b"tok",
+        ttl_ms: None,
+        reply: -1,
+        stored_after: Some(b"\x01\x09tok"),
+    },
+    ScriptVector {
+        name: "unlock value with an empty versioned token as plain",
+        operation: ScriptOperation::Unlock,
+        stored: Some(b"\x01\x00tok"),
+        value: b"\x01\x00oth",
+        ttl_ms: None,
+        reply: -1,
+        stored_after: Some(b"\x01\x00tok"),
+    },
+    ScriptVector {
+        name: "extend owned plain value",
+        operation: ScriptOperation::Extend,
+        stored: Some(b"tok"),
+        value: b"tok",
+        ttl_ms: TTL_MS,
+        reply: 1,
+        stored_after: Some(b"tok"),
+    },
+    ScriptVector {
+        name: "extend absent key",
+        operation: ScriptOperation::Extend,
+        stored: None,
+        value: b"tok",
+        ttl_ms: TTL_MS,
+        reply: 0,
+        stored_after: None,
+    },
+    ScriptVector {
+        name: "extend key held with another value",
+        operation: ScriptOperation::Extend,
+        stored: Some(b"other"),
+        value: b"tok",
+        ttl_ms: TTL_MS,
+        reply: 0,
+        stored_after: Some(b"other"),
+    },
+    ScriptVector {
+        name: "extend replaces the metadata of a versioned value",
+        operation: ScriptOperation::Extend,
+        stored: Some(b"\x01\x03tokold"),
+        value: b"\x01\x03toknew",
+        ttl_ms: TTL_MS,
+        reply: 1,
+        stored_after: Some(b"\x01\x03toknew"),
+    },
+];
+
+pub const VALUE_VECTORS: &[ValueVector] = &[
+    ValueVector {
+        name: "plain value",
+        value: b"tok",
+        token: Some(b"tok"),
+        metadata: None,
+    },
+    ValueVector {
+        name: "versioned value",
+        value: b"\x01\x03tokmeta",
+        token: Some(b"tok"),
+        metadata: Some(b"meta"),
+    },
+    ValueVector {
+        name: "versioned value without metadata",
+        value: b"\x01\x03tok",
+        token: Some(b"tok"),
+        metadata: Some(b""),
+    },
+    ValueVector {
+        name: "truncated versioned value is plain",
+        value: b"\x01\x09tok",
+        token: Some(b"\x01\x09tok"),
+        metadata: None,
+    },
+    ValueVector {
+        name: "unknown version is plain",
+        value: b"\x02\x03tokmeta",
+        token: Some(b"\x02\x03tokmeta"),
+        metadata: None,
+    },
+    ValueVector {
+        name: "empty value",
+        value: b"",
+        token: None,
+        metadata: None,
+    },
+    ValueVector {
+        name: "versioned value with an empty token",
+        value: b"\x01\x00meta",
+        token: None,
+        metadata: None,
+    },
+];
+
+/// Check `VALUE_VECTORS` against `core::decode_value` and `core::encode_value`.
+pub fn check_value_vectors() -> Result<(), CheckFailure> {
+    for vector in VALUE_VECTORS {
+        let decoded = core::decode_value(vector.value).ok();
+        let expected = match (vector.token, vector.metadata) {
+            (Some(token), Some(metadata)) => Some(LockValue::V1 { token, metadata }),
+            (Some(token), None) => Some(LockValue::Plain(token)),
+            _ => None,
+        };
+        if decoded != expected {
+            return Err(fail(
+                vector.name,
+                format!("decoded as {:?}, expected {:?}", decoded, expected),
+            ));
+        }
+        if let Some(LockValue::V1 { token, metadata }) = expected {
+            if core::encode_value(token, metadata).as_deref() != Ok(vector.value) {
+                return Err(fail(vector.name, "encoding does not give back the value"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check `VALUE_VECTORS` and replay `SCRIPT_VECTORS` against the Redis instance at `address`,
+/// failing with the first vector that does not hold.
+///
+/// Uses fresh random keys and deletes them afterwards, so it can run on shared instances.
+pub async fn run_vectors(address: &str) -> Result<(), CheckFailure> {
+    check_value_vectors()?;
+
+    let client = redis::Client::open(address).map_err(|e| fail("connect", e))?;
+    let mut con = client
+        .get_multiplexed_async_connection()
+        .await
+        .map_err(|e| fail("connect", e))?;
+    let prefix = format!("rslock:vectors:{:016x}", rand::random::<u64>());
+
+    for (i, vector) in SCRIPT_VECTORS.iter().enumerate() {
+        let key = format!("{}:{}", prefix, i);
+        let result = run_script_vector(&mut con, &key, vector).await;
+        let _: redis::RedisResult<()> = con.del(&key).await;
+        result?;
+    }
+    Ok(())
+}
+
+async fn run_script_vector(
+    con: &mut redis::aio::MultiplexedConnection,
+    key: &str,
+    vector: &ScriptVector,
+) -> Result<(), CheckFailure> {
+    let step = vector.name;
+    if let Some(stored) = vector.stored {
+        con.pset_ex::<_, _, ()>(key, stored, 60_000)
+            .await
+            .map_err(|e| fail(step, e))?;
+    }
+
+    let script = redis::Script::new(vector.operation.script());
+    let mut invocation = script.key(key);
+    invocation.arg(vector.value);
+    if let Some(ttl_ms) = vector.ttl_ms {
+        invocation.arg(ttl_ms);
+    }
+    let reply: i64 = invocation
+        .invoke_async(con)
+        .await
+        .map_err(|e| fail(step, e))?;
+    if reply != vector.reply {
+        return Err(fail(
+            step,
+            format!("replied {}, expected {}", reply, vector.reply),
+        ));
+    }
+
+    let stored_after: Option<Vec<u8>> = con.get(key).await.map_err(|e| fail(step, e))?;
+    if stored_after.as_deref() != vector.stored_after {
+        return Err(fail(
+            step,
+            format!(
+                "stored {:?} afterwards, expected {:?}",
+                stored_after, vector.stored_after
+            ),
+        ));
+    }
+    if let (Some(ttl_ms), 1) = (vector.ttl_ms, reply) {
+        let pttl: i64 = con.pttl(key).await.map_err(|e| fail(step, e))?;
+        if pttl <= 0 || pttl as u64 > ttl_ms {
+            return Err(fail(step, format!("TTL of {}ms afterwards", pttl)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[test]
+    fn test_value_vectors() {
+        check_value_vectors().unwrap();
+
+        for vector in VALUE_VECTORS {
+            if let Some(token) = vector.token {
+                assert_eq!(core::value_token(vector.value), token, "{}", vector.name);
+            }
+        }
+        for vector in SCRIPT_VECTORS {
+            if let Some(stored) = vector.stored {
+                let owned = vector.reply == 1;
+                assert_eq!(core::owns(stored, vector.value), owned, "{}", vector.name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_vectors() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        run_vectors(&addresses[0]).await?;
+
+        Ok(())
+    }
+}
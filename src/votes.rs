@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use redis::RedisResult;
+
+use crate::core::{Tally, UnlockOutcome};
+use crate::lock::{LockError, LockManager};
+use crate::{rt, transport};
+
+/// The answers of some instances to a single round of a low-level operation,
+/// see `LockManager::lock_on_instances`.
+#[derive(Debug)]
+pub struct Votes {
+    /// The lock value the round was made with.
+    pub val: Vec<u8>,
+    /// The instances asked and their answers: `Ok(true)` if the instance performed the
+    /// operation, `Ok(false)` if it refused it because the key is held with another value.
+    pub results: Vec<(usize, RedisResult<bool>)>,
+    /// Time the round took, to be passed to `core::validity`.
+    pub elapsed: Duration,
+}
+
+impl Votes {
+    fn new(
+        instances: &[usize],
+        val: Vec<u8>,
+        results: Vec<RedisResult<bool>>,
+        start: Instant,
+    ) -> Votes {
+        Votes {
+            val,
+            results: instances.iter().copied().zip(results).collect(),
+            elapsed: rt::elapsed(start),
+        }
+    }
+
+    /// The instances that performed the operation.
+    pub fn granted(&self) -> Vec<usize> {
+        self.results
+            .iter()
+            .filter(|(_, result)| matches!(result, Ok(true)))
+            .map(|(i, _)| *i)
+            .collect()
+    }
+
+    /// The answers counted like the manager counts them for its own rounds.
+    pub fn tally(&self) -> Tally {
+        Tally::count(
+            self.results
+                .iter()
+                .map(|(_, result)| result.as_ref().copied()),
+        )
+    }
+}
+
+impl LockManager {
+    /// Try to lock `resource` on the given instances only, in a single round.
+    ///
+    /// A building block for custom topologies, e.g. hierarchical quorums, where the caller
+    /// decides which votes are enough. No quorum is required, nothing is retried and nothing
+    /// is undone: instances that granted the lock hold it until it is released with
+    /// `unlock_on_instances` or expires. Combine `Votes::elapsed` with `LockManager::clock_drift`
+    /// and `core::validity` for the time the lock can be relied on.
+    ///
+    /// Fails with `LockError::NoSuchInstance` if an index is out of range,
+    /// and like `lock` if `ttl` is not accepted or the manager is in maintenance mode.
+    pub async fn lock_on_instances(
+        &self,
+        instances: &[usize],
+        resource: &[u8],
+        ttl: Duration,
+    ) -> Result<Votes, LockError> {
+        self.check_instances(instances)?;
+        self.check_maintenance_mode()?;
+        let ttl = self.ttl_millis(ttl)?;
+        let val = self.next_lock_id().map_err(LockError::Io)?;
+        let start = rt::now();
+        let results = self
+            .on_instances(instances, |instance| {
+                transport::lock_instance(instance, resource, val.clone(), ttl)
+            })
+            .await;
+        Ok(Votes::new(instances, val, results, start))
+    }
+
+    /// Extend a lock with the value `val` on the given instances only, in a single round.
+    ///
+    /// Instances refuse the extension if they don't hold `val` anymore.
+    /// See `lock_on_instances`.
+    pub async fn extend_on_instances(
+        &self,
+        instances: &[usize],
+        resource: &[u8],
+        val: &[u8],
+        ttl: Duration,
+    ) -> Result<Votes, LockError> {
+        self.check_instances(instances)?;
+        let ttl = self.ttl_millis(ttl)?;
+        let start = rt::now();
+        let results = self
+            .on_instances(instances, |instance| {
+                transport::extend_lock_instance(instance, resource, val, ttl)
+            })
+            .await;
+        Ok(Votes::new(instances, val.to_vec(), results, start))
+    }
+
+    /// Release a lock with the value `val` on the given instances only.
+    ///
+    /// Returns the outcome per instance, `None` for instances that could not be reached.
+    pub async fn unlock_on_instances(
+        &self,
+        instances: &[usize],
+        resource: &[u8],
+        val: &[u8],
+    ) -> Result<Vec<(usize, Option<UnlockOutcome>)>, LockError> {
+        self.check_instances(instances)?;
+        let results = self
+            .on_instances(instances, |instance| {
+                transport::unlock_instance(instance, resource, val)
+            })
+            .await;
+        Ok(instances.iter().copied().zip(results).collect())
+    }
+
+    fn check_instances(&self, instances: &[usize]) -> Result<(), LockError> {
+        match instances.iter().find(|i| **i >= self.servers.len()) {
+            Some(&index) => Err(LockError::NoSuchInstance { index }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::core;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_lock_on_instances() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+
+        let votes = rl.lock_on_instances(&[0, 2], &key, ttl).await?;
+        assert_eq!(votes.granted(), [0, 2]);
+        assert!(core::validity(ttl, votes.elapsed, rl.clock_drift(ttl)).is_some());
+
+        let other = rl.lock_on_instances(&[1, 2], &key, ttl).await?;
+        assert_eq!(other.granted(), [1]);
+        assert_eq!(other.tally().rejected, 1);
+
+        let extended = rl
+            .extend_on_instances(&[0, 1], &key, &votes.val, ttl)
+            .await?;
+        assert_eq!(extended.granted(), [0]);
+
+        let outcomes = rl.unlock_on_instances(&[0, 1, 2], &key, &votes.val).await?;
+        assert_eq!(
+            outcomes,
+            [
+                (0, Some(UnlockOutcome::Released)),
+                (1, Some(UnlockOutcome::NotOwned)),
+                (2, Some(UnlockOutcome::Released))
+            ]
+        );
+
+        assert!(matches!(
+            rl.lock_on_instances(&[3], &key, ttl).await,
+            Err(LockError::NoSuchInstance { index: 3 })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, Stream};
+
+use crate::core;
+use crate::lock::LockManager;
+use crate::resource::LockResource;
+use crate::rt;
+use crate::transport::{self, holder_instance};
+
+// Bounds of the polling interval of `wait_until_free`, which doubles while the resource is held
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change of a resource's lock state, observed by `LockManager::watch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockEvent {
+    /// The resource has been locked.
+    Locked,
+    /// The lock has been released before it expired.
+    Released,
+    /// The lock has expired.
+    Expired,
+}
+
+// The lock held on enough instances to be granted, as seen by the last poll
+struct Holder {
+    val: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+struct Watch<'a> {
+    lock_manager: &'a LockManager,
+    resource: Vec<u8>,
+    interval: Duration,
+    holder: Option<Holder>,
+    events: VecDeque<LockEvent>,
+    polled: bool,
+}
+
+impl Watch<'_> {
+    async fn poll(&mut self) {
+        let states = self
+            .lock_manager
+            .on_all(|instance| holder_instance(instance, &self.resource))
+            .await;
+        let now = rt::now();
+        let states: Vec<_> = states.into_iter().filter_map(Result::ok).collect();
+        let quorum = self.lock_manager.acquire_quorum() as usize;
+        // Without a quorum of answers the state is unknown, so keep the previous one
+        if states.len() < quorum {
+            return;
+        }
+
+        let holder = states.iter().flatten().find_map(|(val, _)| {
+            let mut pttls: Vec<_> = states
+                .iter()
+                .flatten()
+                .filter(|(v, _)| v == val)
+                .map(|(_, pttl)| *pttl)
+                .collect();
+            // The lock expires once fewer than a quorum of instances hold it
+            pttls.sort_by(|a, b| b.cmp(a));
+            pttls.get(quorum - 1).map(|pttl| Holder {
+                val: val.clone(),
+                expires_at: pttl.map(|pttl| now + pttl),
+            })
+        });
+
+        match (&self.holder, &holder) {
+            (Some(old), Some(new)) if old.val == new.val => (),
+            (old, new) => {
+                if let Some(old) = old {
+                    self.events.push_back(match old.expires_at {
+                        Some(expires_at) if expires_at <= now => LockEvent::Expired,
+                        _ => LockEvent::Released,
+                    });
+                }
+                if new.is_some() {
+                    self.events.push_back(LockEvent::Locked);
+                }
+            }
+        }
+        self.holder = holder;
+    }
+
+    async fn next(&mut self) -> LockEvent {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return event;
+            }
+            if self.polled {
+                rt::sleep(self.interval).await;
+            }
+            self.polled = true;
+            self.poll().await;
+        }
+    }
+}
+
+impl LockManager {
+    /// Observe the lock state of a resource without trying to acquire it.
+    ///
+    /// The instances are polled every `interval`, and a change of the lock held on a quorum,
+    /// including the margin set with `set_quorum_margin`, is reported as `LockEvent`s.
+    /// A lock held when watching starts is reported as `Locked`. A lock replaced between
+    /// two polls yields `Released` or `Expired` followed by `Locked`, changes that are undone
+    /// between two polls are missed. A lock released shortly before it would have expired
+    /// may be reported as `Expired`.
+    ///
+    /// Polls not answered by a quorum of instances are skipped. The stream never ends.
+    pub fn watch<R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+        interval: Duration,
+    ) -> impl Stream<Item = LockEvent> + '_ {
+        let watch = Watch {
+            lock_manager: self,
+            resource: resource.key().into_owned(),
+            interval,
+            holder: None,
+            events: VecDeque::new(),
+            polled: false,
+        };
+        stream::unfold(watch, |mut watch| async move {
+            let event = watch.next().await;
+            Some((event, watch))
+        })
+    }
+
+    /// Wait until the resource is free, without acquiring it, for workflows that only need
+    /// to run after another client's critical section.
+    ///
+    /// The resource is free once enough instances don't hold it for a lock to be acquired.
+    /// The instances are polled, first every few milliseconds and then less and less often
+    /// while the resource stays held, but never past the time the lock is due to expire.
+    /// Polls not answered by a quorum of instances don't count.
+    ///
+    /// Returns whether the resource became free within `timeout`. Another client may
+    /// acquire it right afterwards, so callers that need it free must `lock` it instead.
+    pub async fn wait_until_free<R: LockResource + ?Sized>(
+        &self,
+        resource: &R,
+        timeout: Duration,
+    ) -> bool {
+        let resource = resource.key();
+        // Timeouts too long to be represented never pass
+        let deadline = rt::now().checked_add(timeout);
+        let quorum = self.acquire_quorum();
+        let mut interval = MIN_POLL_INTERVAL;
+
+        loop {
+            // Keys without an expiry are held until someone releases them
+            let free_in: Vec<Duration> = self
+                .on_all(|instance| transport::pttl_instance(instance, &resource))
+                .await
+                .into_iter()
+                .filter_map(|pttl| Some(pttl.ok()?.unwrap_or(Duration::MAX)))
+                .collect();
+            let mut delay = interval;
+            if free_in.len() >= quorum as usize {
+                match core::retry_after(free_in, quorum) {
+                    None => return true,
+                    Some(expires_in) => delay = delay.min(expires_in),
+                }
+            }
+
+            let remaining = deadline.map_or(Duration::MAX, |deadline| {
+                deadline.saturating_duration_since(rt::now())
+            });
+            if remaining.is_zero() {
+                return false;
+            }
+            rt::sleep(delay.min(remaining)).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::lock::tests::create_clients;
+
+    #[tokio::test]
+    async fn test_watch_lock_release_expiry() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let events = rl.watch(&key, Duration::from_millis(20));
+        futures::pin_mut!(events);
+
+        let lock = rl.lock(&key, Duration::from_millis(10_000)).await?;
+        assert_eq!(events.next().await, Some(LockEvent::Locked));
+        rl.unlock(&lock).await;
+        assert_eq!(events.next().await, Some(LockEvent::Released));
+
+        rl.lock(&key, Duration::from_millis(100)).await?;
+        assert_eq!(events.next().await, Some(LockEvent::Locked));
+        assert_eq!(events.next().await, Some(LockEvent::Expired));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_free() -> Result<()> {
+        let (_containers, addresses) = create_clients();
+
+        let rl = LockManager::new(addresses.clone());
+        let key = rl.get_unique_lock_id()?;
+        let ttl = Duration::from_millis(10_000);
+        assert!(rl.wait_until_free(&key, Duration::ZERO).await);
+
+        let lock = rl.lock(&key, ttl).await?;
+        assert!(!rl.wait_until_free(&key, Duration::from_millis(100)).await);
+        let (free, _) = tokio::join!(rl.wait_until_free(&key, ttl), async {
+            rt::sleep(Duration::from_millis(100)).await;
+            rl.unlock(&lock).await
+        });
+        assert!(free);
+
+        // Expiring locks are waited for until they expire
+        rl.lock(&key, Duration::from_millis(200)).await?;
+        assert!(rl.wait_until_free(&key, ttl).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_free_unreachable() {
+        let rl = LockManager::new(vec!["redis://127.0.0.1:1/"]);
+
+        assert!(
+            !rl.wait_until_free(b"resource", Duration::from_millis(50))
+                .await
+        );
+    }
+}